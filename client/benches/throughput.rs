@@ -0,0 +1,127 @@
+//! Measures request throughput against a running server with 10, 100 and 1000
+//! tablets hammering it at once. Start the server first, then run
+//! `cargo bench -p client --bench throughput`.
+
+use std::sync::{Arc, Barrier};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use reqwest::StatusCode;
+use shared::{NewTable, OrderItemsRequest, SeatPartyRequest, Table, TableResponse, SERVICE_URL};
+
+const CLIENT_COUNTS: [usize; 3] = [10, 100, 1000];
+
+/// Each client alternates between ordering for its table and reading it back
+const REQUESTS_PER_CLIENT: usize = 20;
+
+fn main() {
+    let tables = register_tables(CLIENT_COUNTS.into_iter().max().unwrap());
+    seat_parties(&tables);
+
+    println!("clients | requests | failed | elapsed    | req/s");
+    for clients in CLIENT_COUNTS {
+        let (elapsed, failed) = run(&tables[..clients]);
+        let requests = clients * REQUESTS_PER_CLIENT;
+
+        println!(
+            "{clients:>7} | {requests:>8} | {failed:>6} | {:>8.2?} | {:>8.0}",
+            elapsed,
+            requests as f64 / elapsed.as_secs_f64()
+        );
+    }
+}
+
+/// Makes sure the restaurant has a "Bench n" table for each of `count`
+/// clients, registering any missing ones, and returns their ids in order
+fn register_tables(count: usize) -> Vec<i64> {
+    let http = reqwest::blocking::Client::new();
+    let url = format!("http://{SERVICE_URL}/restaurants/1/tables");
+    let existing: Vec<Table> = http.get(&url).send().unwrap().json().unwrap();
+
+    (0..count)
+        .map(|client| {
+            let label = format!("Bench {client}");
+            match existing.iter().find(|table| table.label == label) {
+                Some(table) => table.id,
+                None => {
+                    let table: Table = http
+                        .post(&url)
+                        .json(&NewTable {
+                            label,
+                            section: "Bench".to_string(),
+                            seats: 1,
+                        })
+                        .send()
+                        .and_then(|response| response.error_for_status())
+                        .unwrap()
+                        .json()
+                        .unwrap();
+                    table.id
+                }
+            }
+        })
+        .collect()
+}
+
+/// Seats a one guest party at each table so orders can be placed for it,
+/// keeping the party already seated there from an earlier run
+fn seat_parties(tables: &[i64]) {
+    let http = reqwest::blocking::Client::new();
+
+    for table_id in tables {
+        let response = http
+            .post(format!(
+                "http://{SERVICE_URL}/restaurants/1/tables/{table_id}/party"
+            ))
+            .json(&SeatPartyRequest { guests: 1 })
+            .send()
+            .unwrap();
+        if response.status() != StatusCode::CONFLICT {
+            response.error_for_status().unwrap();
+        }
+    }
+}
+
+/// Runs one concurrent tablet per table, each on their own table so reads for
+/// different tables can proceed in parallel. Returns the wall time taken once
+/// every client was ready, and the number of failed requests.
+fn run(tables: &[i64]) -> (Duration, usize) {
+    let start = Arc::new(Barrier::new(tables.len() + 1));
+
+    let threads: Vec<JoinHandle<usize>> = tables
+        .iter()
+        .map(|&table_id| {
+            let start = start.clone();
+            std::thread::spawn(move || {
+                let http = reqwest::blocking::Client::new();
+                let url = format!("http://{SERVICE_URL}/restaurants/1/tables/{table_id}");
+                start.wait();
+
+                (0..REQUESTS_PER_CLIENT)
+                    .filter(|i| {
+                        let request = if i % 2 == 0 {
+                            http.post(&url).json(&OrderItemsRequest {
+                                items: vec![fastrand::i64(1..=15)],
+                                lines: Vec::new(),
+                            })
+                        } else {
+                            http.get(&url)
+                        };
+
+                        request
+                            .send()
+                            .and_then(|response| response.error_for_status())
+                            .and_then(|response| response.json::<TableResponse>())
+                            .is_err()
+                    })
+                    .count()
+            })
+        })
+        .collect();
+
+    start.wait();
+    let timer = Instant::now();
+    let failed = threads.into_iter().map(|t| t.join().unwrap()).sum();
+
+    (timer.elapsed(), failed)
+}
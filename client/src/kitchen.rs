@@ -0,0 +1,282 @@
+use std::time::{Duration, Instant};
+
+use eframe::egui::{self, Color32, RichText};
+use shared::{ClaimLineRequest, OrderStatus, Station, StationLine};
+
+use crate::{line_details, restaurant_url};
+
+/// How often the kitchen display fetches the station queues again
+const REFRESH_EVERY: Duration = Duration::from_secs(5);
+/// Lines due within this many seconds are shown as running late
+const DUE_SOON_SECS: i64 = 120;
+
+/// How close a line is to its `ready_at`, least urgent first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Ageing {
+    OnTime,
+    DueSoon,
+    Overdue,
+}
+
+impl Ageing {
+    fn of(line: &StationLine) -> Self {
+        match (line.order.overdue, line.order.remaining_secs) {
+            (true, _) => Ageing::Overdue,
+            (false, secs) if secs <= DUE_SOON_SECS => Ageing::DueSoon,
+            _ => Ageing::OnTime,
+        }
+    }
+
+    /// Green while a line has time to spare, amber once it is nearly due and
+    /// red once it is late
+    fn colour(self) -> Color32 {
+        match self {
+            Ageing::OnTime => Color32::from_rgb(60, 160, 75),
+            Ageing::DueSoon => Color32::from_rgb(220, 160, 30),
+            Ageing::Overdue => Color32::from_rgb(210, 50, 45),
+        }
+    }
+}
+
+/// The kitchen's view of the restaurant: every line waiting at any station,
+/// grouped into the tickets they were ordered on
+pub struct KitchenDisplay {
+    tickets: Vec<Ticket>,
+    /// Who bumped lines are claimed by
+    cook_name: String,
+    last_refresh: Option<Instant>,
+}
+
+/// The lines a table ordered together, i.e. placed at the same instant
+struct Ticket {
+    table_id: i64,
+    table_label: String,
+    ordered_at: String,
+    lines: Vec<StationLine>,
+}
+
+impl Ticket {
+    /// How close the ticket's most urgent line is to being late
+    fn ageing(&self) -> Ageing {
+        self.lines
+            .iter()
+            .map(Ageing::of)
+            .max()
+            .unwrap_or(Ageing::OnTime)
+    }
+}
+
+impl KitchenDisplay {
+    pub fn new() -> Self {
+        Self {
+            tickets: Vec::new(),
+            cook_name: "Kitchen".to_string(),
+            last_refresh: None,
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        if !matches!(self.last_refresh, Some(at) if at.elapsed() < REFRESH_EVERY) {
+            self.refresh();
+        }
+        ctx.request_repaint_after(REFRESH_EVERY);
+
+        let mut bumped = Vec::new();
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.heading("Kitchen Display");
+                if ui.button("Refresh").clicked() {
+                    self.last_refresh = None;
+                }
+                ui.label("Cook:");
+                ui.text_edit_singleline(&mut self.cook_name);
+            });
+            ui.label(format!(
+                "{} lines waiting on {} tickets",
+                self.tickets
+                    .iter()
+                    .map(|ticket| ticket.lines.len())
+                    .sum::<usize>(),
+                self.tickets.len()
+            ));
+            ui.separator();
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    self.tickets.iter().for_each(|ticket| {
+                        show_ticket(ui, ticket, &mut bumped);
+                    });
+                });
+            });
+        });
+
+        if !bumped.is_empty() {
+            bumped
+                .iter()
+                .for_each(|line| bump_line(line, &self.cook_name));
+            self.invalidate();
+        }
+    }
+
+    /// Fetches the queues again the next time the display is shown
+    pub fn invalidate(&mut self) {
+        self.last_refresh = None;
+    }
+
+    /// Fetches every station's queue and sorts the lines into tickets,
+    /// oldest first
+    fn refresh(&mut self) {
+        let mut lines = Station::ALL
+            .into_iter()
+            .flat_map(fetch_station_queue)
+            .collect::<Vec<_>>();
+        lines.sort_by(|a, b| {
+            (ordered_at(a), a.table_id, a.order.order_id).cmp(&(
+                ordered_at(b),
+                b.table_id,
+                b.order.order_id,
+            ))
+        });
+
+        self.tickets.clear();
+        for line in lines {
+            match self.tickets.last_mut() {
+                Some(ticket)
+                    if ticket.table_id == line.table_id
+                        && ticket.ordered_at == ordered_at(&line) =>
+                {
+                    ticket.lines.push(line)
+                }
+                _ => self.tickets.push(Ticket {
+                    table_id: line.table_id,
+                    table_label: line.table_label.clone(),
+                    ordered_at: ordered_at(&line).to_string(),
+                    lines: vec![line],
+                }),
+            }
+        }
+        self.last_refresh = Some(Instant::now());
+    }
+}
+
+fn show_ticket<'a>(ui: &mut egui::Ui, ticket: &'a Ticket, bumped: &mut Vec<&'a StationLine>) {
+    egui::Frame::group(ui.style())
+        .stroke(egui::Stroke::new(3.0, ticket.ageing().colour()))
+        .show(ui, |ui| {
+            ui.set_width(320.0);
+            ui.vertical(|ui| {
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(&ticket.table_label).heading().strong());
+                    ui.label(format!("ordered {}", ticket.ordered_at));
+                });
+
+                ticket.lines.iter().for_each(|line| {
+                    ui.horizontal(|ui| {
+                        let bump = egui::Button::new(RichText::new("Bump").size(20.0))
+                            .min_size(egui::vec2(80.0, 40.0));
+                        if ui.add(bump).clicked() {
+                            bumped.push(line);
+                        }
+                        ui.vertical(|ui| {
+                            ui.label(
+                                RichText::new(format!(
+                                    "{}x {}",
+                                    line.order.quantity, line.item_name
+                                ))
+                                .size(18.0)
+                                .strong(),
+                            );
+                            let details =
+                                line_details(&line.order.modifiers, line.order.notes.as_deref());
+                            if !details.is_empty() {
+                                ui.label(RichText::new(details.trim()).size(16.0));
+                            }
+                            let claimed = match &line.order.claimed_by {
+                                Some(claimed_by) => format!(", {claimed_by}"),
+                                None => String::new(),
+                            };
+                            ui.label(format!(
+                                "{} · {}{claimed}",
+                                line.order.station, line.order.status
+                            ));
+                            ui.label(
+                                RichText::new(due_label(line))
+                                    .color(Ageing::of(line).colour())
+                                    .strong(),
+                            );
+                        });
+                    });
+                });
+
+                let bump_all = egui::Button::new(RichText::new("Bump ticket").size(20.0))
+                    .min_size(egui::vec2(ui.available_width(), 40.0));
+                if ui.add(bump_all).clicked() {
+                    bumped.extend(ticket.lines.iter());
+                }
+            });
+        });
+}
+
+/// When the order was placed, which is also when its ticket was
+fn ordered_at(line: &StationLine) -> &str {
+    line.order
+        .status_history
+        .first()
+        .map_or("", |change| change.at.as_str())
+}
+
+fn due_label(line: &StationLine) -> String {
+    let order = &line.order;
+    match order.overdue {
+        true => format!("OVERDUE, due @ {}", order.ready_at_local),
+        false => format!(
+            "{}m{:02}s left, due @ {}",
+            order.remaining_secs / 60,
+            order.remaining_secs % 60,
+            order.ready_at_local
+        ),
+    }
+}
+
+fn fetch_station_queue(station: Station) -> Vec<StationLine> {
+    match reqwest::blocking::get(format!("{}/stations/{station}/queue", restaurant_url()))
+        .unwrap()
+        .json()
+    {
+        Ok(queue) => queue,
+        Err(e) => {
+            println!("{e}");
+            Vec::new()
+        }
+    }
+}
+
+/// Marks a line done, claiming it for `cook_name` first if nobody has
+fn bump_line(line: &StationLine, cook_name: &str) {
+    let client = reqwest::blocking::Client::new();
+    let url = format!(
+        "{}/stations/{}/queue/{}",
+        restaurant_url(),
+        line.order.station,
+        line.order.order_id
+    );
+
+    if line.order.status == OrderStatus::Placed {
+        let claimed = client
+            .post(format!("{url}/claim"))
+            .json(&ClaimLineRequest {
+                claimed_by: cook_name.to_string(),
+            })
+            .send()
+            .unwrap();
+        if !claimed.status().is_success() {
+            println!("Line not claimed: {:?}", claimed.text());
+            return;
+        }
+    }
+
+    let completed = client.post(format!("{url}/complete")).send().unwrap();
+    if !completed.status().is_success() {
+        println!("Line not bumped: {:?}", completed.text());
+    }
+}
@@ -1,7 +1,23 @@
+mod kitchen;
+
+use std::io::{BufRead, BufReader};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::OnceLock;
 use std::thread::JoinHandle;
+use std::time::Duration;
+
+use eframe::egui::{self, RichText};
+use kitchen::KitchenDisplay;
+use reqwest::StatusCode;
+use shared::{
+    format_money, minor_unit_digits, AdjustmentKind, Bill, BillLine, CapturePaymentRequest,
+    LiveEvent, Menu, Modifier, NewPayment, OrderItemsRequest, OrderLine, OrderRejection, Party,
+    Payment, RemovalReason, RemoveOrderRequest, Restaurant, SeatPartyRequest, SplitEvenlyRequest,
+    Table, TableChecks, TablePayments, TableResponse, Tender, SERVICE_URL,
+};
 
-use eframe::egui;
-use shared::{Menu, OrderItemsRequest, TableResponse, SERVICE_URL};
+/// How long to wait before following the event stream again once it drops
+const RECONNECT_AFTER: Duration = Duration::from_secs(2);
 
 fn main() {
     let native_options = eframe::NativeOptions::default();
@@ -13,57 +29,191 @@ fn main() {
     .unwrap();
 }
 
+/// Base URL of the restaurant this client works for, chosen with
+/// `PAIDY_RESTAURANT_ID` (default 1)
+fn restaurant_url() -> &'static str {
+    static URL: OnceLock<String> = OnceLock::new();
+    URL.get_or_init(|| {
+        let restaurant_id = std::env::var("PAIDY_RESTAURANT_ID").unwrap_or_else(|_| "1".into());
+        format!("http://{SERVICE_URL}/restaurants/{restaurant_id}")
+    })
+}
+
+/// Which staff the client is laid out for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Taking orders and looking after tables
+    FrontOfHouse,
+    /// Every order waiting in the kitchen, bumped as it is made
+    Kitchen,
+}
+
+/// The mode the client starts in, chosen with `PAIDY_CLIENT_MODE`
+/// (`front` by default, or `kitchen`)
+fn startup_mode() -> Mode {
+    match std::env::var("PAIDY_CLIENT_MODE").as_deref() {
+        Ok("kitchen") => Mode::Kitchen,
+        Ok("front") | Err(_) => Mode::FrontOfHouse,
+        Ok(other) => {
+            println!("Unknown PAIDY_CLIENT_MODE '{other}', starting in front of house mode");
+            Mode::FrontOfHouse
+        }
+    }
+}
+
 struct App {
+    mode: Mode,
+    kitchen: KitchenDisplay,
+    /// Currency menu prices are in
+    currency: String,
     menu: Vec<MenuListItem>,
-    pending_order: Vec<i64>,
-    table_selector: String,
+    pending_order: Vec<OrderLine>,
+    /// Seat that lines added to the pending order are for
+    seat: Option<i64>,
+    /// Active tables from the restaurant's registry
+    tables: Vec<Table>,
+    selected_table: Option<i64>,
+    /// Party seated at the selected table, as of the last fetch
+    open_party: Option<Party>,
+    guests: String,
     table_response: TableResponse,
+    /// The selected table's bill, as of the last time it was asked for
+    bill: Option<Bill>,
+    /// How many checks "Split Evenly" shares the bill between
+    split_count: String,
+    /// The selected table's checks, as of the last time they were asked for
+    checks: Option<TableChecks>,
+    /// Tip added to the next payment
+    tip: String,
+    /// The selected table's payments, as of the last time they were asked for
+    payments: Option<TablePayments>,
     debug_order_id: String,
+    staff_name: String,
+    removal_reason: RemovalReason,
+    /// (table_id, order_id) of the last removed order, for undo
+    last_removed: Option<(i64, i64)>,
+    /// Changes pushed by the server, which keep `table_response` and the
+    /// kitchen display up to date
+    live_events: Receiver<LiveEvent>,
 }
 
 struct MenuListItem {
     id: i64,
     name: String,
     prep_time: String,
+    modifiers: Vec<Modifier>,
+    price: i64,
 }
 
 impl App {
-    fn new(_: &eframe::CreationContext<'_>) -> Self {
-        let menu: Menu = reqwest::blocking::get(format!("http://{SERVICE_URL}/menu"))
-            .unwrap()
-            .json()
-            .unwrap();
-
-        let menu = menu
-            .items
-            .iter()
-            .map(|item| {
-                let prep_time = format!("~{}mins", (item.prep_min_m + item.prep_max_m) / 2.0);
-
-                MenuListItem {
-                    id: item.id,
-                    name: item.name.clone(),
-                    prep_time,
-                }
-            })
-            .collect();
-
+    fn new(cc: &eframe::CreationContext<'_>) -> Self {
         Self {
-            menu,
+            mode: startup_mode(),
+            kitchen: KitchenDisplay::new(),
+            currency: fetch_restaurant().currency,
+            menu: fetch_menu(),
             pending_order: Vec::new(),
-            table_selector: String::default(),
+            seat: None,
+            tables: fetch_tables(),
+            selected_table: None,
+            open_party: None,
+            guests: "2".to_string(),
             table_response: TableResponse {
                 table_id: 0,
                 ordered_items: Vec::new(),
             },
+            bill: None,
+            split_count: "2".to_string(),
+            checks: None,
+            tip: "0".to_string(),
+            payments: None,
             debug_order_id: String::default(),
+            staff_name: String::default(),
+            removal_reason: RemovalReason::CustomerChangedMind,
+            last_removed: None,
+            live_events: follow_events(cc.egui_ctx.clone()),
         }
     }
+
+    /// Brings what the client shows up to date with a change from the server
+    fn apply_live_event(&mut self, event: LiveEvent) {
+        let shown_table = self.table_response.table_id;
+
+        match event {
+            LiveEvent::OrderCreated { table_id, order }
+            | LiveEvent::OrderStatusChanged { table_id, order }
+            | LiveEvent::OrderUpdated { table_id, order }
+                if table_id == shown_table =>
+            {
+                let items = &mut self.table_response.ordered_items;
+                match items
+                    .iter_mut()
+                    .find(|item| item.order_id == order.order_id)
+                {
+                    Some(item) => *item = order,
+                    None => {
+                        items.push(order);
+                        items.sort_by_key(|item| item.order_id);
+                    }
+                }
+            }
+            LiveEvent::OrderRemoved {
+                table_id, order_id, ..
+            } if table_id == shown_table => {
+                self.table_response
+                    .ordered_items
+                    .retain(|item| item.order_id != order_id);
+            }
+            LiveEvent::MenuChanged { .. } => {
+                self.menu = fetch_menu();
+                return;
+            }
+            LiveEvent::Lagged { .. } if shown_table != 0 => {
+                if let Some(response) = fetch_table_items(shown_table) {
+                    self.table_response = response;
+                }
+            }
+            _ => {}
+        }
+
+        // Every other change is to an order, which the kitchen may be showing
+        self.kitchen.invalidate();
+    }
 }
 
 impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, _: &mut eframe::Frame) {
+        while let Ok(event) = self.live_events.try_recv() {
+            self.apply_live_event(event);
+        }
+
+        egui::TopBottomPanel::top("mode").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                ui.menu_button("View", |ui| {
+                    if ui
+                        .radio_value(&mut self.mode, Mode::FrontOfHouse, "Front of house")
+                        .clicked()
+                        || ui
+                            .radio_value(&mut self.mode, Mode::Kitchen, "Kitchen display")
+                            .clicked()
+                    {
+                        ui.close_menu();
+                    }
+                });
+            });
+        });
+
+        if self.mode == Mode::Kitchen {
+            self.kitchen.show(ctx);
+            return;
+        }
+
         let mut new_response = None;
+        let seats = self
+            .tables
+            .iter()
+            .find(|table| Some(table.id) == self.selected_table)
+            .map_or(0, |table| table.seats);
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.horizontal(|ui| {
@@ -71,13 +221,18 @@ impl eframe::App for App {
                 ui.group(|ui| {
                     ui.vertical(|ui| {
                         ui.heading("Menu");
+                        seat_picker(ui, "new_line_seat", &mut self.seat, seats);
                         self.menu.iter().for_each(|item| {
                             ui.horizontal(|ui| {
                                 if ui.button("+").clicked() {
-                                    self.pending_order.push(item.id);
+                                    self.pending_order.push(OrderLine {
+                                        seat: self.seat,
+                                        ..OrderLine::plain(item.id)
+                                    });
                                 }
                                 ui.label(format!("id: {}:", item.id));
                                 ui.label(&item.name);
+                                ui.label(format_money(item.price, &self.currency));
                                 ui.label(&item.prep_time);
                             });
                         });
@@ -95,22 +250,36 @@ impl eframe::App for App {
                             };
 
                             if ui.button("Submit Order").clicked() {
-                                if let Ok(table_id) = self.table_selector.parse() {
-                                    new_response = order_items(table_id, &self.pending_order);
-                                    self.pending_order.clear();
+                                if let Some(table_id) = self.selected_table {
+                                    let request = OrderItemsRequest {
+                                        items: Vec::new(),
+                                        lines: std::mem::take(&mut self.pending_order),
+                                    };
+                                    new_response = order_items(table_id, &request);
+                                    // A rejected order is kept, so it can be fixed up
+                                    if new_response.is_none() {
+                                        self.pending_order = request.lines;
+                                    }
                                 } else {
-                                    println!("Failed to parse i64 from table_selector");
+                                    println!("No table selected");
                                 }
                             }
                         });
 
                         ui.heading("Pending Order");
-                        self.pending_order.iter().enumerate().for_each(|(i, id)| {
-                            ui.label(format!(
-                                "{i}: {}",
-                                self.menu.iter().find(|item| item.id == *id).unwrap().name
-                            ));
-                        });
+                        let mut dropped = None;
+                        self.pending_order
+                            .iter_mut()
+                            .enumerate()
+                            .for_each(|(i, line)| {
+                                let item = self.menu.iter().find(|item| item.id == line.item_id);
+                                if show_pending_line(ui, i, line, item, seats) {
+                                    dropped = Some(i);
+                                }
+                            });
+                        if let Some(i) = dropped {
+                            self.pending_order.remove(i);
+                        }
                     });
                 });
 
@@ -118,32 +287,200 @@ impl eframe::App for App {
                 ui.group(|ui| {
                     ui.vertical(|ui| {
                         ui.heading("Table Status");
-                        ui.text_edit_singleline(&mut self.table_selector);
-                        if ui.button("Fetch Table Items").clicked() {
-                            if let Ok(table_id) = self.table_selector.parse() {
-                                new_response = fetch_table_items(table_id);
-                            } else {
-                                println!("Failed to parse i64 from table_selector");
+                        let selected_label = self
+                            .tables
+                            .iter()
+                            .find(|table| Some(table.id) == self.selected_table)
+                            .map_or("Select a table", |table| &table.label);
+                        egui::ComboBox::from_label("Table")
+                            .selected_text(selected_label)
+                            .show_ui(ui, |ui| {
+                                self.tables.iter().for_each(|table| {
+                                    ui.selectable_value(
+                                        &mut self.selected_table,
+                                        Some(table.id),
+                                        format!(
+                                            "{} ({}, {} seats)",
+                                            table.label, table.section, table.seats
+                                        ),
+                                    );
+                                });
+                            });
+                        ui.horizontal(|ui| {
+                            if ui.button("Fetch Table Items").clicked() {
+                                if let Some(table_id) = self.selected_table {
+                                    new_response = fetch_table_items(table_id);
+                                    self.open_party = fetch_open_party(table_id);
+                                } else {
+                                    println!("No table selected");
+                                }
+                            }
+                            if ui.button("Refresh Tables").clicked() {
+                                self.tables = fetch_tables();
+                            }
+                            if ui.button("Show Bill").clicked() {
+                                self.bill = self.selected_table.and_then(fetch_bill);
+                            }
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Checks:");
+                            ui.text_edit_singleline(&mut self.split_count);
+                            if ui.button("Split Evenly").clicked() {
+                                if let (Some(table_id), Ok(checks)) =
+                                    (self.selected_table, self.split_count.parse())
+                                {
+                                    self.checks = split_evenly(table_id, checks);
+                                } else {
+                                    println!("No table selected, or failed to parse checks.");
+                                }
+                            }
+                            if ui.button("Split By Seat").clicked() {
+                                self.checks = self.selected_table.and_then(split_by_seat);
+                            }
+                            if ui.button("Join Checks").clicked() {
+                                self.checks = self.selected_table.and_then(join_checks);
+                            }
+                            if ui.button("Show Checks").clicked() {
+                                self.checks = self.selected_table.and_then(fetch_checks);
+                            }
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Tip:");
+                            ui.text_edit_singleline(&mut self.tip);
+                            for (label, tender) in
+                                [("Pay Cash", Tender::Cash), ("Pay Card", Tender::Card)]
+                            {
+                                if ui.button(label).clicked() {
+                                    if let (Some(table_id), Some(tip)) = (
+                                        self.selected_table,
+                                        parse_money(&self.tip, &self.currency),
+                                    ) {
+                                        pay_balance(table_id, tender, tip);
+                                        self.payments = fetch_payments(table_id);
+                                    } else {
+                                        println!("No table selected, or failed to parse tip.");
+                                    }
+                                }
+                            }
+                            if ui.button("Show Payments").clicked() {
+                                self.payments = self.selected_table.and_then(fetch_payments);
+                            }
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Guests:");
+                            ui.text_edit_singleline(&mut self.guests);
+                            if ui.button("Seat Party").clicked() {
+                                if let (Some(table_id), Ok(guests)) =
+                                    (self.selected_table, self.guests.parse())
+                                {
+                                    self.open_party = seat_party(table_id, guests);
+                                } else {
+                                    println!("No table selected, or failed to parse guests.");
+                                }
+                            }
+                        });
+                        let mut closed = false;
+                        if let Some(party) = &self.open_party {
+                            ui.horizontal(|ui| {
+                                ui.label(format!(
+                                    "Party {}: {} guests, seated @ {}",
+                                    party.id, party.guests, party.seated_at
+                                ));
+                                if ui.button("Close Party").clicked() {
+                                    closed = close_party(party.id).is_some();
+                                }
+                            });
+                        } else {
+                            ui.label("No party seated");
+                        }
+                        if closed {
+                            self.open_party = None;
+                            new_response = self.selected_table.and_then(fetch_table_items);
+                        }
+
+                        ui.horizontal(|ui| {
+                            ui.label("Staff:");
+                            ui.text_edit_singleline(&mut self.staff_name);
+                        });
+                        egui::ComboBox::from_label("Removal reason")
+                            .selected_text(format!("{:?}", self.removal_reason))
+                            .show_ui(ui, |ui| {
+                                [
+                                    RemovalReason::CustomerChangedMind,
+                                    RemovalReason::KitchenError,
+                                    RemovalReason::Comp,
+                                ]
+                                .into_iter()
+                                .for_each(|reason| {
+                                    ui.selectable_value(
+                                        &mut self.removal_reason,
+                                        reason,
+                                        format!("{reason:?}"),
+                                    );
+                                });
+                            });
+
+                        if let Some((table_id, order_id)) = self.last_removed {
+                            if ui.button("Undo Last Removal").clicked() {
+                                new_response = restore_item(table_id, order_id);
+                                self.last_removed = None;
                             }
                         }
 
                         self.table_response.ordered_items.iter().for_each(|item| {
                             ui.horizontal(|ui| {
                                 if ui.button("-").clicked() {
-                                    if let Ok(table_id) = self.table_selector.parse() {
-                                        new_response = remove_item(table_id, item.order_id);
+                                    if let Some(table_id) = self.selected_table {
+                                        let request = RemoveOrderRequest {
+                                            reason: self.removal_reason,
+                                            removed_by: self.staff_name.clone(),
+                                        };
+                                        new_response =
+                                            remove_item(table_id, item.order_id, &request);
+                                        if new_response.is_some() {
+                                            self.last_removed = Some((table_id, item.order_id));
+                                        }
                                     } else {
-                                        println!("Failed to parse i64 from table_selector");
+                                        println!("No table selected");
                                     }
                                 }
+                                let remaining = match item.overdue {
+                                    true => "OVERDUE".to_string(),
+                                    false => format!(
+                                        "{}m{:02}s left",
+                                        item.remaining_secs / 60,
+                                        item.remaining_secs % 60
+                                    ),
+                                };
                                 ui.label(format!(
-                                    "oid: {}, {}, rdy @ {}",
+                                    "oid: {}{}, {}x {}{}, {}, rdy @ {} ({remaining})",
                                     item.order_id,
-                                    self.menu.get(item.item_id as usize).unwrap().name, // This is not ideal...
-                                    item.ready_at,
+                                    seat_label(item.seat),
+                                    item.quantity,
+                                    // Items retired from the menu since it was fetched have no name here
+                                    self.menu
+                                        .iter()
+                                        .find(|menu_item| menu_item.id == item.item_id)
+                                        .map_or("(retired item)", |menu_item| &menu_item.name),
+                                    line_details(&item.modifiers, item.notes.as_deref()),
+                                    item.status,
+                                    item.ready_at_local,
                                 ));
                             });
                         });
+
+                        if let Some(bill) = &self.bill {
+                            show_bill(ui, bill);
+                        }
+                        if let Some(payments) = &self.payments {
+                            show_payments(ui, payments);
+                        }
+                        if let Some(checks) = &self.checks {
+                            show_checks(ui, checks);
+                        }
                     });
                 });
 
@@ -152,29 +489,27 @@ impl eframe::App for App {
                     ui.vertical(|ui| {
                         ui.heading("Debug Menu");
                         if ui.button("Random 10 Table Orders").clicked() {
-                            order_random_multiple(10);
+                            order_random_multiple(10, &self.tables);
                         }
 
                         if ui.button("Random 100 Table Orders").clicked() {
-                            order_random_multiple(100);
+                            order_random_multiple(100, &self.tables);
                         }
 
                         if ui.button("Random 1000 Table Orders").clicked() {
-                            order_random_multiple(1000);
+                            order_random_multiple(1000, &self.tables);
                         }
 
                         ui.label("oid:");
                         ui.text_edit_singleline(&mut self.debug_order_id);
 
                         if ui.button("Get Table Item").clicked() {
-                            if let (Ok(table_id), Ok(order_id)) =
-                                (self.table_selector.parse(), self.debug_order_id.parse())
+                            if let (Some(table_id), Ok(order_id)) =
+                                (self.selected_table, self.debug_order_id.parse())
                             {
                                 println!("{:?}", get_table_item(table_id, order_id));
                             } else {
-                                println!(
-                                    "Failed to parse i64 from table_selector or debug_order_id."
-                                );
+                                println!("No table selected, or failed to parse debug_order_id.");
                             }
                         }
                     })
@@ -188,14 +523,248 @@ impl eframe::App for App {
     }
 }
 
-fn order_random_multiple(count: usize) {
+/// Shows a table's bill line by line, then its totals
+fn show_bill(ui: &mut egui::Ui, bill: &Bill) {
+    let money = |amount| format_money(amount, &bill.currency);
+
+    ui.separator();
+    ui.heading(format!("Bill for table {}", bill.table_id));
+    bill.lines.iter().for_each(|line| {
+        ui.label(format!(
+            "{}x {}{}{} @ {} = {}",
+            line.quantity,
+            line.item_name,
+            line_details(&line.modifiers, None),
+            seat_label(line.seat),
+            money(line.unit_price),
+            line_amount(line, &bill.currency)
+        ));
+    });
+    ui.label(format!("Subtotal: {}", money(bill.subtotal)));
+    if bill.check_discount > 0 {
+        ui.label(format!("Discount: -{}", money(bill.check_discount)));
+    }
+    ui.label(format!(
+        "Tax ({}.{:02}%): {}",
+        bill.tax_rate_bps / 100,
+        bill.tax_rate_bps % 100,
+        money(bill.tax)
+    ));
+    ui.label(RichText::new(format!("Total: {}", money(bill.total))).strong());
+}
+
+/// Shows what each of a table's checks comes to, with the orders on it
+fn show_checks(ui: &mut egui::Ui, checks: &TableChecks) {
+    let money = |amount| format_money(amount, &checks.currency);
+
+    ui.separator();
+    ui.heading(format!("Checks for table {}", checks.table_id));
+    checks.checks.iter().for_each(|check| {
+        ui.label(
+            RichText::new(format!(
+                "Check {}: {} ({}{} + {} tax)",
+                check.number,
+                money(check.total),
+                money(check.subtotal),
+                if check.check_discount > 0 {
+                    format!(" - {} discount", money(check.check_discount))
+                } else {
+                    String::new()
+                },
+                money(check.tax)
+            ))
+            .strong(),
+        );
+        check.lines.iter().for_each(|line| {
+            ui.label(format!(
+                "    {}x {}{}{} = {}",
+                line.quantity,
+                line.item_name,
+                line_details(&line.modifiers, None),
+                seat_label(line.seat),
+                line_amount(line, &checks.currency)
+            ));
+        });
+    });
+    ui.label(format!("Total: {}", money(checks.total)));
+}
+
+/// Shows the payments made towards a table's bill and what is still owed
+fn show_payments(ui: &mut egui::Ui, payments: &TablePayments) {
+    let money = |amount| format_money(amount, &payments.currency);
+
+    ui.separator();
+    ui.heading(format!("Payments for table {}", payments.table_id));
+    payments.payments.iter().for_each(|payment| {
+        ui.label(format!(
+            "#{} {:?} {} + {} tip: {:?}{}",
+            payment.id,
+            payment.tender,
+            money(payment.amount),
+            money(payment.tip),
+            payment.status,
+            payment
+                .failure
+                .as_ref()
+                .map_or_else(String::new, |failure| format!(" ({failure})"))
+        ));
+    });
+    ui.label(format!(
+        "Paid {} of {}, with {} in tips",
+        money(payments.paid),
+        money(payments.total),
+        money(payments.tips)
+    ));
+    ui.label(RichText::new(format!("Balance: {}", money(payments.balance))).strong());
+}
+
+/// Shows one line of the pending order, letting its quantity, modifiers and
+/// notes be changed, and which of the table's `seats` it is for. Returns
+/// whether the line should be dropped.
+fn show_pending_line(
+    ui: &mut egui::Ui,
+    index: usize,
+    line: &mut OrderLine,
+    item: Option<&MenuListItem>,
+    seats: i64,
+) -> bool {
+    let mut dropped = false;
+
+    ui.horizontal(|ui| {
+        if ui.button("x").clicked() {
+            dropped = true;
+        }
+        ui.add(egui::DragValue::new(&mut line.quantity).range(1..=99));
+        ui.label(format!(
+            "{index}: {}",
+            item.map_or("(retired item)", |item| &item.name)
+        ));
+    });
+
+    ui.indent(("pending_line", index), |ui| {
+        if let Some(item) = item {
+            ui.horizontal_wrapped(|ui| {
+                item.modifiers.iter().for_each(|modifier| {
+                    let position = line
+                        .modifiers
+                        .iter()
+                        .position(|name| *name == modifier.name);
+                    let mut chosen = position.is_some();
+                    if ui.checkbox(&mut chosen, &modifier.name).changed() {
+                        match position {
+                            Some(position) => {
+                                line.modifiers.remove(position);
+                            }
+                            None => line.modifiers.push(modifier.name.clone()),
+                        }
+                    }
+                });
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.label("Notes:");
+            let mut notes = line.notes.clone().unwrap_or_default();
+            if ui.text_edit_singleline(&mut notes).changed() {
+                line.notes = Some(notes).filter(|notes| !notes.trim().is_empty());
+            }
+        });
+        seat_picker(ui, ("pending_line_seat", index), &mut line.seat, seats);
+    });
+
+    dropped
+}
+
+/// Lets one of a table's `seats` be picked, or none
+fn seat_picker(ui: &mut egui::Ui, id: impl std::hash::Hash, seat: &mut Option<i64>, seats: i64) {
+    ui.horizontal(|ui| {
+        ui.label("Seat:");
+        egui::ComboBox::from_id_salt(id)
+            .selected_text(seat.map_or("Any".to_string(), |seat| seat.to_string()))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(seat, None, "Any");
+                (1..=seats).for_each(|number| {
+                    ui.selectable_value(seat, Some(number), number.to_string());
+                });
+            });
+    });
+}
+
+/// What a bill line comes to, with any discount, comp or void made to it
+fn line_amount(line: &BillLine, currency: &str) -> String {
+    let money = |amount| format_money(amount, currency);
+    match line.adjustment {
+        None => money(line.line_total),
+        Some(AdjustmentKind::Discount) => format!(
+            "{} - {} discount = {}",
+            money(line.line_total),
+            money(line.discount),
+            money(line.net_total)
+        ),
+        Some(AdjustmentKind::Comp) => format!("{} comped", money(line.line_total)),
+        Some(AdjustmentKind::Void) => format!("{} voided", money(line.line_total)),
+    }
+}
+
+/// Reads an amount typed in major units, e.g. `2.50`, as minor units of
+/// `currency`
+fn parse_money(text: &str, currency: &str) -> Option<i64> {
+    let digits = minor_unit_digits(currency) as usize;
+    let (whole, fraction) = text.trim().split_once('.').unwrap_or((text.trim(), ""));
+    if fraction.len() > digits || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let whole = whole.parse::<i64>().ok().filter(|whole| *whole >= 0)?;
+    let fraction = format!("{fraction:0<digits$}").parse::<i64>().unwrap_or(0);
+    whole
+        .checked_mul(10_i64.pow(digits as u32))?
+        .checked_add(fraction)
+}
+
+/// The seat an order is for, for showing after its details
+fn seat_label(seat: Option<i64>) -> String {
+    seat.map_or_else(String::new, |seat| format!(" [seat {seat}]"))
+}
+
+/// The modifiers and notes of an order line, for showing after its item's name
+fn line_details(modifiers: &[Modifier], notes: Option<&str>) -> String {
+    let mut details = String::new();
+    if !modifiers.is_empty() {
+        let names = modifiers
+            .iter()
+            .map(|modifier| modifier.name.as_str())
+            .collect::<Vec<_>>();
+        details.push_str(&format!(" ({})", names.join(", ")));
+    }
+    if let Some(notes) = notes {
+        details.push_str(&format!(" \"{notes}\""));
+    }
+    details
+}
+
+/// Places `count` random orders, each for a random table from `tables`,
+/// seating a party there first when the table is free
+fn order_random_multiple(count: usize, tables: &[Table]) {
+    if tables.is_empty() {
+        println!("No tables to order for");
+        return;
+    }
+
     println!("Ordering random items to {count} tables...");
     let threads: Vec<JoinHandle<()>> = (0..count)
-        .map(|table_id| {
+        .map(|_| {
+            let table_id = tables[fastrand::usize(..tables.len())].id;
             std::thread::spawn(move || {
                 let item_count = fastrand::usize(5..15);
-                let items: Vec<i64> = (0..item_count).map(|_| fastrand::i64(0..15)).collect(); // Hard coded to 15 items
-                let _ = order_items(table_id as i64, &items);
+                let items: Vec<i64> = (0..item_count).map(|_| fastrand::i64(1..=15)).collect(); // Hard coded to 15 items
+                                                                                                // Another thread may have seated a party at the same table already
+                let _ = seat_party(table_id, fastrand::i64(1..=6));
+                let _ = order_items(
+                    table_id,
+                    &OrderItemsRequest {
+                        items,
+                        lines: Vec::new(),
+                    },
+                );
             })
         })
         .collect();
@@ -206,18 +775,328 @@ fn order_random_multiple(count: usize) {
     println!("Done!")
 }
 
-fn order_items(table_id: i64, items: &[i64]) -> Option<TableResponse> {
+fn order_items(table_id: i64, request: &OrderItemsRequest) -> Option<TableResponse> {
+    let client = reqwest::blocking::Client::new();
+    let response = client
+        .post(format!("{}/tables/{table_id}", restaurant_url()))
+        .json(request)
+        .send()
+        .unwrap();
+
+    if response.status() == StatusCode::UNPROCESSABLE_ENTITY {
+        println!("Order rejected: {:?}", response.json::<OrderRejection>());
+        return None;
+    }
+    if response.status() == StatusCode::CONFLICT {
+        println!("Order refused: {:?}", response.text());
+        return None;
+    }
+
+    match response.json() {
+        Ok(response) => Some(response),
+        Err(e) => {
+            println!("{e}");
+            None
+        }
+    }
+}
+
+fn fetch_menu() -> Vec<MenuListItem> {
+    let menu: Menu = reqwest::blocking::get(format!("{}/menu", restaurant_url()))
+        .unwrap()
+        .json()
+        .unwrap();
+
+    menu.items
+        .iter()
+        .map(|item| {
+            let prep_time = format!("~{}mins", (item.prep_min_m + item.prep_max_m) / 2.0);
+
+            MenuListItem {
+                id: item.id,
+                name: item.name.clone(),
+                prep_time,
+                modifiers: item.modifiers.clone(),
+                price: item.price,
+            }
+        })
+        .collect()
+}
+
+/// Follows the restaurant's event stream on a background thread, following
+/// it again whenever it drops. Each event asks `ctx` to repaint, so it is
+/// shown straight away.
+fn follow_events(ctx: egui::Context) -> Receiver<LiveEvent> {
+    let (sender, receiver) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        // The stream never finishes, so it can't have a timeout
+        let client = reqwest::blocking::Client::builder()
+            .timeout(None)
+            .build()
+            .unwrap();
+        let mut reconnecting = false;
+
+        loop {
+            // Changes made while the stream was down are lost, so everything
+            // built from it has to be fetched again
+            if reconnecting && sender.send(LiveEvent::Lagged { missed: 0 }).is_err() {
+                return;
+            }
+            reconnecting = true;
+
+            let response = match client.get(format!("{}/events", restaurant_url())).send() {
+                Ok(response) => response,
+                Err(e) => {
+                    println!("{e}");
+                    std::thread::sleep(RECONNECT_AFTER);
+                    continue;
+                }
+            };
+
+            for line in BufReader::new(response).lines().map_while(Result::ok) {
+                let Some(data) = line.strip_prefix("data:") else {
+                    continue;
+                };
+                match serde_json::from_str(data.trim()) {
+                    Ok(event) => {
+                        if sender.send(event).is_err() {
+                            return;
+                        }
+                        ctx.request_repaint();
+                    }
+                    Err(e) => println!("Unreadable live event: {e}"),
+                }
+            }
+            std::thread::sleep(RECONNECT_AFTER);
+        }
+    });
+
+    receiver
+}
+
+/// Fetches the tables staff can order for, i.e. the active ones
+fn fetch_tables() -> Vec<Table> {
+    match reqwest::blocking::get(format!("{}/tables", restaurant_url()))
+        .unwrap()
+        .json::<Vec<Table>>()
+    {
+        Ok(tables) => tables.into_iter().filter(|table| table.active).collect(),
+        Err(e) => {
+            println!("{e}");
+            Vec::new()
+        }
+    }
+}
+
+/// Seats a party of `guests` at the table, which fails if one is seated already
+fn seat_party(table_id: i64, guests: i64) -> Option<Party> {
+    let client = reqwest::blocking::Client::new();
+    let response = client
+        .post(format!("{}/tables/{table_id}/party", restaurant_url()))
+        .json(&SeatPartyRequest { guests })
+        .send()
+        .unwrap();
+
+    if !response.status().is_success() {
+        println!("Party not seated: {:?}", response.text());
+        return None;
+    }
+
+    match response.json() {
+        Ok(party) => Some(party),
+        Err(e) => {
+            println!("{e}");
+            None
+        }
+    }
+}
+
+/// Fetches the party seated at the table, if any
+fn fetch_open_party(table_id: i64) -> Option<Party> {
+    let response =
+        reqwest::blocking::get(format!("{}/tables/{table_id}/party", restaurant_url())).unwrap();
+
+    if response.status() == StatusCode::NOT_FOUND {
+        return None;
+    }
+
+    match response.json() {
+        Ok(party) => Some(party),
+        Err(e) => {
+            println!("{e}");
+            None
+        }
+    }
+}
+
+/// Checks the party out, finishing its orders and freeing its table
+fn close_party(party_id: i64) -> Option<Party> {
     let client = reqwest::blocking::Client::new();
     match client
-        .post(format!("http://{SERVICE_URL}/tables/{table_id}"))
-        .json(&OrderItemsRequest {
-            items: items.to_vec(),
+        .post(format!("{}/parties/{party_id}/close", restaurant_url()))
+        .send()
+        .unwrap()
+        .error_for_status()
+        .and_then(|response| response.json())
+    {
+        Ok(party) => Some(party),
+        Err(e) => {
+            println!("{e}");
+            None
+        }
+    }
+}
+
+fn fetch_restaurant() -> Restaurant {
+    reqwest::blocking::get(restaurant_url())
+        .unwrap()
+        .json()
+        .unwrap()
+}
+
+fn fetch_bill(table_id: i64) -> Option<Bill> {
+    match reqwest::blocking::get(format!("{}/tables/{table_id}/bill", restaurant_url()))
+        .unwrap()
+        .json()
+    {
+        Ok(bill) => Some(bill),
+        Err(e) => {
+            println!("{e}");
+            None
+        }
+    }
+}
+
+fn fetch_payments(table_id: i64) -> Option<TablePayments> {
+    match reqwest::blocking::get(format!("{}/tables/{table_id}/payments", restaurant_url()))
+        .unwrap()
+        .json()
+    {
+        Ok(payments) => Some(payments),
+        Err(e) => {
+            println!("{e}");
+            None
+        }
+    }
+}
+
+/// Pays whatever the table still owes, plus `tip`. Card payments are
+/// captured as soon as the processor authorizes them.
+fn pay_balance(table_id: i64, tender: Tender, tip: i64) {
+    let Some(owed) = fetch_payments(table_id).map(|payments| payments.balance) else {
+        return;
+    };
+    if owed <= 0 {
+        println!("Table {table_id} has nothing left to pay");
+        return;
+    }
+
+    let client = reqwest::blocking::Client::new();
+    let payment: Payment = match client
+        .post(format!("{}/tables/{table_id}/payments", restaurant_url()))
+        .json(&NewPayment {
+            tender,
+            amount: owed,
+            tip,
         })
         .send()
+        .unwrap()
+        .error_for_status()
+        .and_then(|response| response.json())
+    {
+        Ok(payment) => payment,
+        Err(e) => {
+            println!("Payment not taken: {e}");
+            return;
+        }
+    };
+    if tender == Tender::Cash {
+        return;
+    }
+
+    if let Err(e) = client
+        .post(format!(
+            "{}/payments/{}/capture",
+            restaurant_url(),
+            payment.id
+        ))
+        .json(&CapturePaymentRequest::default())
+        .send()
+        .unwrap()
+        .error_for_status()
+    {
+        println!("Payment {} not captured: {e}", payment.id);
+    }
+}
+
+fn fetch_checks(table_id: i64) -> Option<TableChecks> {
+    match reqwest::blocking::get(format!("{}/tables/{table_id}/checks", restaurant_url()))
         .unwrap()
         .json()
     {
-        Ok(response) => Some(response),
+        Ok(checks) => Some(checks),
+        Err(e) => {
+            println!("{e}");
+            None
+        }
+    }
+}
+
+/// Shares the table's bill evenly between `checks` checks
+fn split_evenly(table_id: i64, checks: i64) -> Option<TableChecks> {
+    let client = reqwest::blocking::Client::new();
+    match client
+        .post(format!(
+            "{}/tables/{table_id}/checks/even",
+            restaurant_url()
+        ))
+        .json(&SplitEvenlyRequest { checks })
+        .send()
+        .unwrap()
+        .error_for_status()
+        .and_then(|response| response.json())
+    {
+        Ok(checks) => Some(checks),
+        Err(e) => {
+            println!("{e}");
+            None
+        }
+    }
+}
+
+/// Gives each seat at the table its own check
+fn split_by_seat(table_id: i64) -> Option<TableChecks> {
+    let client = reqwest::blocking::Client::new();
+    match client
+        .post(format!(
+            "{}/tables/{table_id}/checks/seats",
+            restaurant_url()
+        ))
+        .send()
+        .unwrap()
+        .error_for_status()
+        .and_then(|response| response.json())
+    {
+        Ok(checks) => Some(checks),
+        Err(e) => {
+            println!("{e}");
+            None
+        }
+    }
+}
+
+/// Puts the whole of the table's bill back on one check
+fn join_checks(table_id: i64) -> Option<TableChecks> {
+    let client = reqwest::blocking::Client::new();
+    match client
+        .delete(format!("{}/tables/{table_id}/checks", restaurant_url()))
+        .send()
+        .unwrap()
+        .error_for_status()
+        .and_then(|response| response.json())
+    {
+        Ok(checks) => Some(checks),
         Err(e) => {
             println!("{e}");
             None
@@ -226,7 +1105,28 @@ fn order_items(table_id: i64, items: &[i64]) -> Option<TableResponse> {
 }
 
 fn fetch_table_items(table_id: i64) -> Option<TableResponse> {
-    match reqwest::blocking::get(format!("http://{SERVICE_URL}/tables/{table_id}"))
+    match reqwest::blocking::get(format!("{}/tables/{table_id}", restaurant_url()))
+        .unwrap()
+        .json()
+    {
+        Ok(response) => Some(response),
+        Err(e) => {
+            println!("{e}");
+            None
+        }
+    }
+}
+
+fn remove_item(
+    table_id: i64,
+    order_id: i64,
+    request: &RemoveOrderRequest,
+) -> Option<TableResponse> {
+    let client = reqwest::blocking::Client::new();
+    match client
+        .delete(format!("{}/tables/{table_id}/{order_id}", restaurant_url()))
+        .query(request)
+        .send()
         .unwrap()
         .json()
     {
@@ -238,10 +1138,13 @@ fn fetch_table_items(table_id: i64) -> Option<TableResponse> {
     }
 }
 
-fn remove_item(table_id: i64, order_id: i64) -> Option<TableResponse> {
+fn restore_item(table_id: i64, order_id: i64) -> Option<TableResponse> {
     let client = reqwest::blocking::Client::new();
     match client
-        .delete(format!("http://{SERVICE_URL}/tables/{table_id}/{order_id}"))
+        .post(format!(
+            "{}/tables/{table_id}/{order_id}/restore",
+            restaurant_url()
+        ))
         .send()
         .unwrap()
         .json()
@@ -257,7 +1160,7 @@ fn remove_item(table_id: i64, order_id: i64) -> Option<TableResponse> {
 fn get_table_item(table_id: i64, order_id: i64) -> Option<String> {
     let client = reqwest::blocking::Client::new();
     match client
-        .get(format!("http://{SERVICE_URL}/tables/{table_id}/{order_id}"))
+        .get(format!("{}/tables/{table_id}/{order_id}", restaurant_url()))
         .send()
         .unwrap()
         .text()
@@ -0,0 +1,88 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{delete, get};
+use axum::{Json, Router};
+use shared::{Adjustment, Manager, NewAdjustment, NewManager};
+
+use crate::storage::Storage;
+use crate::{ServiceResponse, ServiceState};
+
+/// Routes for managers and the discounts, comps and voids they approve
+pub fn routes<S: Storage>() -> Router<ServiceState<S>> {
+    Router::new()
+        .route("/managers", get(get_managers::<S>).post(add_manager::<S>))
+        .route("/managers/:manager_id", delete(retire_manager::<S>))
+        .route(
+            "/tables/:table_id/adjustments",
+            get(get_adjustments::<S>).post(add_adjustment::<S>),
+        )
+}
+
+// Manager: list the restaurant's managers, including ones who have left
+async fn get_managers<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path(restaurant_id): Path<i64>,
+) -> ServiceResponse<Json<Vec<Manager>>> {
+    println!("get_managers {restaurant_id}");
+    let managers = state.storage.get_managers(restaurant_id).await?;
+
+    Ok((StatusCode::OK, Json(managers)))
+}
+
+// Manager: add a manager with the PIN they approve comps and voids with
+async fn add_manager<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path(restaurant_id): Path<i64>,
+    Json(payload): Json<NewManager>,
+) -> ServiceResponse<Json<Manager>> {
+    println!("add_manager {restaurant_id}, {}", payload.name);
+    let manager = state.storage.add_manager(restaurant_id, payload).await?;
+
+    Ok((StatusCode::CREATED, Json(manager)))
+}
+
+// Manager: stop a manager approving anything more
+async fn retire_manager<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, manager_id)): Path<(i64, i64)>,
+) -> ServiceResponse<Json<Manager>> {
+    println!("retire_manager {restaurant_id}, {manager_id}");
+    let manager = state
+        .storage
+        .retire_manager(restaurant_id, manager_id)
+        .await?;
+
+    Ok((StatusCode::OK, Json(manager)))
+}
+
+// Client: list the discounts, comps and voids made to the table's bill
+async fn get_adjustments<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+) -> ServiceResponse<Json<Vec<Adjustment>>> {
+    println!("get_adjustments {restaurant_id}, {table_id}");
+    let adjustments = state
+        .storage
+        .get_adjustments(restaurant_id, table_id)
+        .await?;
+
+    Ok((StatusCode::OK, Json(adjustments)))
+}
+
+// Client: discount, comp or void part of the table's bill with a manager's approval
+async fn add_adjustment<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+    Json(payload): Json<NewAdjustment>,
+) -> ServiceResponse<Json<Adjustment>> {
+    println!(
+        "add_adjustment {restaurant_id}, {table_id}, {:?}, {:?}, {}",
+        payload.kind, payload.order_id, payload.approved_by
+    );
+    let adjustment = state
+        .storage
+        .add_adjustment(restaurant_id, table_id, payload)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(adjustment)))
+}
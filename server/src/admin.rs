@@ -0,0 +1,292 @@
+use std::path::{Path, PathBuf};
+
+use axum::extract::{Path as UrlPath, Query, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use shared::{
+    ArchivedOrder, BackupReport, CloseOutSummary, DataExport, ImportReport, RebuildReport,
+};
+
+use crate::closeout;
+use crate::config::Config;
+use crate::storage::{Storage, StorageError, StorageResult};
+use crate::{ServiceResponse, ServiceState};
+
+/// Maintenance routes for operators, covering every restaurant at once
+pub fn routes<S: Storage>() -> Router<ServiceState<S>> {
+    Router::new()
+        .route("/admin/rebuild-orders", post(rebuild_orders::<S>))
+        .route("/admin/backup", post(backup::<S>))
+}
+
+/// Maintenance routes for managers of a single restaurant, nested under
+/// `/restaurants/:restaurant_id`
+pub fn restaurant_routes<S: Storage>() -> Router<ServiceState<S>> {
+    Router::new()
+        .route("/admin/export", get(export_data::<S>))
+        .route("/admin/import", post(import_data::<S>))
+        .route("/admin/close-out", post(close_out::<S>))
+        .route("/admin/close-outs", get(get_close_outs::<S>))
+        .route(
+            "/admin/archive/:business_date",
+            get(get_archived_orders::<S>),
+        )
+}
+
+#[derive(Deserialize)]
+struct RebuildOrdersParams {
+    /// Only compare the orders with the event log, without changing them
+    #[serde(default)]
+    dry_run: bool,
+}
+
+// Admin: rebuild the orders table from the order event log and report any differences
+async fn rebuild_orders<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Query(params): Query<RebuildOrdersParams>,
+) -> ServiceResponse<Json<RebuildReport>> {
+    println!("rebuild_orders dry_run: {}", params.dry_run);
+    let report = state.storage.rebuild_orders(params.dry_run).await?;
+
+    Ok((StatusCode::OK, Json(report)))
+}
+
+// Admin: take an online backup of the database into the configured backup directory
+async fn backup<S: Storage>(
+    State(state): State<ServiceState<S>>,
+) -> ServiceResponse<Json<BackupReport>> {
+    println!("backup");
+    let dir = &state.config.backup_dir;
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(|e| StorageError::Internal(e.to_string()))?;
+
+    let file_name = chrono::Utc::now().format("restaurant-%Y%m%dT%H%M%S%.3fZ.db");
+    let path = dir.join(file_name.to_string());
+    state.storage.backup(path.clone()).await?;
+
+    Ok((StatusCode::CREATED, Json(backup_report(&path).await?)))
+}
+
+// Admin: download the restaurant's menu and every order as portable JSON
+async fn export_data<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    UrlPath(restaurant_id): UrlPath<i64>,
+) -> ServiceResponse<Json<DataExport>> {
+    println!("export_data {restaurant_id}");
+    let data = state.storage.export_data(restaurant_id).await?;
+
+    Ok((StatusCode::OK, Json(data)))
+}
+
+// Admin: replace the restaurant's menu and every order with an exported snapshot
+async fn import_data<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    UrlPath(restaurant_id): UrlPath<i64>,
+    Json(data): Json<DataExport>,
+) -> ServiceResponse<Json<ImportReport>> {
+    println!("import_data {restaurant_id}");
+    let report = state.storage.import_data(restaurant_id, data).await?;
+
+    Ok((StatusCode::OK, Json(report)))
+}
+
+#[derive(Deserialize)]
+struct CloseOutParams {
+    /// `YYYY-MM-DD`, the current business day if left out
+    business_date: Option<String>,
+}
+
+// Admin: archive every order for the business day and summarise them, leaving the tables empty
+async fn close_out<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    UrlPath(restaurant_id): UrlPath<i64>,
+    Query(params): Query<CloseOutParams>,
+) -> ServiceResponse<Json<CloseOutSummary>> {
+    println!("close_out {restaurant_id}, {:?}", params.business_date);
+    let business_date = match params.business_date {
+        Some(business_date) => parse_business_date(&business_date)?,
+        None => {
+            let restaurant = state.storage.get_restaurant(restaurant_id).await?;
+            closeout::current_business_date(&restaurant, state.config.business_day_start)
+        }
+    };
+    let summary = state
+        .storage
+        .close_out(restaurant_id, business_date)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(summary)))
+}
+
+// Admin: list the summary of every past close-out
+async fn get_close_outs<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    UrlPath(restaurant_id): UrlPath<i64>,
+) -> ServiceResponse<Json<Vec<CloseOutSummary>>> {
+    println!("get_close_outs {restaurant_id}");
+    let summaries = state.storage.get_close_outs(restaurant_id).await?;
+
+    Ok((StatusCode::OK, Json(summaries)))
+}
+
+// Admin: query the orders archived for a business day
+async fn get_archived_orders<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    UrlPath((restaurant_id, business_date)): UrlPath<(i64, String)>,
+) -> ServiceResponse<Json<Vec<ArchivedOrder>>> {
+    println!("get_archived_orders {restaurant_id}, {business_date}");
+    let business_date = parse_business_date(&business_date)?;
+    let orders = state
+        .storage
+        .get_archived_orders(restaurant_id, business_date)
+        .await?;
+
+    Ok((StatusCode::OK, Json(orders)))
+}
+
+fn parse_business_date(text: &str) -> StorageResult<NaiveDate> {
+    NaiveDate::parse_from_str(text, "%Y-%m-%d").map_err(|_| {
+        StorageError::BadRequest(format!("'{text}' is not a YYYY-MM-DD business date"))
+    })
+}
+
+async fn backup_report(path: &Path) -> StorageResult<BackupReport> {
+    let metadata = tokio::fs::metadata(path)
+        .await
+        .map_err(|e| StorageError::Internal(e.to_string()))?;
+
+    Ok(BackupReport {
+        path: path.display().to_string(),
+        bytes: metadata.len(),
+    })
+}
+
+/// Runs a one-off admin command from the command line instead of serving
+/// requests. Returns false if `args` isn't a known command.
+pub async fn run_command<S: Storage>(storage: S, config: &Config, args: &[String]) -> bool {
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["rebuild-orders"] => print_rebuild(storage.rebuild_orders(false).await),
+        ["rebuild-orders", "--dry-run"] => print_rebuild(storage.rebuild_orders(true).await),
+        ["backup", path] => {
+            let path = PathBuf::from(path);
+            exit_on_error(storage.backup(path.clone()).await);
+            let report = exit_on_error(backup_report(&path).await);
+            println!("Backed up {} bytes to {}", report.bytes, report.path);
+        }
+        ["restore", path] => {
+            exit_on_error(storage.restore(PathBuf::from(path)).await);
+            println!("Restored database from {path}");
+        }
+        ["export", restaurant_id, path] => {
+            let restaurant_id = exit_on_error(parse_restaurant(&storage, restaurant_id).await);
+            let data = exit_on_error(storage.export_data(restaurant_id).await);
+            let json = serde_json::to_string_pretty(&data).unwrap();
+            exit_on_error(
+                std::fs::write(path, json).map_err(|e| StorageError::Internal(e.to_string())),
+            );
+            println!(
+                "Exported {} menu items and {} tables to {path}",
+                data.menu.items.len() + data.retired_items.len(),
+                data.tables.len()
+            );
+        }
+        ["import", restaurant_id, path] => {
+            let restaurant_id = exit_on_error(parse_restaurant(&storage, restaurant_id).await);
+            let data = exit_on_error(
+                std::fs::read_to_string(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+                    .map_err(StorageError::BadRequest),
+            );
+            let report = exit_on_error(storage.import_data(restaurant_id, data).await);
+            println!(
+                "Imported {} menu items, {} tables, {} parties and {} orders from {path}",
+                report.menu_items, report.tables, report.parties, report.orders
+            );
+        }
+        ["close-out"] => {
+            close_out_all(&storage, None, config).await;
+        }
+        ["close-out", business_date] => {
+            let business_date = exit_on_error(parse_business_date(business_date));
+            close_out_all(&storage, Some(business_date), config).await;
+        }
+        _ => return false,
+    }
+
+    true
+}
+
+/// Parses a restaurant id given on the command line, checking it exists
+async fn parse_restaurant<S: Storage>(storage: &S, text: &str) -> StorageResult<i64> {
+    let restaurant_id = text
+        .parse()
+        .map_err(|_| StorageError::BadRequest(format!("'{text}' is not a restaurant id")))?;
+    storage.get_restaurant(restaurant_id).await?;
+
+    Ok(restaurant_id)
+}
+
+/// Closes out the business day for every restaurant in turn, each restaurant's
+/// current one in its own time zone if no date is given
+async fn close_out_all<S: Storage>(storage: &S, business_date: Option<NaiveDate>, config: &Config) {
+    for restaurant in exit_on_error(storage.get_restaurants().await) {
+        let business_date = business_date.unwrap_or_else(|| {
+            closeout::current_business_date(&restaurant, config.business_day_start)
+        });
+        println!(
+            "Restaurant {} ({}), {business_date}:",
+            restaurant.id, restaurant.name
+        );
+        print_close_out(storage.close_out(restaurant.id, business_date).await);
+    }
+}
+
+fn exit_on_error<T>(result: StorageResult<T>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{e:?}");
+        std::process::exit(1);
+    })
+}
+
+fn print_rebuild(report: StorageResult<RebuildReport>) {
+    let report = exit_on_error(report);
+
+    report
+        .mismatches
+        .iter()
+        .for_each(|mismatch| println!("Mismatch: {mismatch}"));
+    println!(
+        "Replayed {} events into {} orders, {} mismatches, {}",
+        report.events_replayed,
+        report.orders,
+        report.mismatches.len(),
+        match report.rebuilt {
+            true => "orders rebuilt",
+            false => "dry run, nothing changed",
+        }
+    );
+}
+
+fn print_close_out(summary: StorageResult<CloseOutSummary>) {
+    let summary = exit_on_error(summary);
+
+    summary
+        .items_served
+        .iter()
+        .for_each(|item| println!("{:>5} x {}", item.count, item.name));
+    println!(
+        "Closed out {}: archived {} orders ({} removed) from {} tables",
+        summary.business_date, summary.orders, summary.removed_orders, summary.tables
+    );
+    if !summary.unsettled_parties.is_empty() {
+        println!(
+            "Left parties {:?} open, as they haven't settled up",
+            summary.unsettled_parties
+        );
+    }
+}
@@ -0,0 +1,565 @@
+use std::cmp::Reverse;
+
+use axum::extract::{Extension, Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use shared::{
+    Adjustment, AdjustmentKind, Bill, BillLine, Check, CheckSplit, Discount, MoveToCheckRequest,
+    Restaurant, SplitEvenlyRequest, TableChecks,
+};
+
+use crate::storage::{SplitChange, Storage, StorageResult};
+use crate::{ServiceResponse, ServiceState};
+
+/// Routes working out what tables owe
+pub fn routes<S: Storage>() -> Router<ServiceState<S>> {
+    Router::new()
+        .route("/tables/:table_id/bill", get(get_bill::<S>))
+        .route(
+            "/tables/:table_id/checks",
+            get(get_checks::<S>).delete(join_checks::<S>),
+        )
+        .route("/tables/:table_id/checks/even", post(split_evenly::<S>))
+        .route("/tables/:table_id/checks/move", post(move_to_check::<S>))
+        .route("/tables/:table_id/checks/seats", post(split_by_seat::<S>))
+}
+
+// Client: itemize what a table owes for its live orders, with any
+// adjustments taken off and tax added
+async fn get_bill<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Extension(restaurant): Extension<Restaurant>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+) -> ServiceResponse<Json<Bill>> {
+    println!("get_bill {restaurant_id}, {table_id}");
+    let bill = fetch_bill(&state.storage, &restaurant, restaurant_id, table_id).await?;
+
+    Ok((StatusCode::OK, Json(bill)))
+}
+
+// Client: show the checks the table's bill is split into
+async fn get_checks<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Extension(restaurant): Extension<Restaurant>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+) -> ServiceResponse<Json<TableChecks>> {
+    println!("get_checks {restaurant_id}, {table_id}");
+    let split = state
+        .storage
+        .get_check_split(restaurant_id, table_id)
+        .await?;
+    let bill = fetch_bill(&state.storage, &restaurant, restaurant_id, table_id).await?;
+
+    Ok((StatusCode::OK, Json(checks(split, bill))))
+}
+
+// Client: share the table's bill evenly between a number of checks
+async fn split_evenly<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Extension(restaurant): Extension<Restaurant>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+    Json(payload): Json<SplitEvenlyRequest>,
+) -> ServiceResponse<Json<TableChecks>> {
+    println!(
+        "split_evenly {restaurant_id}, {table_id}, {}",
+        payload.checks
+    );
+    let change = SplitChange::Evenly(payload.checks);
+    split_checks(state, restaurant, restaurant_id, table_id, change).await
+}
+
+// Client: move orders onto one of the table's checks, or onto a new one
+async fn move_to_check<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Extension(restaurant): Extension<Restaurant>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+    Json(payload): Json<MoveToCheckRequest>,
+) -> ServiceResponse<Json<TableChecks>> {
+    println!(
+        "move_to_check {restaurant_id}, {table_id}, {:?}, {}",
+        payload.order_ids, payload.check
+    );
+    let change = SplitChange::Move {
+        order_ids: payload.order_ids,
+        check: payload.check,
+    };
+    split_checks(state, restaurant, restaurant_id, table_id, change).await
+}
+
+// Client: give each seat at the table its own check
+async fn split_by_seat<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Extension(restaurant): Extension<Restaurant>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+) -> ServiceResponse<Json<TableChecks>> {
+    println!("split_by_seat {restaurant_id}, {table_id}");
+    split_checks(
+        state,
+        restaurant,
+        restaurant_id,
+        table_id,
+        SplitChange::BySeat,
+    )
+    .await
+}
+
+// Client: put the whole of the table's bill back on one check
+async fn join_checks<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Extension(restaurant): Extension<Restaurant>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+) -> ServiceResponse<Json<TableChecks>> {
+    println!("join_checks {restaurant_id}, {table_id}");
+    split_checks(
+        state,
+        restaurant,
+        restaurant_id,
+        table_id,
+        SplitChange::Join,
+    )
+    .await
+}
+
+async fn split_checks<S: Storage>(
+    state: ServiceState<S>,
+    restaurant: Restaurant,
+    restaurant_id: i64,
+    table_id: i64,
+    change: SplitChange,
+) -> ServiceResponse<Json<TableChecks>> {
+    let split = state
+        .storage
+        .split_checks(restaurant_id, table_id, change)
+        .await?;
+    let bill = fetch_bill(&state.storage, &restaurant, restaurant_id, table_id).await?;
+
+    Ok((StatusCode::OK, Json(checks(split, bill))))
+}
+
+/// Fetches what the table owes, with its adjustments taken off
+pub async fn fetch_bill<S: Storage>(
+    storage: &S,
+    restaurant: &Restaurant,
+    restaurant_id: i64,
+    table_id: i64,
+) -> StorageResult<Bill> {
+    let lines = storage.get_bill_lines(restaurant_id, table_id).await?;
+    let adjustments = storage.get_adjustments(restaurant_id, table_id).await?;
+
+    Ok(bill(table_id, restaurant, lines, adjustments))
+}
+
+/// Totals a table's bill lines, takes off the party's discounts, comps and
+/// voids, and adds the restaurant's tax on what is left
+pub fn bill(
+    table_id: i64,
+    restaurant: &Restaurant,
+    mut lines: Vec<BillLine>,
+    adjustments: Vec<Adjustment>,
+) -> Bill {
+    for line in &mut lines {
+        let Some(adjustment) = adjustments
+            .iter()
+            .find(|adjustment| adjustment.order_id == Some(line.order_id))
+        else {
+            continue;
+        };
+        line.discount = match (adjustment.kind, adjustment.discount) {
+            (AdjustmentKind::Discount, Some(discount)) => discount_on(line.line_total, discount),
+            _ => line.line_total,
+        };
+        line.net_total = line.line_total - line.discount;
+        line.adjustment = Some(adjustment.kind);
+    }
+
+    let subtotal = lines.iter().map(|line| line.net_total).sum();
+    let check_discount = adjustments
+        .iter()
+        .filter(|adjustment| adjustment.order_id.is_none())
+        .filter_map(|adjustment| adjustment.discount)
+        .map(|discount| discount_on(subtotal, discount))
+        .sum::<i64>()
+        .min(subtotal);
+    let tax = bps_of(subtotal - check_discount, restaurant.tax_rate_bps);
+
+    Bill {
+        table_id,
+        currency: restaurant.currency.clone(),
+        lines,
+        subtotal,
+        check_discount,
+        tax_rate_bps: restaurant.tax_rate_bps,
+        tax,
+        total: subtotal - check_discount + tax,
+        adjustments,
+    }
+}
+
+/// How much `discount` takes off `amount`, never more than all of it
+fn discount_on(amount: i64, discount: Discount) -> i64 {
+    match discount {
+        Discount::Percent { bps } => bps_of(amount, bps),
+        Discount::Fixed { amount: off } => off,
+    }
+    .min(amount)
+}
+
+/// Divides a table's bill into checks. Check-level discounts and tax are
+/// worked out once on the whole bill and then shared out, so the checks add
+/// up to exactly what the bill would have been.
+pub fn checks(split: CheckSplit, bill: Bill) -> TableChecks {
+    let checks = match &split {
+        CheckSplit::Single => vec![Check {
+            number: 1,
+            lines: bill.lines,
+            subtotal: bill.subtotal,
+            check_discount: bill.check_discount,
+            tax: bill.tax,
+            total: bill.total,
+        }],
+        CheckSplit::Even { checks } => {
+            let weights = vec![1; *checks as usize];
+            let totals = share(bill.total, &weights);
+            let taxes = share(bill.tax, &weights);
+            let discounts = share(bill.check_discount, &weights);
+            totals
+                .into_iter()
+                .zip(taxes)
+                .zip(discounts)
+                .zip(1..)
+                .map(|(((total, tax), check_discount), number)| Check {
+                    number,
+                    lines: Vec::new(),
+                    subtotal: total - tax + check_discount,
+                    check_discount,
+                    tax,
+                    total,
+                })
+                .collect()
+        }
+        CheckSplit::ByOrder {
+            checks,
+            assignments,
+        } => {
+            let mut lines_by_check = vec![Vec::new(); *checks as usize];
+            for line in bill.lines {
+                let check = assignments
+                    .iter()
+                    .find(|assignment| assignment.order_id == line.order_id)
+                    .map_or(1, |assignment| assignment.check);
+                lines_by_check[check as usize - 1].push(line);
+            }
+            let subtotals = lines_by_check
+                .iter()
+                .map(|lines| lines.iter().map(|line| line.net_total).sum())
+                .collect::<Vec<i64>>();
+            let discounts = share(bill.check_discount, &subtotals);
+            let taxable = subtotals
+                .iter()
+                .zip(&discounts)
+                .map(|(subtotal, discount)| subtotal - discount)
+                .collect::<Vec<i64>>();
+            let taxes = share(bill.tax, &taxable);
+            lines_by_check
+                .into_iter()
+                .zip(subtotals)
+                .zip(discounts)
+                .zip(taxes)
+                .zip(1..)
+                .map(
+                    |((((lines, subtotal), check_discount), tax), number)| Check {
+                        number,
+                        lines,
+                        subtotal,
+                        check_discount,
+                        tax,
+                        total: subtotal - check_discount + tax,
+                    },
+                )
+                .collect()
+        }
+    };
+
+    TableChecks {
+        table_id: bill.table_id,
+        currency: bill.currency,
+        split,
+        checks,
+        subtotal: bill.subtotal,
+        check_discount: bill.check_discount,
+        tax: bill.tax,
+        total: bill.total,
+    }
+}
+
+/// `rate_bps` basis points of `amount`, rounded to the nearest minor unit
+/// with halves rounded up
+pub fn bps_of(amount: i64, rate_bps: i64) -> i64 {
+    ((amount as i128 * rate_bps as i128 + 5_000).div_euclid(10_000)) as i64
+}
+
+/// Shares `amount` out in proportion to `weights` so that the shares add up
+/// to it exactly. Every share is rounded down, then the minor units left
+/// over go one each to the shares which lost the most to rounding, earlier
+/// ones first on a tie. With nothing to weigh by the amount is shared evenly.
+fn share(amount: i64, weights: &[i64]) -> Vec<i64> {
+    let mut weights = weights
+        .iter()
+        .map(|&weight| weight as i128)
+        .collect::<Vec<_>>();
+    if weights.iter().all(|&weight| weight == 0) {
+        weights.fill(1);
+    }
+    let total_weight = weights.iter().sum::<i128>();
+
+    let (mut shares, remainders): (Vec<i64>, Vec<i128>) = weights
+        .iter()
+        .map(|weight| {
+            let exact = amount as i128 * weight;
+            ((exact / total_weight) as i64, exact % total_weight)
+        })
+        .unzip();
+
+    let left_over = amount - shares.iter().sum::<i64>();
+    let mut by_remainder = (0..shares.len()).collect::<Vec<_>>();
+    by_remainder.sort_by_key(|&index| Reverse(remainders[index]));
+    for index in by_remainder.into_iter().take(left_over as usize) {
+        shares[index] += 1;
+    }
+    shares
+}
+
+#[cfg(test)]
+mod tests {
+    use shared::CheckAssignment;
+
+    use crate::storage::apply_split_change;
+
+    use super::*;
+
+    fn restaurant(tax_rate_bps: i64) -> Restaurant {
+        Restaurant {
+            id: 1,
+            name: "Test".to_string(),
+            time_zone: "UTC".to_string(),
+            kitchen_slots: 4,
+            currency: "USD".to_string(),
+            tax_rate_bps,
+        }
+    }
+
+    fn line(order_id: i64, line_total: i64, seat: Option<i64>) -> BillLine {
+        BillLine {
+            order_id,
+            item_id: 1,
+            item_name: "Soup".to_string(),
+            quantity: 1,
+            modifiers: Vec::new(),
+            unit_price: line_total,
+            line_total,
+            seat,
+            adjustment: None,
+            discount: 0,
+            net_total: line_total,
+        }
+    }
+
+    fn adjustment(
+        order_id: Option<i64>,
+        kind: AdjustmentKind,
+        discount: Option<Discount>,
+    ) -> Adjustment {
+        Adjustment {
+            id: 1,
+            party_id: 1,
+            table_id: 1,
+            order_id,
+            kind,
+            discount,
+            reason: "test".to_string(),
+            requested_by: "amy".to_string(),
+            approved_by: "Sam".to_string(),
+            created_at: "2026-01-01T00:00:00Z".to_string(),
+        }
+    }
+
+    /// Three lines, two at seat 1 and one at seat 2, with 10% off the bill
+    /// and 10% tax
+    fn seated_bill() -> Bill {
+        let lines = vec![
+            line(1, 1099, Some(1)),
+            line(2, 550, Some(2)),
+            line(3, 333, Some(1)),
+        ];
+        let discount = Discount::Percent { bps: 1000 };
+        let adjustments = vec![adjustment(None, AdjustmentKind::Discount, Some(discount))];
+        bill(1, &restaurant(1000), lines, adjustments)
+    }
+
+    fn assert_adds_up(checks: &TableChecks) {
+        let sum = |amount: fn(&Check) -> i64| checks.checks.iter().map(amount).sum::<i64>();
+        assert_eq!(sum(|check| check.subtotal), checks.subtotal);
+        assert_eq!(sum(|check| check.check_discount), checks.check_discount);
+        assert_eq!(sum(|check| check.tax), checks.tax);
+        assert_eq!(sum(|check| check.total), checks.total);
+        for check in &checks.checks {
+            assert_eq!(
+                check.total,
+                check.subtotal - check.check_discount + check.tax
+            );
+        }
+    }
+
+    #[test]
+    fn shares_add_up_with_leftovers_to_the_biggest_remainders() {
+        assert_eq!(share(100, &[1, 1, 1]), vec![34, 33, 33]);
+        assert_eq!(share(10, &[1, 2]), vec![3, 7]);
+        assert_eq!(share(5, &[0, 0]), vec![3, 2]);
+        assert_eq!(share(0, &[3, 1]), vec![0, 0]);
+    }
+
+    #[test]
+    fn percent_and_fixed_discounts_come_off_their_lines() {
+        let lines = vec![line(1, 1000, None), line(2, 200, None), line(3, 500, None)];
+        let adjustments = vec![
+            adjustment(
+                Some(1),
+                AdjustmentKind::Discount,
+                Some(Discount::Percent { bps: 1250 }),
+            ),
+            adjustment(
+                Some(2),
+                AdjustmentKind::Discount,
+                Some(Discount::Fixed { amount: 300 }),
+            ),
+        ];
+
+        let bill = bill(1, &restaurant(800), lines, adjustments);
+        let discounts = bill
+            .lines
+            .iter()
+            .map(|line| line.discount)
+            .collect::<Vec<_>>();
+        assert_eq!(discounts, vec![125, 200, 0]);
+        assert_eq!(bill.subtotal, 875 + 500);
+        assert_eq!(bill.tax, 110);
+        assert_eq!(bill.total, 1375 + 110);
+    }
+
+    #[test]
+    fn comps_and_voids_take_the_whole_line() {
+        let lines = vec![line(1, 1000, None), line(2, 700, None), line(3, 300, None)];
+        let adjustments = vec![
+            adjustment(Some(1), AdjustmentKind::Comp, None),
+            adjustment(Some(2), AdjustmentKind::Void, None),
+        ];
+
+        let bill = bill(1, &restaurant(0), lines, adjustments);
+        assert_eq!(bill.subtotal, 300);
+        assert_eq!(bill.lines[0].net_total, 0);
+        assert_eq!(bill.lines[1].adjustment, Some(AdjustmentKind::Void));
+    }
+
+    #[test]
+    fn check_level_discounts_come_off_before_tax() {
+        let bill = seated_bill();
+        assert_eq!(bill.subtotal, 1982);
+        assert_eq!(bill.check_discount, 198);
+        assert_eq!(bill.tax, 178);
+        assert_eq!(bill.total, 1962);
+
+        let lines = vec![line(1, 400, None)];
+        let adjustments = vec![adjustment(
+            None,
+            AdjustmentKind::Discount,
+            Some(Discount::Fixed { amount: 1000 }),
+        )];
+        let bill = super::bill(1, &restaurant(1000), lines, adjustments);
+        assert_eq!((bill.check_discount, bill.tax, bill.total), (400, 0, 0));
+    }
+
+    #[test]
+    fn even_splits_share_everything() {
+        let checks = checks(CheckSplit::Even { checks: 3 }, seated_bill());
+        assert_adds_up(&checks);
+
+        let amounts =
+            |amount: fn(&Check) -> i64| checks.checks.iter().map(amount).collect::<Vec<_>>();
+        assert_eq!(amounts(|check| check.total), vec![654, 654, 654]);
+        assert_eq!(amounts(|check| check.tax), vec![60, 59, 59]);
+        assert_eq!(amounts(|check| check.check_discount), vec![66, 66, 66]);
+    }
+
+    #[test]
+    fn order_splits_share_discount_and_tax_by_subtotal() {
+        let split = CheckSplit::ByOrder {
+            checks: 2,
+            assignments: vec![CheckAssignment {
+                order_id: 2,
+                check: 2,
+            }],
+        };
+        let checks = checks(split, seated_bill());
+        assert_adds_up(&checks);
+
+        let orders = |check: &Check| {
+            check
+                .lines
+                .iter()
+                .map(|line| line.order_id)
+                .collect::<Vec<_>>()
+        };
+        let amounts = |check: &Check| (check.subtotal, check.check_discount, check.tax);
+        assert_eq!(orders(&checks.checks[0]), vec![1, 3]);
+        assert_eq!(orders(&checks.checks[1]), vec![2]);
+        assert_eq!(amounts(&checks.checks[0]), (1432, 143, 129));
+        assert_eq!(amounts(&checks.checks[1]), (550, 55, 49));
+    }
+
+    #[test]
+    fn seat_splits_give_each_seat_a_check() {
+        let bill = seated_bill();
+        let split =
+            apply_split_change(&CheckSplit::Single, &bill.lines, SplitChange::BySeat).unwrap();
+        let checks = checks(split, bill);
+        assert_adds_up(&checks);
+
+        let totals = checks
+            .checks
+            .iter()
+            .map(|check| check.total)
+            .collect::<Vec<_>>();
+        assert_eq!(totals, vec![1418, 544]);
+    }
+
+    #[test]
+    fn checks_always_add_up_to_the_bill() {
+        for tax_rate_bps in [0, 825, 1000] {
+            for cents in [1, 7, 99, 1001, 12345] {
+                let lines = vec![
+                    line(1, cents, Some(1)),
+                    line(2, cents * 3 + 1, Some(2)),
+                    line(3, 1, Some(3)),
+                ];
+                let discount = Discount::Percent { bps: 333 };
+                let adjustments = vec![adjustment(None, AdjustmentKind::Discount, Some(discount))];
+                let bill = bill(1, &restaurant(tax_rate_bps), lines, adjustments);
+
+                for count in 1..=7 {
+                    assert_adds_up(&checks(CheckSplit::Even { checks: count }, bill.clone()));
+                }
+                let split = CheckSplit::ByOrder {
+                    checks: 3,
+                    assignments: (1..=3)
+                        .map(|order_id| CheckAssignment {
+                            order_id,
+                            check: order_id,
+                        })
+                        .collect(),
+                };
+                assert_adds_up(&checks(split, bill.clone()));
+            }
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+use shared::NewMenuItem;
+
+use crate::storage::{menu_item_problems, Storage};
+
+/// How often the catalog file is checked for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Layout of TOML (`[[items]]` tables) and JSON (`{ "items": [...] }`) catalogs.
+/// Unknown keys or columns are ignored, so a catalog can carry fields before
+/// the server makes use of them.
+#[derive(Deserialize)]
+struct CatalogFile {
+    items: Vec<NewMenuItem>,
+}
+
+/// What applying a catalog changed in the menu
+#[derive(Debug, Default)]
+pub struct CatalogChanges {
+    pub added: usize,
+    pub updated: usize,
+    pub retired: usize,
+}
+
+impl fmt::Display for CatalogChanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} updated, {} retired",
+            self.added, self.updated, self.retired
+        )
+    }
+}
+
+/// Reads, parses and validates the catalog at `path`. The format is picked
+/// from the file extension: `.toml`, `.json` or `.csv`.
+pub fn load(path: &Path) -> Result<Vec<NewMenuItem>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    parse(path, &contents)
+}
+
+fn parse(path: &Path, contents: &str) -> Result<Vec<NewMenuItem>, String> {
+    let mut items = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str::<CatalogFile>(contents)
+            .map(|file| file.items)
+            .map_err(|e| e.to_string())?,
+        Some("json") => serde_json::from_str::<CatalogFile>(contents)
+            .map(|file| file.items)
+            .map_err(|e| e.to_string())?,
+        Some("csv") => csv::Reader::from_reader(contents.as_bytes())
+            .deserialize()
+            .collect::<Result<Vec<NewMenuItem>, _>>()
+            .map_err(|e| e.to_string())?,
+        _ => return Err("unsupported catalog format, expected .toml, .json or .csv".to_string()),
+    };
+
+    items
+        .iter_mut()
+        .for_each(|item| item.name = item.name.trim().to_string());
+
+    validate(&items)?;
+    Ok(items)
+}
+
+/// Checks the catalog as a whole, reporting every problem found rather than
+/// stopping at the first one.
+fn validate(items: &[NewMenuItem]) -> Result<(), String> {
+    let mut seen = HashSet::new();
+
+    let problems = items
+        .iter()
+        .flat_map(|item| {
+            let mut problems = menu_item_problems(item);
+            if !item.name.is_empty() && !seen.insert(item.name.to_lowercase()) {
+                problems.push(format!("'{}' is listed more than once", item.name));
+            }
+            problems
+        })
+        .collect::<Vec<_>>();
+
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(problems.join("; ")),
+    }
+}
+
+/// Polls the catalog file and applies each new valid version of it to the
+/// restaurant's menu. An invalid edit is logged and skipped, keeping the
+/// current menu.
+pub async fn watch<S: Storage>(path: PathBuf, storage: S, restaurant_id: i64) {
+    let mut last_modified = modified(&path).await;
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let modified = modified(&path).await;
+        if modified == last_modified {
+            continue;
+        }
+        last_modified = modified;
+
+        let items = match tokio::fs::read_to_string(&path).await {
+            Ok(contents) => parse(&path, &contents),
+            Err(e) => Err(e.to_string()),
+        };
+
+        match items {
+            Ok(items) => match storage.apply_catalog(restaurant_id, items).await {
+                Ok(changes) => println!("Reloaded menu catalog: {changes}"),
+                Err(e) => println!("Failed to apply menu catalog: {e:?}"),
+            },
+            Err(e) => println!("Ignoring invalid menu catalog {}: {e}", path.display()),
+        }
+    }
+}
+
+async fn modified(path: &Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path).await.ok()?.modified().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use shared::Station;
+
+    use super::*;
+
+    fn parse_as(extension: &str, contents: &str) -> Result<Vec<NewMenuItem>, String> {
+        parse(Path::new(&format!("menu.{extension}")), contents)
+    }
+
+    #[test]
+    fn toml_catalogs_are_read() {
+        let items = parse_as(
+            "toml",
+            r#"
+            [[items]]
+            name = "  Fries "
+            prep_min_m = 1.0
+            prep_max_m = 2.0
+            station = "fryer"
+            price = 250
+            extra = "ignored"
+
+            [[items]]
+            name = "Cola"
+            prep_min_m = 0.0
+            prep_max_m = 0.5
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].name, "Fries");
+        assert_eq!(items[0].station, Station::Fryer);
+        assert_eq!(items[1].station, Station::Grill);
+        assert_eq!(items[1].price, 0);
+    }
+
+    #[test]
+    fn json_catalogs_are_read() {
+        let items = parse_as(
+            "json",
+            r#"{"items": [{"name": "Shake", "prep_min_m": 1, "prep_max_m": 3,
+                "station": "drinks", "modifiers": [{"name": "Malt", "price_delta": 40}]}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(items[0].station, Station::Drinks);
+        assert_eq!(items[0].modifiers[0].name, "Malt");
+        assert_eq!(items[0].modifiers[0].price_delta, 40);
+    }
+
+    #[test]
+    fn csv_catalogs_are_read() {
+        let items = parse_as(
+            "csv",
+            "name,prep_min_m,prep_max_m,station,price\nSundae,1,2,dessert,300\nBurger,3,5,grill,600\n",
+        )
+        .unwrap();
+
+        let names = items
+            .iter()
+            .map(|item| item.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["Sundae", "Burger"]);
+        assert_eq!(items[0].station, Station::Dessert);
+    }
+
+    #[test]
+    fn malformed_csv_rows_are_rejected() {
+        let missing_column = "name,prep_min_m,prep_max_m\nFries,1\n";
+        assert!(parse_as("csv", missing_column).is_err());
+
+        let not_a_number = "name,prep_min_m,prep_max_m\nFries,quick,2\n";
+        assert!(parse_as("csv", not_a_number).is_err());
+
+        let unknown_station = "name,prep_min_m,prep_max_m,station\nFries,1,2,oven\n";
+        assert!(parse_as("csv", unknown_station).is_err());
+    }
+
+    #[test]
+    fn malformed_files_and_unknown_formats_are_rejected() {
+        assert!(parse_as("toml", "[[items]]\nname = ").is_err());
+        assert!(parse_as("json", r#"{"items": [{"name": "Fries"}]}"#).is_err());
+        assert!(parse_as("yaml", "items: []")
+            .unwrap_err()
+            .contains("unsupported catalog format"));
+    }
+
+    #[test]
+    fn items_listed_twice_are_rejected_whatever_their_case() {
+        let error = parse_as(
+            "csv",
+            "name,prep_min_m,prep_max_m\nFries,1,2\n fries ,1,2\nCola,0,1\n",
+        )
+        .unwrap_err();
+
+        assert_eq!(error, "'fries' is listed more than once");
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let error = parse_as(
+            "csv",
+            "name,prep_min_m,prep_max_m,price\nFries,3,2,100\n,1,2,100\nCola,1,2,-5\n",
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            error,
+            "'Fries' has prep_min_m above prep_max_m; an item has an empty name; \
+            'Cola' has an invalid price"
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use shared::{ArchivedOrder, CloseOutSummary, ItemCount, OrderStatus, Restaurant};
+
+use crate::events;
+use crate::storage::Storage;
+use crate::timing;
+
+/// How often the schedule looks for new restaurants and time zone changes,
+/// even when no business day is due to end
+const RECHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// The business day `at` falls in, in the given time zone. Each business day
+/// starts at `day_start`, so orders placed after midnight still count towards
+/// the evening before.
+pub fn business_date(at: DateTime<Utc>, time_zone: Tz, day_start: NaiveTime) -> NaiveDate {
+    (at.with_timezone(&time_zone).naive_local() - (day_start - NaiveTime::MIN)).date()
+}
+
+/// The business day the restaurant is in right now
+pub fn current_business_date(restaurant: &Restaurant, day_start: NaiveTime) -> NaiveDate {
+    business_date(
+        Utc::now(),
+        timing::restaurant_time_zone(restaurant),
+        day_start,
+    )
+}
+
+/// When the business day after `date` starts in the given time zone
+fn next_day_start(date: NaiveDate, time_zone: Tz, day_start: NaiveTime) -> DateTime<Utc> {
+    let start = (date + Days::new(1)).and_time(day_start);
+
+    // A start skipped over by a daylight saving change happens an hour later
+    time_zone
+        .from_local_datetime(&start)
+        .earliest()
+        .or_else(|| {
+            time_zone
+                .from_local_datetime(&(start + chrono::Duration::hours(1)))
+                .earliest()
+        })
+        .unwrap()
+        .to_utc()
+}
+
+/// Sums up the orders archived by a close-out
+pub fn summarize(
+    business_date: NaiveDate,
+    orders: &[ArchivedOrder],
+    unsettled_parties: Vec<i64>,
+) -> CloseOutSummary {
+    let mut items_served = BTreeMap::<i64, ItemCount>::new();
+    orders
+        .iter()
+        .filter(|order| order.removal.is_none() && order.status != OrderStatus::Cancelled)
+        .for_each(|order| {
+            items_served
+                .entry(order.item_id)
+                .or_insert_with(|| ItemCount {
+                    item_id: order.item_id,
+                    name: order.item_name.clone(),
+                    count: 0,
+                })
+                .count += order.quantity as usize;
+        });
+
+    CloseOutSummary {
+        business_date: business_date.to_string(),
+        closed_at: events::now(),
+        tables: orders
+            .iter()
+            .map(|order| order.table_id)
+            .collect::<HashSet<_>>()
+            .len(),
+        orders: orders.len(),
+        removed_orders: orders
+            .iter()
+            .filter(|order| order.removal.is_some())
+            .count(),
+        items_served: items_served.into_values().collect(),
+        unsettled_parties,
+    }
+}
+
+/// Closes out each restaurant's business day as soon as its next one starts,
+/// going by the restaurant's own time zone
+pub async fn schedule<S: Storage>(storage: S, day_start: NaiveTime) {
+    // The business day each restaurant was in when last checked
+    let mut open_days = HashMap::<i64, NaiveDate>::new();
+
+    loop {
+        let restaurants = match storage.get_restaurants().await {
+            Ok(restaurants) => restaurants,
+            Err(e) => {
+                println!("Failed to look up the restaurants to close out: {e:?}");
+                tokio::time::sleep(RECHECK_INTERVAL).await;
+                continue;
+            }
+        };
+
+        let now = Utc::now();
+        let checked_at = tokio::time::Instant::now();
+        let mut wait = RECHECK_INTERVAL;
+        for restaurant in restaurants {
+            let id = restaurant.id;
+            let time_zone = timing::restaurant_time_zone(&restaurant);
+            let today = business_date(now, time_zone, day_start);
+            let until_tomorrow = next_day_start(today, time_zone, day_start) - now;
+            wait = wait.min(until_tomorrow.to_std().unwrap_or_default());
+
+            let Some(date) = open_days.insert(id, today).filter(|&date| date < today) else {
+                continue;
+            };
+            match storage.close_out(id, date).await {
+                Ok(summary) => println!(
+                    "Closed out {date} for restaurant {id}: archived {} orders from {} tables, \
+                    left {} unsettled parties open",
+                    summary.orders,
+                    summary.tables,
+                    summary.unsettled_parties.len()
+                ),
+                Err(e) => println!("Failed to close out {date} for restaurant {id}: {e:?}"),
+            }
+        }
+
+        tokio::time::sleep_until(checked_at + wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(utc: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(utc).unwrap().to_utc()
+    }
+
+    fn date(text: &str) -> NaiveDate {
+        text.parse().unwrap()
+    }
+
+    const FOUR_AM: NaiveTime = NaiveTime::from_hms_opt(4, 0, 0).unwrap();
+
+    #[test]
+    fn business_dates_follow_the_restaurants_time_zone() {
+        // 02:30 in London is still the evening before, while Tokyo is well into the next day
+        let now = at("2024-06-14T01:30:00Z");
+
+        assert_eq!(
+            business_date(now, chrono_tz::Europe::London, FOUR_AM),
+            date("2024-06-13")
+        );
+        assert_eq!(
+            business_date(now, chrono_tz::Asia::Tokyo, FOUR_AM),
+            date("2024-06-14")
+        );
+    }
+
+    #[test]
+    fn each_time_zone_starts_its_next_day_at_its_own_local_time() {
+        let today = date("2024-06-13");
+
+        assert_eq!(
+            next_day_start(today, chrono_tz::Europe::London, FOUR_AM),
+            at("2024-06-14T03:00:00Z")
+        );
+        assert_eq!(
+            next_day_start(today, chrono_tz::America::New_York, FOUR_AM),
+            at("2024-06-14T08:00:00Z")
+        );
+    }
+
+    #[test]
+    fn a_day_start_skipped_by_daylight_saving_happens_an_hour_later() {
+        let two_am = NaiveTime::from_hms_opt(2, 0, 0).unwrap();
+
+        // New York skipped from 02:00 to 03:00 on 10 March 2024
+        assert_eq!(
+            next_day_start(date("2024-03-09"), chrono_tz::America::New_York, two_am),
+            at("2024-03-10T07:00:00Z")
+        );
+    }
+}
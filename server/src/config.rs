@@ -0,0 +1,99 @@
+use std::path::PathBuf;
+
+use chrono::{Duration, NaiveTime};
+
+use crate::processor::MockOutcome;
+
+/// Settings chosen when the server starts, read from environment variables
+/// so they can be changed per deployment without a recompile.
+pub struct Config {
+    /// Which storage backend to run against.
+    pub storage: StorageBackend,
+    /// Path to the SQLite database file. Created on first boot if missing.
+    pub db_path: String,
+    /// Number of pooled SQLite connections, i.e. how many queries can run at once.
+    pub db_pool_size: usize,
+    /// Optional TOML, JSON or CSV menu catalog. When set, it is applied at
+    /// startup and watched for changes instead of seeding the default menu.
+    pub menu_catalog: Option<PathBuf>,
+    /// Restaurant whose menu the catalog file manages.
+    pub menu_catalog_restaurant: i64,
+    /// How long after removing an order it can still be restored.
+    pub undo_window: Duration,
+    /// Directory `POST /admin/backup` writes backups to. Created if missing.
+    pub backup_dir: PathBuf,
+    /// Time each business day starts at, in each restaurant's own time zone.
+    /// Orders placed before it count towards the previous day.
+    pub business_day_start: NaiveTime,
+    /// Whether to close out each business day automatically when the next starts.
+    pub scheduled_close_out: bool,
+    /// Which payment processor takes card payments.
+    pub payment_processor: ProcessorBackend,
+    /// How long to wait for the payment processor before giving up on it.
+    pub processor_timeout: std::time::Duration,
+    /// How the mock processor answers its first calls, in order, e.g.
+    /// `approve,decline,timeout`. Every call after those is approved.
+    pub mock_processor_script: Vec<MockOutcome>,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let storage = match env_or("PAIDY_STORAGE", "sqlite").as_str() {
+            "sqlite" => StorageBackend::Sqlite,
+            "memory" => StorageBackend::Memory,
+            other => panic!("Unknown PAIDY_STORAGE backend: {other}"),
+        };
+        let payment_processor = match env_or("PAIDY_PAYMENT_PROCESSOR", "mock").as_str() {
+            "mock" => ProcessorBackend::Mock,
+            other => panic!("Unknown PAIDY_PAYMENT_PROCESSOR: {other}"),
+        };
+
+        Self {
+            storage,
+            db_path: env_or("PAIDY_DB_PATH", "restaurant.db"),
+            db_pool_size: env_or("PAIDY_DB_POOL_SIZE", "8").parse().unwrap(),
+            menu_catalog: std::env::var("PAIDY_MENU_CATALOG").ok().map(PathBuf::from),
+            menu_catalog_restaurant: env_or("PAIDY_MENU_CATALOG_RESTAURANT", "1")
+                .parse()
+                .unwrap(),
+            undo_window: Duration::seconds(
+                env_or("PAIDY_UNDO_WINDOW_SECS", "300").parse().unwrap(),
+            ),
+            backup_dir: PathBuf::from(env_or("PAIDY_BACKUP_DIR", "backups")),
+            business_day_start: NaiveTime::parse_from_str(
+                &env_or("PAIDY_BUSINESS_DAY_START", "04:00"),
+                "%H:%M",
+            )
+            .unwrap(),
+            scheduled_close_out: env_or("PAIDY_SCHEDULED_CLOSE_OUT", "true").parse().unwrap(),
+            payment_processor,
+            processor_timeout: std::time::Duration::from_secs(
+                env_or("PAIDY_PROCESSOR_TIMEOUT_SECS", "30")
+                    .parse()
+                    .unwrap(),
+            ),
+            mock_processor_script: env_or("PAIDY_MOCK_PROCESSOR_SCRIPT", "")
+                .split(',')
+                .map(str::trim)
+                .filter(|outcome| !outcome.is_empty())
+                .map(|outcome| outcome.parse().unwrap())
+                .collect(),
+        }
+    }
+}
+
+pub enum StorageBackend {
+    /// File-backed SQLite database at `Config::db_path`.
+    Sqlite,
+    /// Plain in-process collections. Data is lost on restart.
+    Memory,
+}
+
+pub enum ProcessorBackend {
+    /// In-process processor answering as `Config::mock_processor_script` says.
+    Mock,
+}
+
+fn env_or(key: &str, default: &str) -> String {
+    std::env::var(key).unwrap_or_else(|_| default.to_string())
+}
@@ -1,292 +1,3046 @@
-use std::{collections::HashMap, sync::Arc};
-
-use axum::http::StatusCode;
-use chrono::Duration;
-use rusqlite::Connection;
-use shared::{MenuItem, OrderItemsRequest, TableOrder};
-use tokio::sync::Mutex;
-
-use crate::HttpError;
-
-const INIT_DB_QUERY: &str = r#"
-    BEGIN;
-    CREATE TABLE menu (
-        id INTEGER PRIMARY KEY,
-        name TEXT NOT NULL,
-        prep_min_m REAL NOT NULL,
-        prep_max_m REAL NOT NULL
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use chrono::{Duration, NaiveDate, Utc};
+use rusqlite::backup::{Backup, Progress, StepResult};
+use rusqlite::types::Type;
+use rusqlite::{
+    params, Connection, DatabaseName, ErrorCode, OpenFlags, OptionalExtension, TransactionBehavior,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use shared::{
+    Adjustment, ArchivedOrder, Bill, BillLine, CheckSplit, ClaimLineRequest, CloseOutSummary,
+    DataExport, ImportReport, LiveEvent, Manager, Menu, MenuItem, MenuItemPatch, NewAdjustment,
+    NewManager, NewMenuItem, NewPayment, NewRestaurant, NewTable, OrderHistoryEntry,
+    OrderItemsRequest, OrderStatus, Party, PartyDetails, Payment, PaymentStatus, RebuildReport,
+    Removal, RemoveOrderRequest, Restaurant, RestaurantPatch, SeatPartyRequest, Station,
+    StationLine, StatusChange, Table, TableHistory, TableOrder, TablePatch, Tender,
+};
+use tokio::sync::{broadcast, Notify};
+
+use crate::billing;
+use crate::catalog::CatalogChanges;
+use crate::closeout;
+use crate::events::{self, OrderChange, OrderEvent, ProjectedOrder};
+use crate::kitchen::{self, Kitchen};
+use crate::live::{self, LiveFeed, LiveUpdate};
+use crate::migrations;
+use crate::pool::Pool;
+use crate::storage::{
+    apply_payment_change, apply_split_change, bill_line, check_adjustable, check_approval,
+    check_claimable, check_completable, check_party_open, check_payable, check_restorable,
+    check_settled, check_table_free, check_table_open, check_transition, check_voidable,
+    default_modifiers, default_tables, hash_pin, import_table_registry, line_not_found,
+    manager_not_found, no_open_party, party_not_found, party_required, patch_menu_item,
+    patch_restaurant, patch_table, payment_not_found, pin_hash_outdated, pin_matches,
+    restaurant_not_found, table_not_found, validate_adjustment, validate_claim, validate_import,
+    validate_manager, validate_menu_item, validate_order, validate_party, validate_payment,
+    validate_removal, validate_restaurant, validate_table, ManagerRow, MenuItemRow, PaymentChange,
+    SplitChange, Storage, StorageError, StorageResult, DEFAULT_KITCHEN_SLOTS,
+    DEFAULT_RESTAURANT_ID, ITEMS_MCDONALDS,
+};
+use crate::timing;
+
+/// Adds a menu item numbered after the restaurant's highest item id,
+/// returning its id
+const INSERT_MENU_ITEM: &str =
+    "INSERT INTO menu (restaurant_id, id, name, prep_min_m, prep_max_m, station, modifiers, \
+    price) SELECT ?1, COALESCE(MAX(id), 0) + 1, ?2, ?3, ?4, ?5, ?6, ?7 FROM menu \
+    WHERE restaurant_id == ?1 RETURNING id;";
+
+/// Registers a table numbered after the restaurant's highest table id,
+/// returning its id
+const INSERT_TABLE: &str = "INSERT INTO tables (restaurant_id, id, label, section, seats) \
+    SELECT ?1, COALESCE(MAX(id), 0) + 1, ?2, ?3, ?4 FROM tables WHERE restaurant_id == ?1 \
+    RETURNING id;";
+
+/// Adds a manager numbered after the restaurant's highest manager id,
+/// returning their id
+const INSERT_MANAGER: &str = "INSERT INTO managers (restaurant_id, id, name, pin_hash) \
+    SELECT ?1, COALESCE(MAX(id), 0) + 1, ?2, ?3 FROM managers WHERE restaurant_id == ?1 \
+    RETURNING id;";
+
+/// How long a backup waits before retrying when the database is locked
+const BACKUP_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Storage backed by a pool of connections to a SQLite database file
+#[derive(Clone)]
+pub struct SqliteStorage {
+    pool: Arc<Pool>,
+    live: LiveFeed,
+    tail: Arc<LogTail>,
+}
+
+/// Follows the order event log and menu versions on behalf of the live feed.
+/// Every write wakes it and it publishes whatever changed since it last
+/// looked, so changes made by any query, in any order, reach followers once
+/// they are committed. It only starts once someone first follows the feed.
+#[derive(Default)]
+struct LogTail {
+    wake: Notify,
+    started: OnceLock<()>,
+}
+
+/// How far the tail has published
+#[derive(Clone)]
+struct TailCursor {
+    /// The last event published
+    seq: i64,
+    /// Restaurant id -> menu version last published
+    menu_versions: HashMap<i64, i64>,
+}
+
+impl SqliteStorage {
+    /// Opens and migrates the database at `path`, then opens `pool_size`
+    /// connections to it. See `init_db` for `seed_menu`.
+    pub fn open(path: &str, pool_size: usize, seed_menu: bool) -> Self {
+        let mut connections = vec![init_db(path, seed_menu)];
+        connections.extend((1..pool_size).map(|_| Pool::connect(path).unwrap()));
+        println!("Opened {} pooled connections.", connections.len());
+
+        Self {
+            pool: Arc::new(Pool::new(path, connections)),
+            live: LiveFeed::new(),
+            tail: Arc::default(),
+        }
+    }
+
+    /// Runs a query which changes orders or menus, then wakes the live feed's
+    /// tail to publish the changes
+    async fn write<T, F>(&self, query: F) -> StorageResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> StorageResult<T> + Send + 'static,
+    {
+        let result = self.pool.run(query).await;
+        self.tail.wake.notify_one();
+        result
+    }
+}
+
+/// Publishes every change committed after it starts, for as long as the
+/// server runs
+async fn follow_log(pool: Arc<Pool>, live: LiveFeed, tail: Arc<LogTail>) {
+    let mut cursor = match pool.run(|conn| query_tail_cursor(conn)).await {
+        Ok(cursor) => cursor,
+        Err(e) => {
+            println!("Failed to start following the order event log: {e:?}");
+            return;
+        }
+    };
+
+    loop {
+        tail.wake.notified().await;
+        let (from, live) = (cursor.clone(), live.clone());
+        match pool.run(move |conn| publish_since(conn, from, &live)).await {
+            Ok(next) => cursor = next,
+            Err(e) => println!("Failed to follow the order event log: {e:?}"),
+        }
+    }
+}
+
+/// Opens the database file at `path`, creating it if needed, and migrates it
+/// to the latest schema. A brand new database gets the default restaurant's
+/// tables, and with `seed_menu` its menu too, in the same transaction that
+/// builds it.
+pub fn init_db(path: &str, seed_menu: bool) -> Connection {
+    let mut conn = Pool::connect(path).unwrap();
+    println!("Opened database at {path}");
+
+    let previous_version = migrations::run(&mut conn, |conn| {
+        fill_tables(conn)?;
+        println!("Filled tables successfully.");
+        if seed_menu {
+            fill_menu(conn)?;
+            println!("Filled menu successfully.");
+        }
+        Ok(())
+    })
+    .unwrap();
+    println!(
+        "Schema at version {} (was {previous_version}).",
+        migrations::latest_version()
     );
 
-    CREATE TABLE orders (
-        id INTEGER PRIMARY KEY,
-        table_id INTEGER NOT NULL,
-        item_id INTEGER NOT NULL,
-        ready_at TEXT NOT NULL,
-        FOREIGN KEY (item_id) REFERENCES menu (id)
-    );
-    COMMIT;
-"#;
-
-const ITEMS_MCDONALDS: &str = r#"
-    INSERT INTO menu (name, prep_min_m, prep_max_m) VALUES
-        ('Big Mac', 6.0, 12.0),
-        ('Quarter Pounder with Cheese', 5.0, 8.0),
-        ('Cheeseburger', 5.0, 8.0),
-        ('McChicken', 8.0, 12.0),
-        ('Filet-O-Fish', 5.0, 8.0),
-        ('Chicken McNuggets (10 pieces)', 6.0, 10.0),
-        ('French Fries (Medium)', 5.0, 7.0),
-        ('French Fries (Large)', 5.0, 7.0),
-        ('McFlurry', 5.0, 15.0),
-        ('Apple Pie', 5.0, 9.0),
-        ('Egg McMuffin', 5.0, 7.0),
-        ('Sausage McMuffin', 5.0, 7.0),
-        ('Bacon, Egg & Cheese Biscuit', 5.0, 7.0),
-        ('Iced Coffee', 5.0, 6.0),
-        ('McCafe Latte', 5.0, 6.0);
-"#;
-
-/// Initializes a database and calls the initialization query
-/// which fills it with some temporary data
-pub fn init_db() -> Connection {
-    // Note: In a real situation, would use an actual database somewhere
-    // and persist data across sessions
-    let conn = Connection::open_in_memory().unwrap();
-    println!("Initialized connection to DB");
-
-    conn.execute_batch(INIT_DB_QUERY).unwrap();
-    println!("Setup tables successfully.");
-
-    conn.execute(ITEMS_MCDONALDS, ()).unwrap();
-    println!("Filled menu successfully.");
+    conn
+}
+
+fn fill_tables(conn: &Connection) -> rusqlite::Result<()> {
+    let mut insert = conn.prepare(INSERT_TABLE)?;
+    for table in default_tables() {
+        insert.query_row(
+            (
+                DEFAULT_RESTAURANT_ID,
+                table.label,
+                table.section,
+                table.seats,
+            ),
+            |_| Ok(()),
+        )?;
+    }
+    Ok(())
+}
+
+fn fill_menu(conn: &Connection) -> rusqlite::Result<()> {
+    let mut insert = conn.prepare(INSERT_MENU_ITEM)?;
+    for &(name, prep_min_m, prep_max_m, station, price) in ITEMS_MCDONALDS {
+        let station = to_sql_enum(&station);
+        let modifiers = to_sql_json(&default_modifiers(name));
+        insert.query_row(
+            (
+                DEFAULT_RESTAURANT_ID,
+                name,
+                prep_min_m,
+                prep_max_m,
+                station,
+                modifiers,
+                price,
+            ),
+            |_| Ok(()),
+        )?;
+    }
+    Ok(())
+}
+
+fn handle_query_error(error: rusqlite::Error) -> StorageError {
+    use rusqlite::Error::*;
+
+    let body = error.to_string();
+    println!("Query Error: {body}");
+
+    match error {
+        QueryReturnedNoRows => StorageError::NotFound(body),
+        SqliteFailure(e, _) if e.code == ErrorCode::ConstraintViolation => {
+            StorageError::Conflict(body)
+        }
+        IntegralValueOutOfRange(_, _)
+        | NulError(_)
+        | InvalidParameterName(_)
+        | MultipleStatement
+        | InvalidParameterCount(_, _)
+        | SqlInputError { .. } => StorageError::BadRequest(body),
+        _ => StorageError::Internal(body),
+    }
+}
+
+impl Storage for SqliteStorage {
+    async fn get_restaurants(&self) -> StorageResult<Vec<Restaurant>> {
+        const QUERY: &str = "SELECT id, name, time_zone, kitchen_slots, currency, tax_rate_bps \
+            FROM restaurants ORDER BY id;";
+
+        self.pool
+            .run(|conn| {
+                conn.prepare(QUERY)
+                    .map_err(handle_query_error)?
+                    .query_map([], restaurant_from_row)
+                    .map_err(handle_query_error)?
+                    .collect::<Result<_, _>>()
+                    .map_err(handle_query_error)
+            })
+            .await
+    }
+
+    async fn get_restaurant(&self, restaurant_id: i64) -> StorageResult<Restaurant> {
+        self.pool
+            .run(move |conn| query_restaurant(conn, restaurant_id))
+            .await
+    }
+
+    async fn update_restaurant(
+        &self,
+        restaurant_id: i64,
+        patch: RestaurantPatch,
+    ) -> StorageResult<Restaurant> {
+        const UPDATE: &str = "UPDATE restaurants SET name = ?2, time_zone = ?3, \
+            kitchen_slots = ?4, tax_rate_bps = ?5 WHERE id == ?1;";
+
+        self.write(move |conn| {
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(handle_query_error)?;
+
+            let restaurant = query_restaurant(&tx, restaurant_id)?;
+            let fields = validate_restaurant(patch_restaurant(&restaurant, patch))?;
+            let time_zone = fields.time_zone.unwrap_or_default();
+            let kitchen_slots = fields.kitchen_slots.unwrap_or(DEFAULT_KITCHEN_SLOTS);
+            let tax_rate_bps = fields.tax_rate_bps.unwrap_or_default();
+            tx.execute(
+                UPDATE,
+                (
+                    restaurant_id,
+                    &fields.name,
+                    &time_zone,
+                    kitchen_slots,
+                    tax_rate_bps,
+                ),
+            )
+            .map_err(handle_query_error)?;
+            if kitchen_slots != restaurant.kitchen_slots {
+                reschedule(&tx, restaurant_id)?;
+            }
+
+            tx.commit().map_err(handle_query_error)?;
+            Ok(Restaurant {
+                id: restaurant_id,
+                name: fields.name,
+                time_zone,
+                kitchen_slots,
+                currency: restaurant.currency,
+                tax_rate_bps,
+            })
+        })
+        .await
+    }
+
+    async fn create_restaurant(&self, restaurant: NewRestaurant) -> StorageResult<Restaurant> {
+        const INSERT: &str = "INSERT INTO restaurants (name, time_zone, kitchen_slots, currency, \
+            tax_rate_bps) VALUES (?1, ?2, ?3, ?4, ?5);";
+        const MENU_VERSION: &str =
+            "INSERT INTO menu_version (restaurant_id, version) VALUES (?1, 1);";
+
+        let restaurant = validate_restaurant(restaurant)?;
+
+        self.pool
+            .run(move |conn| {
+                let tx = conn
+                    .transaction_with_behavior(TransactionBehavior::Immediate)
+                    .map_err(handle_query_error)?;
+
+                let time_zone = restaurant.time_zone.unwrap_or_default();
+                let kitchen_slots = restaurant.kitchen_slots.unwrap_or(DEFAULT_KITCHEN_SLOTS);
+                let currency = restaurant.currency.unwrap_or_default();
+                let tax_rate_bps = restaurant.tax_rate_bps.unwrap_or_default();
+                tx.execute(
+                    INSERT,
+                    (
+                        &restaurant.name,
+                        &time_zone,
+                        kitchen_slots,
+                        &currency,
+                        tax_rate_bps,
+                    ),
+                )
+                .map_err(handle_query_error)?;
+                let id = tx.last_insert_rowid();
+                tx.execute(MENU_VERSION, [id]).map_err(handle_query_error)?;
+
+                tx.commit().map_err(handle_query_error)?;
+                Ok(Restaurant {
+                    id,
+                    name: restaurant.name,
+                    time_zone,
+                    kitchen_slots,
+                    currency,
+                    tax_rate_bps,
+                })
+            })
+            .await
+    }
+
+    async fn get_menu(&self, restaurant_id: i64) -> StorageResult<Menu> {
+        self.pool
+            .run(move |conn| {
+                // Read both in one transaction so the version matches the items
+                let tx = conn.transaction().map_err(handle_query_error)?;
+                let version = query_menu_version(&tx, restaurant_id)?;
+                let items = query_menu(&tx, restaurant_id)?;
+                Ok(Menu { version, items })
+            })
+            .await
+    }
+
+    async fn create_menu_item(
+        &self,
+        restaurant_id: i64,
+        item: NewMenuItem,
+    ) -> StorageResult<MenuItem> {
+        let item = NewMenuItem {
+            name: item.name.trim().to_string(),
+            ..item
+        };
+        validate_menu_item(&item)?;
+
+        self.write(move |conn| {
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(handle_query_error)?;
+
+            let params = (
+                restaurant_id,
+                &item.name,
+                item.prep_min_m,
+                item.prep_max_m,
+                to_sql_enum(&item.station),
+                to_sql_json(&item.modifiers),
+                item.price,
+            );
+            let id = tx
+                .query_row(INSERT_MENU_ITEM, params, |row| row.get(0))
+                .map_err(handle_query_error)?;
+            bump_menu_version(&tx, restaurant_id)?;
+
+            tx.commit().map_err(handle_query_error)?;
+            Ok(MenuItem {
+                id,
+                name: item.name,
+                prep_min_m: item.prep_min_m,
+                prep_max_m: item.prep_max_m,
+                station: item.station,
+                modifiers: item.modifiers,
+                price: item.price,
+            })
+        })
+        .await
+    }
+
+    async fn update_menu_item(
+        &self,
+        restaurant_id: i64,
+        item_id: i64,
+        patch: MenuItemPatch,
+    ) -> StorageResult<MenuItem> {
+        const QUERY: &str = "UPDATE menu SET name = ?3, prep_min_m = ?4, prep_max_m = ?5, \
+            station = ?6, modifiers = ?7, price = ?8 WHERE restaurant_id == ?1 AND id == ?2;";
+
+        self.write(move |conn| {
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(handle_query_error)?;
+
+            let current = query_menu_item(&tx, restaurant_id, item_id)?;
+            let item = patch_menu_item(&current, patch);
+            validate_menu_item(&item)?;
+
+            tx.execute(
+                QUERY,
+                (
+                    restaurant_id,
+                    item_id,
+                    &item.name,
+                    item.prep_min_m,
+                    item.prep_max_m,
+                    to_sql_enum(&item.station),
+                    to_sql_json(&item.modifiers),
+                    item.price,
+                ),
+            )
+            .map_err(handle_query_error)?;
+            bump_menu_version(&tx, restaurant_id)?;
+
+            tx.commit().map_err(handle_query_error)?;
+            Ok(MenuItem {
+                id: item_id,
+                name: item.name,
+                prep_min_m: item.prep_min_m,
+                prep_max_m: item.prep_max_m,
+                station: item.station,
+                modifiers: item.modifiers,
+                price: item.price,
+            })
+        })
+        .await
+    }
+
+    async fn delete_menu_item(
+        &self,
+        restaurant_id: i64,
+        item_id: i64,
+        retire: bool,
+    ) -> StorageResult<()> {
+        const REFERENCED: &str = "SELECT EXISTS (SELECT 1 FROM orders \
+            WHERE restaurant_id == ?1 AND item_id == ?2);";
+        const DELETE: &str = "DELETE FROM menu WHERE restaurant_id == ?1 AND id == ?2;";
+        const RETIRE: &str = "UPDATE menu SET active = 0 WHERE restaurant_id == ?1 AND id == ?2;";
+
+        self.write(move |conn| {
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(handle_query_error)?;
+
+            query_menu_item(&tx, restaurant_id, item_id)?;
+            let referenced: bool = tx
+                .query_row(REFERENCED, [restaurant_id, item_id], |row| row.get(0))
+                .map_err(handle_query_error)?;
+
+            match (referenced, retire) {
+                (false, _) => tx.execute(DELETE, [restaurant_id, item_id]),
+                (true, true) => tx.execute(RETIRE, [restaurant_id, item_id]),
+                (true, false) => {
+                    return Err(StorageError::Conflict(
+                        "menu item is referenced by orders, retire it instead".to_string(),
+                    ))
+                }
+            }
+            .map_err(handle_query_error)?;
+            bump_menu_version(&tx, restaurant_id)?;
+
+            tx.commit().map_err(handle_query_error)
+        })
+        .await
+    }
+
+    async fn apply_catalog(
+        &self,
+        restaurant_id: i64,
+        items: Vec<NewMenuItem>,
+    ) -> StorageResult<CatalogChanges> {
+        const SELECT: &str = "SELECT id, name, prep_min_m, prep_max_m, station, modifiers, price, \
+            active FROM menu WHERE restaurant_id == ?1;";
+        const UPDATE: &str = "UPDATE menu SET prep_min_m = ?3, prep_max_m = ?4, station = ?5, \
+            modifiers = ?6, price = ?7, active = 1 WHERE restaurant_id == ?1 AND id == ?2;";
+        const RETIRE: &str = "UPDATE menu SET active = 0 WHERE restaurant_id == ?1 AND id == ?2;";
+
+        self.write(move |conn| {
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(handle_query_error)?;
+
+            // Name -> (item, active)
+            let existing = tx
+                .prepare(SELECT)
+                .map_err(handle_query_error)?
+                .query_map([restaurant_id], |row| {
+                    let item = menu_item_from_row(row)?;
+                    Ok((item.name.clone(), (item, row.get::<_, bool>(7)?)))
+                })
+                .map_err(handle_query_error)?
+                .collect::<Result<HashMap<_, _>, _>>()
+                .map_err(handle_query_error)?;
+
+            let mut changes = CatalogChanges::default();
+
+            for item in &items {
+                match existing.get(&item.name) {
+                    None => {
+                        let params = (
+                            restaurant_id,
+                            &item.name,
+                            item.prep_min_m,
+                            item.prep_max_m,
+                            to_sql_enum(&item.station),
+                            to_sql_json(&item.modifiers),
+                            item.price,
+                        );
+                        tx.query_row(INSERT_MENU_ITEM, params, |_| Ok(()))
+                            .map_err(handle_query_error)?;
+                        changes.added += 1;
+                    }
+                    Some((current, active))
+                        if !active
+                            || current.prep_min_m != item.prep_min_m
+                            || current.prep_max_m != item.prep_max_m
+                            || current.station != item.station
+                            || current.modifiers != item.modifiers
+                            || current.price != item.price =>
+                    {
+                        let params = (
+                            restaurant_id,
+                            current.id,
+                            item.prep_min_m,
+                            item.prep_max_m,
+                            to_sql_enum(&item.station),
+                            to_sql_json(&item.modifiers),
+                            item.price,
+                        );
+                        tx.execute(UPDATE, params).map_err(handle_query_error)?;
+                        changes.updated += 1;
+                    }
+                    Some(_) => (),
+                }
+            }
+
+            let names = items.iter().map(|item| &item.name).collect::<HashSet<_>>();
+            for (name, (current, active)) in &existing {
+                if *active && !names.contains(name) {
+                    tx.execute(RETIRE, [restaurant_id, current.id])
+                        .map_err(handle_query_error)?;
+                    changes.retired += 1;
+                }
+            }
+
+            if changes.added + changes.updated + changes.retired > 0 {
+                bump_menu_version(&tx, restaurant_id)?;
+            }
+
+            tx.commit().map_err(handle_query_error)?;
+            Ok(changes)
+        })
+        .await
+    }
+
+    async fn get_tables(&self, restaurant_id: i64) -> StorageResult<Vec<Table>> {
+        const QUERY: &str = "SELECT id, label, section, seats, active FROM tables \
+            WHERE restaurant_id == ?1 ORDER BY id;";
+
+        self.pool
+            .run(move |conn| {
+                conn.prepare(QUERY)
+                    .map_err(handle_query_error)?
+                    .query_map([restaurant_id], table_from_row)
+                    .map_err(handle_query_error)?
+                    .collect::<Result<_, _>>()
+                    .map_err(handle_query_error)
+            })
+            .await
+    }
+
+    async fn get_table(&self, restaurant_id: i64, table_id: i64) -> StorageResult<Table> {
+        self.pool
+            .run(move |conn| query_table(conn, restaurant_id, table_id))
+            .await
+    }
+
+    async fn create_table(&self, restaurant_id: i64, table: NewTable) -> StorageResult<Table> {
+        let table = validate_table(table)?;
+
+        self.pool
+            .run(move |conn| {
+                let id = conn
+                    .query_row(
+                        INSERT_TABLE,
+                        (restaurant_id, &table.label, &table.section, table.seats),
+                        |row| row.get(0),
+                    )
+                    .map_err(handle_query_error)?;
+
+                Ok(Table {
+                    id,
+                    label: table.label,
+                    section: table.section,
+                    seats: table.seats,
+                    active: true,
+                })
+            })
+            .await
+    }
+
+    async fn update_table(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        patch: TablePatch,
+    ) -> StorageResult<Table> {
+        const QUERY: &str = "UPDATE tables SET label = ?3, section = ?4, seats = ?5, active = ?6 \
+            WHERE restaurant_id == ?1 AND id == ?2;";
+
+        self.pool
+            .run(move |conn| {
+                let tx = conn
+                    .transaction_with_behavior(TransactionBehavior::Immediate)
+                    .map_err(handle_query_error)?;
+
+                let (fields, active) =
+                    patch_table(&query_table(&tx, restaurant_id, table_id)?, patch);
+                let fields = validate_table(fields)?;
+
+                tx.execute(
+                    QUERY,
+                    (
+                        restaurant_id,
+                        table_id,
+                        &fields.label,
+                        &fields.section,
+                        fields.seats,
+                        active,
+                    ),
+                )
+                .map_err(handle_query_error)?;
+
+                tx.commit().map_err(handle_query_error)?;
+                Ok(Table {
+                    id: table_id,
+                    label: fields.label,
+                    section: fields.section,
+                    seats: fields.seats,
+                    active,
+                })
+            })
+            .await
+    }
+
+    async fn delete_table(&self, restaurant_id: i64, table_id: i64) -> StorageResult<()> {
+        const REFERENCED: &str = "SELECT EXISTS (SELECT 1 FROM order_events \
+            WHERE restaurant_id == ?1 AND table_id == ?2) OR EXISTS (SELECT 1 FROM parties \
+            WHERE restaurant_id == ?1 AND table_id == ?2);";
+        const DELETE: &str = "DELETE FROM tables WHERE restaurant_id == ?1 AND id == ?2;";
+
+        self.pool
+            .run(move |conn| {
+                let tx = conn
+                    .transaction_with_behavior(TransactionBehavior::Immediate)
+                    .map_err(handle_query_error)?;
+
+                query_table(&tx, restaurant_id, table_id)?;
+                let referenced: bool = tx
+                    .query_row(REFERENCED, [restaurant_id, table_id], |row| row.get(0))
+                    .map_err(handle_query_error)?;
+                if referenced {
+                    return Err(StorageError::Conflict(
+                        "table has been used, deactivate it instead".to_string(),
+                    ));
+                }
+
+                tx.execute(DELETE, [restaurant_id, table_id])
+                    .map_err(handle_query_error)?;
+                tx.commit().map_err(handle_query_error)
+            })
+            .await
+    }
+
+    async fn seat_party(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        request: SeatPartyRequest,
+    ) -> StorageResult<Party> {
+        const QUERY: &str = "INSERT INTO parties (restaurant_id, id, table_id, guests, seated_at) \
+            SELECT ?1, COALESCE(MAX(id), 0) + 1, ?2, ?3, ?4 FROM parties WHERE restaurant_id == ?1 \
+            RETURNING id;";
+
+        let request = validate_party(request)?;
+
+        self.pool
+            .run(move |conn| {
+                let tx = conn
+                    .transaction_with_behavior(TransactionBehavior::Immediate)
+                    .map_err(handle_query_error)?;
+
+                check_table_open(&query_table(&tx, restaurant_id, table_id)?)?;
+                check_table_free(query_open_party(&tx, restaurant_id, table_id)?.as_ref())?;
+
+                let seated_at = events::now();
+                let id = tx
+                    .query_row(
+                        QUERY,
+                        (restaurant_id, table_id, request.guests, &seated_at),
+                        |row| row.get(0),
+                    )
+                    .map_err(handle_query_error)?;
+
+                tx.commit().map_err(handle_query_error)?;
+                Ok(Party {
+                    id,
+                    table_id,
+                    guests: request.guests,
+                    seated_at,
+                    closed_at: None,
+                    check_split: CheckSplit::Single,
+                })
+            })
+            .await
+    }
+
+    async fn get_open_party(&self, restaurant_id: i64, table_id: i64) -> StorageResult<Party> {
+        self.pool
+            .run(move |conn| {
+                query_table(conn, restaurant_id, table_id)?;
+                query_open_party(conn, restaurant_id, table_id)?.ok_or_else(no_open_party)
+            })
+            .await
+    }
+
+    async fn get_table_parties(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> StorageResult<Vec<Party>> {
+        const QUERY: &str =
+            "SELECT id, table_id, guests, seated_at, closed_at, check_split FROM parties \
+            WHERE restaurant_id == ?1 AND table_id == ?2 ORDER BY id;";
+
+        self.pool
+            .run(move |conn| {
+                query_table(conn, restaurant_id, table_id)?;
+                conn.prepare(QUERY)
+                    .map_err(handle_query_error)?
+                    .query_map([restaurant_id, table_id], party_from_row)
+                    .map_err(handle_query_error)?
+                    .collect::<Result<_, _>>()
+                    .map_err(handle_query_error)
+            })
+            .await
+    }
+
+    async fn get_party(&self, restaurant_id: i64, party_id: i64) -> StorageResult<PartyDetails> {
+        // Orders archived by a close-out are no longer live, but still belong
+        // to the party
+        const ORDERS: &str = "SELECT id, item_id, ordered_at, ready_at, removed_at, removed_by, \
+            removed_reason, party_id, finished_at, started_at, prepared_at, served_at, \
+            cancelled_at, prep_secs, station, claimed_by, quantity, modifiers, notes, unit_price, \
+            seat FROM orders WHERE restaurant_id == ?1 AND party_id == ?2 \
+            UNION ALL SELECT order_id, item_id, ordered_at, ready_at, removed_at, removed_by, \
+            removed_reason, party_id, finished_at, started_at, prepared_at, served_at, \
+            cancelled_at, prep_secs, station, NULL, quantity, modifiers, notes, unit_price, seat \
+            FROM archived_orders \
+            WHERE restaurant_id == ?1 AND party_id == ?2 \
+            ORDER BY 1;";
+
+        self.pool
+            .run(move |conn| {
+                let tx = conn.transaction().map_err(handle_query_error)?;
+
+                let party = query_party(&tx, restaurant_id, party_id)?;
+                let orders = tx
+                    .prepare(ORDERS)
+                    .map_err(handle_query_error)?
+                    .query_map([restaurant_id, party_id], history_entry_from_row)
+                    .map_err(handle_query_error)?
+                    .collect::<Result<_, _>>()
+                    .map_err(handle_query_error)?;
+
+                Ok(PartyDetails { party, orders })
+            })
+            .await
+    }
+
+    async fn close_party(&self, restaurant_id: i64, party_id: i64) -> StorageResult<Party> {
+        self.write(move |conn| {
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(handle_query_error)?;
+
+            let mut party = query_party(&tx, restaurant_id, party_id)?;
+            check_party_open(&party)?;
+            party.closed_at = Some(close_settled_party(&tx, restaurant_id, &party)?);
+            reschedule(&tx, restaurant_id)?;
+
+            tx.commit().map_err(handle_query_error)?;
+            Ok(party)
+        })
+        .await
+    }
+
+    async fn get_tables_items(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> StorageResult<Vec<TableOrder>> {
+        self.pool
+            .run(move |conn| {
+                query_table(conn, restaurant_id, table_id)?;
+                query_tables_items(conn, restaurant_id, table_id)
+            })
+            .await
+    }
+
+    async fn order_items(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        items: OrderItemsRequest,
+    ) -> StorageResult<Vec<TableOrder>> {
+        self.write(move |conn| {
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(handle_query_error)?;
+
+            let table = query_table(&tx, restaurant_id, table_id)?;
+            check_table_open(&table)?;
+            let party_id = query_open_party(&tx, restaurant_id, table_id)?
+                .ok_or_else(party_required)?
+                .id;
+
+            let lines = items.into_lines();
+            let item_ids = lines.iter().map(|line| line.item_id).collect::<Vec<_>>();
+            let menu_items = menu_lookup(&tx, restaurant_id, &item_ids)?;
+            let lines =
+                validate_order(&lines, &menu_items, table.seats).map_err(StorageError::Rejected)?;
+
+            let mut kitchen = query_kitchen(&tx, restaurant_id)?;
+            let ordered_at = Utc::now();
+
+            let first_order_id = next_order_id(&tx, restaurant_id)?;
+            for (order_id, line) in (first_order_id..).zip(lines) {
+                let item = &menu_items[&line.item_id];
+                let prep_secs = item.line_prep_secs(&line.modifiers);
+                let change = OrderChange::Placed {
+                    item_id: line.item_id,
+                    ready_at: timing::format_ready_at(kitchen.queue(ordered_at, prep_secs)),
+                    party_id: Some(party_id),
+                    prep_secs: Some(prep_secs),
+                    station: item.station,
+                    quantity: line.quantity,
+                    modifiers: line.modifiers,
+                    notes: line.notes,
+                    unit_price: line.unit_price,
+                    seat: line.seat,
+                };
+                let event = OrderEvent {
+                    seq: 0,
+                    at: ordered_at.to_rfc3339(),
+                    order_id,
+                    table_id,
+                    change,
+                };
+                append_event(&tx, restaurant_id, event)?;
+            }
+
+            tx.commit().map_err(handle_query_error)?;
+
+            query_tables_items(conn, restaurant_id, table_id)
+        })
+        .await
+    }
+
+    async fn delete_table_item(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        order_id: i64,
+        request: RemoveOrderRequest,
+    ) -> StorageResult<Vec<TableOrder>> {
+        const QUERY: &str = "SELECT EXISTS (SELECT 1 FROM orders WHERE restaurant_id == ?1 \
+            AND id == ?2 AND table_id == ?3 AND removed_at IS NULL AND finished_at IS NULL);";
+
+        let request = validate_removal(request)?;
+
+        self.write(move |conn| {
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(handle_query_error)?;
+
+            let exists: bool = tx
+                .query_row(QUERY, [restaurant_id, order_id, table_id], |row| row.get(0))
+                .map_err(handle_query_error)?;
+
+            // Tried to delete a non-existing row
+            if !exists {
+                return Err(StorageError::NotFound(
+                    "order id does not exist".to_string(),
+                ));
+            }
+
+            let change = OrderChange::Removed {
+                removed_by: Some(request.removed_by),
+                reason: Some(request.reason),
+            };
+            record_event(&tx, restaurant_id, order_id, table_id, change)?;
+            reschedule(&tx, restaurant_id)?;
+            tx.commit().map_err(handle_query_error)?;
+
+            // Row removed successfully, return the remaining rows
+            query_tables_items(conn, restaurant_id, table_id)
+        })
+        .await
+    }
+
+    async fn restore_table_item(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        order_id: i64,
+        window: Duration,
+    ) -> StorageResult<Vec<TableOrder>> {
+        const QUERY: &str = "SELECT removed_at, removed_by, removed_reason, party_id FROM orders \
+            WHERE restaurant_id == ?1 AND id == ?2 AND table_id == ?3;";
+
+        self.write(move |conn| {
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(handle_query_error)?;
+
+            let (removal, party_id) = tx
+                .query_row(QUERY, [restaurant_id, order_id, table_id], |row| {
+                    Ok((removal_from_row(row, 0)?, row.get::<_, Option<i64>>(3)?))
+                })
+                .map_err(|e| match e {
+                    rusqlite::Error::QueryReturnedNoRows => {
+                        StorageError::NotFound("order id does not exist".to_string())
+                    }
+                    e => handle_query_error(e),
+                })?;
+
+            check_restorable(removal.as_ref(), window)?;
+            if let Some(party_id) = party_id {
+                check_party_open(&query_party(&tx, restaurant_id, party_id)?)?;
+            }
+            record_event(
+                &tx,
+                restaurant_id,
+                order_id,
+                table_id,
+                OrderChange::Restored,
+            )?;
+            reschedule(&tx, restaurant_id)?;
+            tx.commit().map_err(handle_query_error)?;
+
+            query_tables_items(conn, restaurant_id, table_id)
+        })
+        .await
+    }
+
+    async fn update_order_status(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        order_id: i64,
+        status: OrderStatus,
+    ) -> StorageResult<TableOrder> {
+        self.write(move |conn| {
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(handle_query_error)?;
+
+            let order = query_table_item(&tx, restaurant_id, table_id, order_id)?;
+            check_transition(order.status, status)?;
+            record_event(
+                &tx,
+                restaurant_id,
+                order_id,
+                table_id,
+                OrderChange::StatusChanged { status },
+            )?;
+            if status == OrderStatus::Cancelled {
+                reschedule(&tx, restaurant_id)?;
+            }
+
+            let order = query_table_item(&tx, restaurant_id, table_id, order_id)?;
+            tx.commit().map_err(handle_query_error)?;
+            Ok(order)
+        })
+        .await
+    }
+
+    async fn get_station_queue(
+        &self,
+        restaurant_id: i64,
+        station: Station,
+    ) -> StorageResult<Vec<StationLine>> {
+        let query =
+            format!("{STATION_LINE_SELECT} {STATION_WAITING} ORDER BY orders.ready_at, orders.id;");
+
+        self.pool
+            .run(move |conn| {
+                query_restaurant(conn, restaurant_id)?;
+                conn.prepare(&query)
+                    .map_err(handle_query_error)?
+                    .query_map(
+                        (restaurant_id, to_sql_enum(&station)),
+                        station_line_from_row,
+                    )
+                    .map_err(handle_query_error)?
+                    .collect::<Result<_, _>>()
+                    .map_err(handle_query_error)
+            })
+            .await
+    }
+
+    async fn claim_line(
+        &self,
+        restaurant_id: i64,
+        station: Station,
+        order_id: i64,
+        request: ClaimLineRequest,
+    ) -> StorageResult<StationLine> {
+        let request = validate_claim(request)?;
+
+        self.write(move |conn| {
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(handle_query_error)?;
+
+            let line = query_station_line(&tx, restaurant_id, station, order_id)?;
+            check_claimable(line.order.status, line.order.claimed_by.as_deref())?;
+            let change = OrderChange::Claimed {
+                claimed_by: request.claimed_by,
+            };
+            record_event(&tx, restaurant_id, order_id, line.table_id, change)?;
+
+            let line = query_station_line(&tx, restaurant_id, station, order_id)?;
+            tx.commit().map_err(handle_query_error)?;
+            Ok(line)
+        })
+        .await
+    }
+
+    async fn complete_line(
+        &self,
+        restaurant_id: i64,
+        station: Station,
+        order_id: i64,
+    ) -> StorageResult<StationLine> {
+        self.write(move |conn| {
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(handle_query_error)?;
+
+            let line = query_station_line(&tx, restaurant_id, station, order_id)?;
+            check_completable(line.order.status)?;
+            let change = OrderChange::StatusChanged {
+                status: OrderStatus::Ready,
+            };
+            record_event(&tx, restaurant_id, order_id, line.table_id, change)?;
+
+            // A finished line has left the queue, so it's read back directly
+            let query = format!(
+                "{STATION_LINE_SELECT} WHERE orders.restaurant_id == ?1 \
+                    AND orders.id == ?2;"
+            );
+            let line = tx
+                .query_row(&query, [restaurant_id, order_id], station_line_from_row)
+                .map_err(handle_query_error)?;
+            tx.commit().map_err(handle_query_error)?;
+            Ok(line)
+        })
+        .await
+    }
+
+    async fn get_table_history(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> StorageResult<Vec<OrderHistoryEntry>> {
+        const QUERY: &str = "SELECT id, item_id, ordered_at, ready_at, \
+            removed_at, removed_by, removed_reason, party_id, finished_at, \
+            started_at, prepared_at, served_at, cancelled_at, prep_secs, station, claimed_by, \
+            quantity, modifiers, notes, unit_price, seat \
+            FROM orders WHERE restaurant_id == ?1 AND table_id == ?2 ORDER BY id;";
+
+        self.pool
+            .run(move |conn| {
+                conn.prepare(QUERY)
+                    .map_err(handle_query_error)?
+                    .query_map([restaurant_id, table_id], history_entry_from_row)
+                    .map_err(handle_query_error)?
+                    .collect::<Result<_, _>>()
+                    .map_err(handle_query_error)
+            })
+            .await
+    }
+
+    async fn get_table_item(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        order_id: i64,
+    ) -> StorageResult<TableOrder> {
+        self.pool
+            .run(move |conn| query_table_item(conn, restaurant_id, table_id, order_id))
+            .await
+    }
+
+    async fn get_bill_lines(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> StorageResult<Vec<BillLine>> {
+        self.pool
+            .run(move |conn| {
+                query_table(conn, restaurant_id, table_id)?;
+                query_bill_lines(conn, restaurant_id, table_id)
+            })
+            .await
+    }
+
+    async fn get_check_split(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> StorageResult<CheckSplit> {
+        self.pool
+            .run(move |conn| {
+                query_table(conn, restaurant_id, table_id)?;
+                Ok(query_open_party(conn, restaurant_id, table_id)?
+                    .map(|party| party.check_split)
+                    .unwrap_or_default())
+            })
+            .await
+    }
+
+    async fn split_checks(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        change: SplitChange,
+    ) -> StorageResult<CheckSplit> {
+        const QUERY: &str =
+            "UPDATE parties SET check_split = ?3 WHERE restaurant_id == ?1 AND id == ?2;";
+
+        self.pool
+            .run(move |conn| {
+                let tx = conn
+                    .transaction_with_behavior(TransactionBehavior::Immediate)
+                    .map_err(handle_query_error)?;
+
+                query_table(&tx, restaurant_id, table_id)?;
+                let party =
+                    query_open_party(&tx, restaurant_id, table_id)?.ok_or_else(party_required)?;
+                let lines = query_bill_lines(&tx, restaurant_id, table_id)?;
+                let split = apply_split_change(&party.check_split, &lines, change)?;
+
+                tx.execute(QUERY, (restaurant_id, party.id, to_sql_json(&split)))
+                    .map_err(handle_query_error)?;
+                tx.commit().map_err(handle_query_error)?;
+                Ok(split)
+            })
+            .await
+    }
+
+    async fn get_managers(&self, restaurant_id: i64) -> StorageResult<Vec<Manager>> {
+        const QUERY: &str = "SELECT id, name, active, pin_hash FROM managers \
+            WHERE restaurant_id == ?1 ORDER BY id;";
+
+        self.pool
+            .run(move |conn| {
+                conn.prepare(QUERY)
+                    .map_err(handle_query_error)?
+                    .query_map([restaurant_id], |row| Ok(manager_from_row(row)?.manager))
+                    .map_err(handle_query_error)?
+                    .collect::<Result<_, _>>()
+                    .map_err(handle_query_error)
+            })
+            .await
+    }
+
+    async fn add_manager(&self, restaurant_id: i64, manager: NewManager) -> StorageResult<Manager> {
+        let manager = validate_manager(manager)?;
+
+        self.pool
+            .run(move |conn| {
+                let id = conn
+                    .query_row(
+                        INSERT_MANAGER,
+                        (restaurant_id, &manager.name, hash_pin(&manager.pin)),
+                        |row| row.get(0),
+                    )
+                    .map_err(handle_query_error)?;
+
+                Ok(Manager {
+                    id,
+                    name: manager.name,
+                    active: true,
+                })
+            })
+            .await
+    }
+
+    async fn retire_manager(&self, restaurant_id: i64, manager_id: i64) -> StorageResult<Manager> {
+        const QUERY: &str = "UPDATE managers SET active = 0 \
+            WHERE restaurant_id == ?1 AND id == ?2 RETURNING id, name, active, pin_hash;";
+
+        self.pool
+            .run(move |conn| {
+                conn.query_row(QUERY, [restaurant_id, manager_id], manager_from_row)
+                    .optional()
+                    .map_err(handle_query_error)?
+                    .map(|row| row.manager)
+                    .ok_or_else(manager_not_found)
+            })
+            .await
+    }
+
+    async fn get_adjustments(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> StorageResult<Vec<Adjustment>> {
+        self.pool
+            .run(move |conn| {
+                query_table(conn, restaurant_id, table_id)?;
+                match query_open_party(conn, restaurant_id, table_id)? {
+                    Some(party) => query_party_adjustments(conn, restaurant_id, party.id),
+                    None => Ok(Vec::new()),
+                }
+            })
+            .await
+    }
+
+    async fn add_adjustment(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        request: NewAdjustment,
+    ) -> StorageResult<Adjustment> {
+        const MANAGER: &str = "SELECT id, name, active, pin_hash FROM managers \
+            WHERE restaurant_id == ?1 AND name == ?2;";
+        // PINs hashed more weakly than they are today get hashed again
+        const UPDATE_PIN_HASH: &str =
+            "UPDATE managers SET pin_hash = ?3 WHERE restaurant_id == ?1 AND id == ?2;";
+        const INSERT: &str = "INSERT INTO adjustments \
+            (restaurant_id, id, party_id, table_id, order_id, kind, discount, reason, \
+            requested_by, approved_by, created_at) \
+            SELECT ?1, COALESCE(MAX(id), 0) + 1, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11 \
+            FROM adjustments WHERE restaurant_id == ?1 RETURNING id;";
+
+        let request = validate_adjustment(request)?;
+
+        self.pool
+            .run(move |conn| {
+                let tx = conn
+                    .transaction_with_behavior(TransactionBehavior::Immediate)
+                    .map_err(handle_query_error)?;
+
+                query_table(&tx, restaurant_id, table_id)?;
+                let party_id = query_open_party(&tx, restaurant_id, table_id)?
+                    .ok_or_else(party_required)?
+                    .id;
+                let manager = tx
+                    .query_row(
+                        MANAGER,
+                        (restaurant_id, &request.approved_by),
+                        manager_from_row,
+                    )
+                    .optional()
+                    .map_err(handle_query_error)?;
+                check_approval(&request, manager.as_ref())?;
+                if let (Some(row), Some(pin)) = (&manager, &request.pin) {
+                    if pin_hash_outdated(&row.pin_hash) && pin_matches(pin, &row.pin_hash) {
+                        tx.execute(
+                            UPDATE_PIN_HASH,
+                            (restaurant_id, row.manager.id, hash_pin(pin)),
+                        )
+                        .map_err(handle_query_error)?;
+                    }
+                }
+                check_adjustable(
+                    &request,
+                    &query_bill_lines(&tx, restaurant_id, table_id)?,
+                    &query_party_adjustments(&tx, restaurant_id, party_id)?,
+                )?;
+                if let Some(order_id) = request.order_id {
+                    let order = query_table_item(&tx, restaurant_id, table_id, order_id)?;
+                    check_voidable(&request, order.status)?;
+                }
+
+                let mut adjustment = Adjustment {
+                    id: 0,
+                    party_id,
+                    table_id,
+                    order_id: request.order_id,
+                    kind: request.kind,
+                    discount: request.discount,
+                    reason: request.reason,
+                    requested_by: request.requested_by,
+                    approved_by: request.approved_by,
+                    created_at: events::now(),
+                };
+                adjustment.id = tx
+                    .query_row(
+                        INSERT,
+                        adjustment_params(restaurant_id, &adjustment),
+                        |row| row.get(0),
+                    )
+                    .map_err(handle_query_error)?;
+
+                tx.commit().map_err(handle_query_error)?;
+                Ok(adjustment)
+            })
+            .await
+    }
+
+    async fn get_payments(&self, restaurant_id: i64, table_id: i64) -> StorageResult<Vec<Payment>> {
+        self.pool
+            .run(move |conn| {
+                query_table(conn, restaurant_id, table_id)?;
+                match query_open_party(conn, restaurant_id, table_id)? {
+                    Some(party) => query_party_payments(conn, restaurant_id, party.id),
+                    None => Ok(Vec::new()),
+                }
+            })
+            .await
+    }
+
+    async fn get_payment(&self, restaurant_id: i64, payment_id: i64) -> StorageResult<Payment> {
+        self.pool
+            .run(move |conn| query_payment(conn, restaurant_id, payment_id))
+            .await
+    }
+
+    async fn add_payment(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        payment: NewPayment,
+    ) -> StorageResult<Payment> {
+        const INSERT: &str = "INSERT INTO payments \
+            (restaurant_id, id, party_id, table_id, tender, amount, tip, status, reference, \
+            failure, created_at, updated_at) \
+            SELECT ?1, COALESCE(MAX(id), 0) + 1, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12 \
+            FROM payments WHERE restaurant_id == ?1 RETURNING id;";
+
+        validate_payment(&payment)?;
+
+        self.pool
+            .run(move |conn| {
+                let tx = conn
+                    .transaction_with_behavior(TransactionBehavior::Immediate)
+                    .map_err(handle_query_error)?;
+
+                query_table(&tx, restaurant_id, table_id)?;
+                let party_id = query_open_party(&tx, restaurant_id, table_id)?
+                    .ok_or_else(party_required)?
+                    .id;
+                check_payable(
+                    &payment,
+                    &query_party_bill(&tx, restaurant_id, table_id, party_id)?,
+                    &query_party_payments(&tx, restaurant_id, party_id)?,
+                )?;
+
+                let now = events::now();
+                let mut payment = Payment {
+                    id: 0,
+                    party_id,
+                    table_id,
+                    tender: payment.tender,
+                    amount: payment.amount,
+                    tip: payment.tip,
+                    status: match payment.tender {
+                        Tender::Cash => PaymentStatus::Captured,
+                        Tender::Card => PaymentStatus::Pending,
+                    },
+                    reference: None,
+                    failure: None,
+                    created_at: now.clone(),
+                    updated_at: now,
+                };
+                payment.id = tx
+                    .query_row(INSERT, payment_params(restaurant_id, &payment), |row| {
+                        row.get(0)
+                    })
+                    .map_err(handle_query_error)?;
+
+                tx.commit().map_err(handle_query_error)?;
+                Ok(payment)
+            })
+            .await
+    }
+
+    async fn update_payment(
+        &self,
+        restaurant_id: i64,
+        payment_id: i64,
+        change: PaymentChange,
+    ) -> StorageResult<Payment> {
+        const UPDATE: &str = "UPDATE payments SET tip = ?3, status = ?4, reference = ?5, \
+            failure = ?6, updated_at = ?7 WHERE restaurant_id == ?1 AND id == ?2;";
+
+        self.pool
+            .run(move |conn| {
+                let tx = conn
+                    .transaction_with_behavior(TransactionBehavior::Immediate)
+                    .map_err(handle_query_error)?;
+
+                let payment = query_payment(&tx, restaurant_id, payment_id)?;
+                let payment = apply_payment_change(payment, change, events::now())?;
+                tx.execute(
+                    UPDATE,
+                    (
+                        restaurant_id,
+                        payment_id,
+                        payment.tip,
+                        to_sql_enum(&payment.status),
+                        &payment.reference,
+                        &payment.failure,
+                        &payment.updated_at,
+                    ),
+                )
+                .map_err(handle_query_error)?;
+
+                tx.commit().map_err(handle_query_error)?;
+                Ok(payment)
+            })
+            .await
+    }
+
+    async fn rebuild_orders(&self, dry_run: bool) -> StorageResult<RebuildReport> {
+        self.pool
+            .run(move |conn| {
+                let tx = conn
+                    .transaction_with_behavior(TransactionBehavior::Immediate)
+                    .map_err(handle_query_error)?;
+
+                let restaurant_ids = tx
+                    .prepare("SELECT id FROM restaurants ORDER BY id;")
+                    .map_err(handle_query_error)?
+                    .query_map([], |row| row.get(0))
+                    .map_err(handle_query_error)?
+                    .collect::<Result<Vec<i64>, _>>()
+                    .map_err(handle_query_error)?;
+
+                let mut report = RebuildReport {
+                    events_replayed: 0,
+                    orders: 0,
+                    mismatches: Vec::new(),
+                    rebuilt: !dry_run,
+                };
+
+                let mut logs = Vec::new();
+                for restaurant_id in restaurant_ids {
+                    let events = query_events(&tx, restaurant_id)?;
+                    let expected = events::project(&events);
+                    let actual = query_projection(&tx, restaurant_id)?;
+
+                    report.events_replayed += events.len();
+                    report.orders += expected.len();
+                    report.mismatches.extend(
+                        events::diff(&expected, &actual)
+                            .into_iter()
+                            .map(|mismatch| format!("restaurant {restaurant_id}: {mismatch}")),
+                    );
+                    logs.push((restaurant_id, events));
+                }
+
+                if !dry_run {
+                    // Replaying may briefly insert orders for menu items which were
+                    // deleted after the order was removed, so only check at commit.
+                    tx.execute_batch("PRAGMA defer_foreign_keys = ON; DELETE FROM orders;")
+                        .map_err(handle_query_error)?;
+                    for (restaurant_id, events) in &logs {
+                        for event in events {
+                            project_event(&tx, *restaurant_id, event)?;
+                        }
+                    }
+                    tx.commit().map_err(handle_query_error)?;
+                }
+
+                Ok(report)
+            })
+            .await
+    }
+
+    async fn backup(&self, path: PathBuf) -> StorageResult<()> {
+        if path.exists() {
+            return Err(StorageError::Conflict(format!(
+                "{} already exists",
+                path.display()
+            )));
+        }
+
+        self.pool
+            .run(move |conn| {
+                let mut destination = Connection::open(&path).map_err(handle_query_error)?;
+                let backup = Backup::new(conn, &mut destination).map_err(handle_query_error)?;
+
+                // Copy every page in a single step, so writes made by other
+                // connections meanwhile can't force the backup to start over
+                loop {
+                    match backup.step(-1).map_err(handle_query_error)? {
+                        StepResult::Done => return Ok(()),
+                        _ => std::thread::sleep(BACKUP_RETRY_INTERVAL),
+                    }
+                }
+            })
+            .await
+    }
+
+    async fn restore(&self, path: PathBuf) -> StorageResult<()> {
+        self.pool
+            .run(move |conn| {
+                check_backup(&path)?;
+                conn.restore(DatabaseName::Main, &path, None::<fn(Progress)>)
+                    .map_err(handle_query_error)?;
+                migrations::run(conn, |_| Ok(())).map_err(handle_query_error)?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn export_data(&self, restaurant_id: i64) -> StorageResult<DataExport> {
+        const RETIRED: &str = "SELECT id, name, prep_min_m, prep_max_m, station, modifiers, \
+            price FROM menu WHERE restaurant_id == ?1 AND active == 0 ORDER BY id;";
+        const ORDERS: &str = "SELECT table_id, id, item_id, ordered_at, ready_at, \
+            removed_at, removed_by, removed_reason, party_id, finished_at, \
+            started_at, prepared_at, served_at, cancelled_at, prep_secs, station, claimed_by, \
+            quantity, modifiers, notes, unit_price, seat \
+            FROM orders WHERE restaurant_id == ?1 ORDER BY table_id, id;";
+        const PARTIES: &str =
+            "SELECT id, table_id, guests, seated_at, closed_at, check_split FROM parties \
+            WHERE restaurant_id == ?1 ORDER BY id;";
+        const ADJUSTMENTS: &str = "SELECT id, party_id, table_id, order_id, kind, discount, \
+            reason, requested_by, approved_by, created_at FROM adjustments \
+            WHERE restaurant_id == ?1 ORDER BY id;";
+        const PAYMENTS: &str = "SELECT id, party_id, table_id, tender, amount, tip, status, \
+            reference, failure, created_at, updated_at FROM payments \
+            WHERE restaurant_id == ?1 ORDER BY id;";
+
+        self.pool
+            .run(move |conn| {
+                let tx = conn.transaction().map_err(handle_query_error)?;
+
+                let menu = Menu {
+                    version: query_menu_version(&tx, restaurant_id)?,
+                    items: query_menu(&tx, restaurant_id)?,
+                };
+                let retired_items = tx
+                    .prepare(RETIRED)
+                    .map_err(handle_query_error)?
+                    .query_map([restaurant_id], menu_item_from_row)
+                    .map_err(handle_query_error)?
+                    .collect::<Result<_, _>>()
+                    .map_err(handle_query_error)?;
+
+                let mut tables: Vec<TableHistory> = Vec::new();
+                let mut statement = tx.prepare(ORDERS).map_err(handle_query_error)?;
+                let mut rows = statement
+                    .query([restaurant_id])
+                    .map_err(handle_query_error)?;
+                while let Some(row) = rows.next().map_err(handle_query_error)? {
+                    let table_id = row.get(0).map_err(handle_query_error)?;
+                    let status_history =
+                        status_history_from_row(row, 3, 10).map_err(handle_query_error)?;
+                    let order = OrderHistoryEntry {
+                        order_id: row.get(1).map_err(handle_query_error)?,
+                        item_id: row.get(2).map_err(handle_query_error)?,
+                        ordered_at: row.get(3).map_err(handle_query_error)?,
+                        ready_at: row.get(4).map_err(handle_query_error)?,
+                        removal: removal_from_row(row, 5).map_err(handle_query_error)?,
+                        party_id: row.get(8).map_err(handle_query_error)?,
+                        finished_at: row.get(9).map_err(handle_query_error)?,
+                        status: events::current_status(&status_history),
+                        status_history,
+                        prep_secs: row.get(14).map_err(handle_query_error)?,
+                        station: row
+                            .get(15)
+                            .and_then(from_sql_enum)
+                            .map_err(handle_query_error)?,
+                        claimed_by: row.get(16).map_err(handle_query_error)?,
+                        quantity: row.get(17).map_err(handle_query_error)?,
+                        modifiers: row
+                            .get(18)
+                            .and_then(from_sql_json)
+                            .map_err(handle_query_error)?,
+                        notes: row.get(19).map_err(handle_query_error)?,
+                        unit_price: row.get(20).map_err(handle_query_error)?,
+                        seat: row.get(21).map_err(handle_query_error)?,
+                    };
+
+                    match tables.last_mut() {
+                        Some(table) if table.table_id == table_id => table.orders.push(order),
+                        _ => tables.push(TableHistory {
+                            table_id,
+                            orders: vec![order],
+                        }),
+                    }
+                }
+
+                let table_registry = tx
+                    .prepare(
+                        "SELECT id, label, section, seats, active FROM tables \
+                        WHERE restaurant_id == ?1 ORDER BY id;",
+                    )
+                    .map_err(handle_query_error)?
+                    .query_map([restaurant_id], table_from_row)
+                    .map_err(handle_query_error)?
+                    .collect::<Result<_, _>>()
+                    .map_err(handle_query_error)?;
+
+                let parties = tx
+                    .prepare(PARTIES)
+                    .map_err(handle_query_error)?
+                    .query_map([restaurant_id], party_from_row)
+                    .map_err(handle_query_error)?
+                    .collect::<Result<_, _>>()
+                    .map_err(handle_query_error)?;
+
+                let adjustments = tx
+                    .prepare(ADJUSTMENTS)
+                    .map_err(handle_query_error)?
+                    .query_map([restaurant_id], adjustment_from_row)
+                    .map_err(handle_query_error)?
+                    .collect::<Result<_, _>>()
+                    .map_err(handle_query_error)?;
+
+                let payments = tx
+                    .prepare(PAYMENTS)
+                    .map_err(handle_query_error)?
+                    .query_map([restaurant_id], payment_from_row)
+                    .map_err(handle_query_error)?
+                    .collect::<Result<_, _>>()
+                    .map_err(handle_query_error)?;
+
+                Ok(DataExport {
+                    menu,
+                    retired_items,
+                    tables,
+                    table_registry,
+                    parties,
+                    adjustments,
+                    payments,
+                })
+            })
+            .await
+    }
+
+    async fn import_data(
+        &self,
+        restaurant_id: i64,
+        data: DataExport,
+    ) -> StorageResult<ImportReport> {
+        const INSERT_ITEM: &str = "INSERT INTO menu \
+            (restaurant_id, id, name, prep_min_m, prep_max_m, active, station, modifiers, price) \
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9);";
+        const INSERT_TABLE: &str = "INSERT INTO tables \
+            (restaurant_id, id, label, section, seats, active) \
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6);";
+        const INSERT_PARTY: &str = "INSERT INTO parties \
+            (restaurant_id, id, table_id, guests, seated_at, closed_at, check_split) \
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);";
+        const INSERT_ADJUSTMENT: &str = "INSERT INTO adjustments \
+            (restaurant_id, id, party_id, table_id, order_id, kind, discount, reason, \
+            requested_by, approved_by, created_at) \
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11);";
+        const INSERT_PAYMENT: &str = "INSERT INTO payments \
+            (restaurant_id, id, party_id, table_id, tender, amount, tip, status, reference, \
+            failure, created_at, updated_at) \
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12);";
+
+        validate_import(&data)?;
+        let registry = import_table_registry(&data);
+
+        self.write(move |conn| {
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(handle_query_error)?;
+
+            // Orders are briefly left pointing at deleted menu items and
+            // tables until the snapshot's are inserted, so only check at commit.
+            tx.execute_batch("PRAGMA defer_foreign_keys = ON;")
+                .map_err(handle_query_error)?;
+            for table in [
+                "orders",
+                "order_events",
+                "adjustments",
+                "payments",
+                "parties",
+                "menu",
+                "tables",
+            ] {
+                tx.execute(
+                    &format!("DELETE FROM {table} WHERE restaurant_id == ?1;"),
+                    [restaurant_id],
+                )
+                .map_err(handle_query_error)?;
+            }
+
+            let items = data.menu.items.iter().map(|item| (item, true));
+            let retired = data.retired_items.iter().map(|item| (item, false));
+            for (item, active) in items.chain(retired) {
+                tx.execute(
+                    INSERT_ITEM,
+                    (
+                        restaurant_id,
+                        item.id,
+                        &item.name,
+                        item.prep_min_m,
+                        item.prep_max_m,
+                        active,
+                        to_sql_enum(&item.station),
+                        to_sql_json(&item.modifiers),
+                        item.price,
+                    ),
+                )
+                .map_err(handle_query_error)?;
+            }
+            tx.execute(
+                "UPDATE menu_version SET version = ?2 WHERE restaurant_id == ?1;",
+                [restaurant_id, data.menu.version],
+            )
+            .map_err(handle_query_error)?;
+
+            for table in &registry {
+                tx.execute(
+                    INSERT_TABLE,
+                    (
+                        restaurant_id,
+                        table.id,
+                        &table.label,
+                        &table.section,
+                        table.seats,
+                        table.active,
+                    ),
+                )
+                .map_err(handle_query_error)?;
+            }
+
+            for party in &data.parties {
+                tx.execute(
+                    INSERT_PARTY,
+                    (
+                        restaurant_id,
+                        party.id,
+                        party.table_id,
+                        party.guests,
+                        &party.seated_at,
+                        &party.closed_at,
+                        to_sql_json(&party.check_split),
+                    ),
+                )
+                .map_err(handle_query_error)?;
+            }
 
-    conn
+            for adjustment in &data.adjustments {
+                insert_adjustment(&tx, INSERT_ADJUSTMENT, restaurant_id, adjustment)?;
+            }
+
+            for payment in &data.payments {
+                tx.execute(INSERT_PAYMENT, payment_params(restaurant_id, payment))
+                    .map_err(handle_query_error)?;
+            }
+
+            let events = events::from_history(&data.tables);
+            for event in events.iter().cloned() {
+                append_event(&tx, restaurant_id, event)?;
+            }
+
+            tx.commit().map_err(handle_query_error)?;
+
+            Ok(ImportReport {
+                menu_items: data.menu.items.len() + data.retired_items.len(),
+                tables: registry.len(),
+                parties: data.parties.len(),
+                orders: events::project(&events).len(),
+                adjustments: data.adjustments.len(),
+                payments: data.payments.len(),
+            })
+        })
+        .await
+    }
+
+    async fn close_out(
+        &self,
+        restaurant_id: i64,
+        business_date: NaiveDate,
+    ) -> StorageResult<CloseOutSummary> {
+        const ORDERS: &str =
+            "SELECT orders.id, table_id, item_id, menu.name, ordered_at, ready_at, \
+            removed_at, removed_by, removed_reason, party_id, finished_at, \
+            started_at, prepared_at, served_at, cancelled_at, quantity, orders.modifiers, notes, \
+            unit_price, seat, orders.station, prep_secs FROM orders JOIN menu \
+            ON menu.restaurant_id == orders.restaurant_id AND menu.id == orders.item_id \
+            WHERE orders.restaurant_id == ?1 AND (removed_at IS NOT NULL OR finished_at IS NOT NULL \
+            OR served_at IS NOT NULL OR cancelled_at IS NOT NULL) \
+            AND (party_id IS NULL OR party_id NOT IN (SELECT id FROM parties \
+            WHERE restaurant_id == ?1 AND closed_at IS NULL)) ORDER BY orders.id;";
+        const ARCHIVE: &str =
+            "INSERT INTO archived_orders (restaurant_id, order_id, business_date, \
+            table_id, item_id, item_name, ordered_at, ready_at, removed_at, removed_by, \
+            removed_reason, party_id, finished_at, status, started_at, prepared_at, served_at, \
+            cancelled_at, quantity, modifiers, notes, unit_price, seat, station, prep_secs) \
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, \
+            ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25);";
+        const OPEN_PARTIES: &str =
+            "SELECT id, table_id, guests, seated_at, closed_at, check_split FROM parties \
+            WHERE restaurant_id == ?1 AND closed_at IS NULL ORDER BY id;";
+        const SUMMARY: &str = "INSERT INTO close_outs (restaurant_id, business_date, closed_at, \
+            summary) VALUES (?1, ?2, ?3, ?4);";
+
+        let business_date_text = business_date.to_string();
+
+        self.write(move |conn| {
+            let tx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(handle_query_error)?;
+
+            // Parties which have settled up leave, and the rest stay open
+            // with their orders so they can still pay
+            let open_parties = tx
+                .prepare(OPEN_PARTIES)
+                .map_err(handle_query_error)?
+                .query_map([restaurant_id], party_from_row)
+                .map_err(handle_query_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(handle_query_error)?;
+            let mut unsettled_parties = Vec::new();
+            for party in open_parties {
+                match close_settled_party(&tx, restaurant_id, &party) {
+                    Ok(_) => {}
+                    Err(StorageError::Conflict(_)) => unsettled_parties.push(party.id),
+                    Err(e) => return Err(e),
+                }
+            }
+            reschedule(&tx, restaurant_id)?;
+
+            let orders = tx
+                .prepare(ORDERS)
+                .map_err(handle_query_error)?
+                .query_map([restaurant_id], |row| {
+                    let status_history = status_history_from_row(row, 4, 11)?;
+                    Ok(ArchivedOrder {
+                        business_date: business_date_text.clone(),
+                        order_id: row.get(0)?,
+                        table_id: row.get(1)?,
+                        item_id: row.get(2)?,
+                        item_name: row.get(3)?,
+                        ordered_at: row.get(4)?,
+                        ready_at: row.get(5)?,
+                        removal: removal_from_row(row, 6)?,
+                        party_id: row.get(9)?,
+                        finished_at: row.get(10)?,
+                        status: events::current_status(&status_history),
+                        status_history,
+                        quantity: row.get(15)?,
+                        modifiers: from_sql_json(row.get(16)?)?,
+                        notes: row.get(17)?,
+                        unit_price: row.get(18)?,
+                        seat: row.get(19)?,
+                        station: from_sql_enum(row.get(20)?)?,
+                        prep_secs: row.get(21)?,
+                    })
+                })
+                .map_err(handle_query_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(handle_query_error)?;
+
+            for order in &orders {
+                let removal = order.removal.as_ref();
+                let entered = |status| status_entered_at(&order.status_history, status);
+                tx.execute(
+                    ARCHIVE,
+                    params![
+                        restaurant_id,
+                        order.order_id,
+                        &order.business_date,
+                        order.table_id,
+                        order.item_id,
+                        &order.item_name,
+                        &order.ordered_at,
+                        &order.ready_at,
+                        removal.map(|removal| &removal.removed_at),
+                        removal.map(|removal| &removal.removed_by),
+                        removal.map(|removal| to_sql_enum(&removal.reason)),
+                        order.party_id,
+                        &order.finished_at,
+                        to_sql_enum(&order.status),
+                        entered(OrderStatus::InProgress),
+                        entered(OrderStatus::Ready),
+                        entered(OrderStatus::Served),
+                        entered(OrderStatus::Cancelled),
+                        order.quantity,
+                        to_sql_json(&order.modifiers),
+                        &order.notes,
+                        order.unit_price,
+                        order.seat,
+                        to_sql_enum(&order.station),
+                        order.prep_secs,
+                    ],
+                )
+                .map_err(handle_query_error)?;
+
+                let change = OrderChange::Archived {
+                    business_date: order.business_date.clone(),
+                };
+                record_event(&tx, restaurant_id, order.order_id, order.table_id, change)?;
+            }
+
+            let summary = closeout::summarize(business_date, &orders, unsettled_parties);
+            let json = serde_json::to_string(&summary)
+                .map_err(|e| StorageError::Internal(e.to_string()))?;
+            tx.execute(
+                SUMMARY,
+                (
+                    restaurant_id,
+                    &summary.business_date,
+                    &summary.closed_at,
+                    json,
+                ),
+            )
+            .map_err(handle_query_error)?;
+
+            tx.commit().map_err(handle_query_error)?;
+            Ok(summary)
+        })
+        .await
+    }
+
+    async fn get_close_outs(&self, restaurant_id: i64) -> StorageResult<Vec<CloseOutSummary>> {
+        const QUERY: &str =
+            "SELECT id, summary FROM close_outs WHERE restaurant_id == ?1 ORDER BY id;";
+
+        self.pool
+            .run(move |conn| {
+                conn.prepare(QUERY)
+                    .map_err(handle_query_error)?
+                    .query_map([restaurant_id], |row| {
+                        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+                    })
+                    .map_err(handle_query_error)?
+                    .map(|row| {
+                        let (id, summary) = row.map_err(handle_query_error)?;
+                        serde_json::from_str(&summary).map_err(|e| {
+                            StorageError::Internal(format!("close-out {id} is unreadable: {e}"))
+                        })
+                    })
+                    .collect()
+            })
+            .await
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<LiveUpdate> {
+        let receiver = self.live.subscribe();
+        self.tail.started.get_or_init(|| {
+            let (pool, live, tail) = (self.pool.clone(), self.live.clone(), self.tail.clone());
+            tokio::spawn(follow_log(pool, live, tail));
+        });
+        receiver
+    }
+
+    async fn get_archived_orders(
+        &self,
+        restaurant_id: i64,
+        business_date: NaiveDate,
+    ) -> StorageResult<Vec<ArchivedOrder>> {
+        const QUERY: &str = "SELECT business_date, order_id, table_id, item_id, item_name, \
+            ordered_at, ready_at, removed_at, removed_by, removed_reason, party_id, finished_at, \
+            started_at, prepared_at, served_at, cancelled_at, quantity, modifiers, notes, \
+            unit_price, seat, station, prep_secs FROM archived_orders \
+            WHERE restaurant_id == ?1 AND business_date == ?2 \
+            ORDER BY order_id;";
+
+        self.pool
+            .run(move |conn| {
+                conn.prepare(QUERY)
+                    .map_err(handle_query_error)?
+                    .query_map((restaurant_id, business_date.to_string()), |row| {
+                        let status_history = status_history_from_row(row, 5, 12)?;
+                        Ok(ArchivedOrder {
+                            business_date: row.get(0)?,
+                            order_id: row.get(1)?,
+                            table_id: row.get(2)?,
+                            item_id: row.get(3)?,
+                            item_name: row.get(4)?,
+                            ordered_at: row.get(5)?,
+                            ready_at: row.get(6)?,
+                            removal: removal_from_row(row, 7)?,
+                            party_id: row.get(10)?,
+                            finished_at: row.get(11)?,
+                            status: events::current_status(&status_history),
+                            status_history,
+                            quantity: row.get(16)?,
+                            modifiers: from_sql_json(row.get(17)?)?,
+                            notes: row.get(18)?,
+                            unit_price: row.get(19)?,
+                            seat: row.get(20)?,
+                            station: from_sql_enum(row.get(21)?)?,
+                            prep_secs: row.get(22)?,
+                        })
+                    })
+                    .map_err(handle_query_error)?
+                    .collect::<Result<_, _>>()
+                    .map_err(handle_query_error)
+            })
+            .await
+    }
 }
 
-fn handle_query_error(error: rusqlite::Error) -> HttpError {
-    use rusqlite::Error::*;
+/// Checks that `path` holds a database this server can restore and migrate
+fn check_backup(path: &Path) -> StorageResult<()> {
+    let invalid = |e: rusqlite::Error| {
+        StorageError::BadRequest(format!("{} is not a database backup: {e}", path.display()))
+    };
+
+    let conn =
+        Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(invalid)?;
+    let version: usize = conn
+        .query_row("SELECT MAX(version) FROM schema_version;", [], |row| {
+            row.get(0)
+        })
+        .map_err(invalid)?;
+
+    match version > migrations::latest_version() {
+        true => Err(StorageError::Invalid(format!(
+            "backup is at schema version {version}, newer than this server supports"
+        ))),
+        false => Ok(()),
+    }
+}
+
+/// Order ids are never reused, even once an order is removed, so that each
+/// id in a restaurant's event log refers to exactly one order.
+fn next_order_id(conn: &Connection, restaurant_id: i64) -> StorageResult<i64> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(order_id), 0) + 1 FROM order_events WHERE restaurant_id == ?1;",
+        [restaurant_id],
+        |row| row.get(0),
+    )
+    .map_err(handle_query_error)
+}
+
+/// Appends a change to the order event log and applies it to the orders
+/// projection. Must be called inside a transaction so the two never disagree.
+fn record_event(
+    conn: &Connection,
+    restaurant_id: i64,
+    order_id: i64,
+    table_id: i64,
+    change: OrderChange,
+) -> StorageResult<()> {
+    append_event(
+        conn,
+        restaurant_id,
+        OrderEvent {
+            seq: 0,
+            at: events::now(),
+            order_id,
+            table_id,
+            change,
+        },
+    )
+}
+
+/// Appends an already timestamped event to the log, numbering it after the
+/// last one, and applies it to the orders projection
+fn append_event(conn: &Connection, restaurant_id: i64, mut event: OrderEvent) -> StorageResult<()> {
+    const QUERY: &str = "INSERT INTO order_events (restaurant_id, at, order_id, table_id, change) \
+        VALUES (?1, ?2, ?3, ?4, ?5);";
+
+    let json =
+        serde_json::to_string(&event.change).map_err(|e| StorageError::Internal(e.to_string()))?;
+    conn.execute(
+        QUERY,
+        (
+            restaurant_id,
+            &event.at,
+            event.order_id,
+            event.table_id,
+            json,
+        ),
+    )
+    .map_err(handle_query_error)?;
+
+    event.seq = conn.last_insert_rowid();
+    project_event(conn, restaurant_id, &event)
+}
+
+/// Updates the orders projection to reflect a single event
+fn project_event(conn: &Connection, restaurant_id: i64, event: &OrderEvent) -> StorageResult<()> {
+    const PLACE: &str = "INSERT INTO orders (restaurant_id, id, table_id, item_id, \
+        ordered_at, ready_at, party_id, prep_secs, station, quantity, modifiers, notes, \
+        unit_price, seat) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14);";
+    const REMOVE: &str = "UPDATE orders SET removed_at = ?3, removed_by = ?4, removed_reason = ?5 \
+        WHERE restaurant_id == ?1 AND id == ?2;";
+    const DELETE: &str = "DELETE FROM orders WHERE restaurant_id == ?1 AND id == ?2;";
+    const RESTORE: &str = "UPDATE orders SET removed_at = NULL, removed_by = NULL, \
+        removed_reason = NULL WHERE restaurant_id == ?1 AND id == ?2;";
+    const CLAIM: &str = "UPDATE orders SET status = 'in_progress', started_at = ?3, \
+        claimed_by = ?4 WHERE restaurant_id == ?1 AND id == ?2;";
+    const RESCHEDULE: &str =
+        "UPDATE orders SET ready_at = ?3 WHERE restaurant_id == ?1 AND id == ?2;";
+    const FINISH: &str =
+        "UPDATE orders SET finished_at = ?3 WHERE restaurant_id == ?1 AND id == ?2;";
+
+    let order_id = event.order_id;
+
+    match &event.change {
+        OrderChange::Placed {
+            item_id,
+            ready_at,
+            party_id,
+            prep_secs,
+            station,
+            quantity,
+            modifiers,
+            notes,
+            unit_price,
+            seat,
+        } => conn.execute(
+            PLACE,
+            params![
+                restaurant_id,
+                order_id,
+                event.table_id,
+                item_id,
+                &event.at,
+                ready_at,
+                party_id,
+                prep_secs.unwrap_or_else(|| events::legacy_prep_secs(&event.at, ready_at)),
+                to_sql_enum(station),
+                quantity,
+                to_sql_json(modifiers),
+                notes,
+                unit_price,
+                seat,
+            ],
+        ),
+        OrderChange::Removed {
+            removed_by: Some(removed_by),
+            reason: Some(reason),
+        } => conn.execute(
+            REMOVE,
+            (
+                restaurant_id,
+                order_id,
+                &event.at,
+                removed_by,
+                to_sql_enum(reason),
+            ),
+        ),
+        OrderChange::Removed { .. } => conn.execute(DELETE, [restaurant_id, order_id]),
+        OrderChange::Restored => conn.execute(RESTORE, [restaurant_id, order_id]),
+        OrderChange::Rescheduled { ready_at } => {
+            conn.execute(RESCHEDULE, (restaurant_id, order_id, ready_at))
+        }
+        OrderChange::StatusChanged { status } => conn.execute(
+            &format!(
+                "UPDATE orders SET status = ?3, {} = ?4 WHERE restaurant_id == ?1 AND id == ?2;",
+                status_column(*status)
+            ),
+            (restaurant_id, order_id, to_sql_enum(status), &event.at),
+        ),
+        OrderChange::Claimed { claimed_by } => {
+            conn.execute(CLAIM, (restaurant_id, order_id, &event.at, claimed_by))
+        }
+        OrderChange::Finished => conn.execute(FINISH, (restaurant_id, order_id, &event.at)),
+        OrderChange::Archived { .. } => conn.execute(DELETE, [restaurant_id, order_id]),
+    }
+    .map_err(handle_query_error)
+    .map(|_| ())
+}
+
+/// Stores a unit enum as its serde name, e.g. `kitchen_error`
+fn to_sql_enum<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => unreachable!("only used with unit enums"),
+    }
+}
+
+/// Reads back a unit enum stored with `to_sql_enum`
+fn from_sql_enum<T: DeserializeOwned>(name: String) -> rusqlite::Result<T> {
+    serde_json::from_value(serde_json::Value::String(name))
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e)))
+}
+
+/// Stores a list of values, e.g. modifiers, as JSON text
+fn to_sql_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("plain data serializes")
+}
+
+/// Reads back a value stored with `to_sql_json`
+fn from_sql_json<T: DeserializeOwned>(json: String) -> rusqlite::Result<T> {
+    serde_json::from_str(&json)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e)))
+}
+
+/// The `orders` column holding when an order entered `status`
+fn status_column(status: OrderStatus) -> &'static str {
+    match status {
+        OrderStatus::Placed => "ordered_at",
+        OrderStatus::InProgress => "started_at",
+        OrderStatus::Ready => "prepared_at",
+        OrderStatus::Served => "served_at",
+        OrderStatus::Cancelled => "cancelled_at",
+    }
+}
+
+/// When an order entered `status`, if it ever did
+fn status_entered_at(history: &[StatusChange], status: OrderStatus) -> Option<&String> {
+    history
+        .iter()
+        .find(|change| change.status == status)
+        .map(|change| &change.at)
+}
 
-    let status_code = match error {
-        SqliteFailure(_, _) => StatusCode::INTERNAL_SERVER_ERROR,
-        SqliteSingleThreadedMode => StatusCode::INTERNAL_SERVER_ERROR,
-        FromSqlConversionFailure(_, _, _) => StatusCode::INTERNAL_SERVER_ERROR,
-        IntegralValueOutOfRange(_, _) => StatusCode::BAD_REQUEST,
-        Utf8Error(_) => StatusCode::INTERNAL_SERVER_ERROR,
-        NulError(_) => StatusCode::BAD_REQUEST,
-        InvalidParameterName(_) => StatusCode::BAD_REQUEST,
-        InvalidPath(_) => StatusCode::INTERNAL_SERVER_ERROR,
-        ExecuteReturnedResults => StatusCode::INTERNAL_SERVER_ERROR,
-        QueryReturnedNoRows => StatusCode::NOT_FOUND,
-        InvalidColumnIndex(_) => StatusCode::INTERNAL_SERVER_ERROR,
-        InvalidColumnName(_) => StatusCode::INTERNAL_SERVER_ERROR,
-        InvalidColumnType(_, _, _) => StatusCode::INTERNAL_SERVER_ERROR,
-        StatementChangedRows(_) => StatusCode::INTERNAL_SERVER_ERROR,
-        ToSqlConversionFailure(_) => StatusCode::INTERNAL_SERVER_ERROR,
-        InvalidQuery => StatusCode::INTERNAL_SERVER_ERROR,
-        UnwindingPanic => StatusCode::INTERNAL_SERVER_ERROR,
-        MultipleStatement => StatusCode::BAD_REQUEST,
-        InvalidParameterCount(_, _) => StatusCode::BAD_REQUEST,
-        SqlInputError { .. } => StatusCode::BAD_REQUEST,
-        InvalidDatabaseIndex(_) => StatusCode::INTERNAL_SERVER_ERROR,
-        _ => StatusCode::INTERNAL_SERVER_ERROR,
+/// Reads an order's status history from its `ordered_at` column and the
+/// nullable `started_at, prepared_at, served_at, cancelled_at` columns.
+/// Statuses only move forward, so their order is always the same.
+fn status_history_from_row(
+    row: &rusqlite::Row,
+    ordered_at_column: usize,
+    first_column: usize,
+) -> rusqlite::Result<Vec<StatusChange>> {
+    let placed = StatusChange {
+        status: OrderStatus::Placed,
+        at: row.get(ordered_at_column)?,
     };
+    let later = [
+        OrderStatus::InProgress,
+        OrderStatus::Ready,
+        OrderStatus::Served,
+        OrderStatus::Cancelled,
+    ]
+    .into_iter()
+    .zip(first_column..)
+    .filter_map(|(status, column)| {
+        row.get::<_, Option<String>>(column)
+            .transpose()
+            .map(|at| at.map(|at| StatusChange { status, at }))
+    });
 
-    let body = error.to_string();
-    println!("Query Error: {body}");
+    std::iter::once(Ok(placed)).chain(later).collect()
+}
 
-    HttpError { status_code, body }
+/// Reads an order's removal details from three nullable columns
+fn removal_from_row(row: &rusqlite::Row, first_column: usize) -> rusqlite::Result<Option<Removal>> {
+    let removed_at: Option<String> = row.get(first_column)?;
+    let removed_by: Option<String> = row.get(first_column + 1)?;
+    let reason: Option<String> = row.get(first_column + 2)?;
+
+    match (removed_at, removed_by, reason) {
+        (Some(removed_at), Some(removed_by), Some(reason)) => Ok(Some(Removal {
+            removed_at,
+            removed_by,
+            reason: from_sql_enum(reason)?,
+        })),
+        _ => Ok(None),
+    }
 }
 
-/// Fetches the Menu table and returns all items
-pub async fn get_menu(connection: &Arc<Mutex<Connection>>) -> Result<Vec<MenuItem>, HttpError> {
-    const QUERY: &str = "SELECT * FROM menu;";
+/// Reads an order history entry from the columns `id, item_id, ordered_at,
+/// ready_at, removed_at, removed_by, removed_reason, party_id, finished_at,
+/// started_at, prepared_at, served_at, cancelled_at, prep_secs, station, claimed_by,
+/// quantity, modifiers, notes, unit_price, seat`
+fn history_entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<OrderHistoryEntry> {
+    let status_history = status_history_from_row(row, 2, 9)?;
+    Ok(OrderHistoryEntry {
+        order_id: row.get(0)?,
+        item_id: row.get(1)?,
+        ordered_at: row.get(2)?,
+        ready_at: row.get(3)?,
+        removal: removal_from_row(row, 4)?,
+        party_id: row.get(7)?,
+        finished_at: row.get(8)?,
+        status: events::current_status(&status_history),
+        status_history,
+        prep_secs: row.get(13)?,
+        station: from_sql_enum(row.get(14)?)?,
+        claimed_by: row.get(15)?,
+        quantity: row.get(16)?,
+        modifiers: from_sql_json(row.get(17)?)?,
+        notes: row.get(18)?,
+        unit_price: row.get(19)?,
+        seat: row.get(20)?,
+    })
+}
 
-    Ok(connection
-        .lock()
-        .await
-        .prepare(QUERY)
+/// Reads a restaurant's whole order event log, oldest first
+fn query_events(conn: &Connection, restaurant_id: i64) -> StorageResult<Vec<OrderEvent>> {
+    const QUERY: &str = "SELECT seq, at, order_id, table_id, change FROM order_events \
+        WHERE restaurant_id == ?1 ORDER BY seq;";
+
+    conn.prepare(QUERY)
         .map_err(handle_query_error)?
-        .query_map([], |row| {
-            Ok(MenuItem {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                prep_min_m: row.get(2)?,
-                prep_max_m: row.get(3)?,
+        .query_map([restaurant_id], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })
+        .map_err(handle_query_error)?
+        .map(|row| {
+            let (seq, at, order_id, table_id, change) = row.map_err(handle_query_error)?;
+            Ok(OrderEvent {
+                seq,
+                at,
+                order_id,
+                table_id,
+                change: parse_change(seq, &change)?,
             })
         })
+        .collect()
+}
+
+fn parse_change(seq: i64, change: &str) -> StorageResult<OrderChange> {
+    serde_json::from_str(change)
+        .map_err(|e| StorageError::Internal(format!("order event {seq} is unreadable: {e}")))
+}
+
+/// Where the live feed's tail starts: everything already committed counts as
+/// published
+fn query_tail_cursor(conn: &Connection) -> StorageResult<TailCursor> {
+    const QUERY: &str = "SELECT COALESCE(MAX(seq), 0) FROM order_events;";
+
+    Ok(TailCursor {
+        seq: conn
+            .query_row(QUERY, [], |row| row.get(0))
+            .map_err(handle_query_error)?,
+        menu_versions: query_menu_versions(conn)?,
+    })
+}
+
+/// Publishes the menu changes and order events committed since `cursor`,
+/// each order as it stands now, and returns how far it got
+fn publish_since(
+    conn: &mut Connection,
+    cursor: TailCursor,
+    live: &LiveFeed,
+) -> StorageResult<TailCursor> {
+    const EVENTS: &str = "SELECT seq, restaurant_id, order_id, table_id, change \
+        FROM order_events WHERE seq > ?1 ORDER BY seq;";
+    let order_query =
+        format!("SELECT {PROJECTION_COLUMNS} FROM orders WHERE restaurant_id == ?1 AND id == ?2;");
+
+    // One read transaction, so the orders match the events read
+    let tx = conn.transaction().map_err(handle_query_error)?;
+    let menu_versions = query_menu_versions(&tx)?;
+    if !live.is_followed() {
+        return Ok(TailCursor {
+            menu_versions,
+            ..query_tail_cursor(&tx)?
+        });
+    }
+
+    // Restaurants created since the last pass start at version 1 and are
+    // only announced once their menu has changed
+    menu_versions
+        .iter()
+        .filter(|&(restaurant_id, version)| {
+            cursor.menu_versions.get(restaurant_id).unwrap_or(&1) != version
+        })
+        .for_each(|(&restaurant_id, &version)| {
+            live.publish(restaurant_id, LiveEvent::MenuChanged { version })
+        });
+
+    let mut order_lookup = tx.prepare(&order_query).map_err(handle_query_error)?;
+    let mut seq = cursor.seq;
+    let mut events = tx.prepare(EVENTS).map_err(handle_query_error)?;
+    let mut rows = events.query([cursor.seq]).map_err(handle_query_error)?;
+    while let Some(row) = rows.next().map_err(handle_query_error)? {
+        let (restaurant_id, order_id, table_id): (i64, i64, i64) = (
+            row.get(1).map_err(handle_query_error)?,
+            row.get(2).map_err(handle_query_error)?,
+            row.get(3).map_err(handle_query_error)?,
+        );
+        seq = row.get(0).map_err(handle_query_error)?;
+        let change = parse_change(seq, &row.get::<_, String>(4).map_err(handle_query_error)?)?;
+
+        let order = order_lookup
+            .query_row([restaurant_id, order_id], projected_order_from_row)
+            .optional()
+            .map_err(handle_query_error)?
+            .map(|(_, order)| order);
+        if let Some(event) = live::order_event(order_id, table_id, &change, order.as_ref()) {
+            live.publish(restaurant_id, event);
+        }
+    }
+
+    Ok(TailCursor { seq, menu_versions })
+}
+
+/// Restaurant id -> menu version, for every restaurant
+fn query_menu_versions(conn: &Connection) -> StorageResult<HashMap<i64, i64>> {
+    const QUERY: &str = "SELECT restaurant_id, version FROM menu_version;";
+
+    conn.prepare(QUERY)
         .map_err(handle_query_error)?
-        .flatten()
-        .collect())
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(handle_query_error)?
+        .collect::<Result<_, _>>()
+        .map_err(handle_query_error)
 }
 
-/// Fetches all orders which match the passed in table id
-pub async fn get_tables_items(
-    connection: &Arc<Mutex<Connection>>,
-    table_id: i64,
-) -> Result<Vec<TableOrder>, HttpError> {
-    const QUERY: &str = "SELECT id, item_id, ready_at FROM orders WHERE table_id == ?1;";
+/// Reads a restaurant's orders in the same shape the event log produces
+fn query_projection(
+    conn: &Connection,
+    restaurant_id: i64,
+) -> StorageResult<BTreeMap<i64, ProjectedOrder>> {
+    let query = format!("SELECT {PROJECTION_COLUMNS} FROM orders WHERE restaurant_id == ?1;");
 
-    Ok(connection
-        .lock()
-        .await
-        .prepare(QUERY)
+    conn.prepare(&query)
         .map_err(handle_query_error)?
-        .query_map([table_id], |row| {
-            Ok(TableOrder {
-                order_id: row.get(0)?,
-                item_id: row.get(1)?,
-                ready_at: row.get(2)?,
-            })
+        .query_map([restaurant_id], projected_order_from_row)
+        .map_err(handle_query_error)?
+        .collect::<Result<_, _>>()
+        .map_err(handle_query_error)
+}
+
+const PROJECTION_COLUMNS: &str = "id, table_id, item_id, ordered_at, ready_at, \
+    removed_at, removed_by, removed_reason, party_id, finished_at, \
+    started_at, prepared_at, served_at, cancelled_at, prep_secs, station, claimed_by, \
+    quantity, modifiers, notes, unit_price, seat";
+
+/// Reads an order's id and projected state from `PROJECTION_COLUMNS`
+fn projected_order_from_row(row: &rusqlite::Row) -> rusqlite::Result<(i64, ProjectedOrder)> {
+    Ok((
+        row.get(0)?,
+        ProjectedOrder {
+            table_id: row.get(1)?,
+            item_id: row.get(2)?,
+            ordered_at: row.get(3)?,
+            ready_at: row.get(4)?,
+            removal: removal_from_row(row, 5)?,
+            party_id: row.get(8)?,
+            finished_at: row.get(9)?,
+            status_history: status_history_from_row(row, 3, 10)?,
+            prep_secs: row.get(14)?,
+            station: from_sql_enum(row.get(15)?)?,
+            claimed_by: row.get(16)?,
+            quantity: row.get(17)?,
+            modifiers: from_sql_json(row.get(18)?)?,
+            notes: row.get(19)?,
+            unit_price: row.get(20)?,
+            seat: row.get(21)?,
+        },
+    ))
+}
+
+/// The orders taking up a slot in the kitchen, now or once started. Mirrors
+/// `kitchen::is_booked`.
+const BOOKED: &str = "removed_at IS NULL AND status != 'cancelled' \
+    AND (status != 'placed' OR finished_at IS NULL)";
+
+/// The kitchen as it stands with every booked order in it, ready to queue
+/// more. Only the latest quotes matter, which the `ready_at` index finds
+/// without reading every order.
+fn query_kitchen(conn: &Connection, restaurant_id: i64) -> StorageResult<Kitchen> {
+    let query = format!(
+        "SELECT ready_at FROM orders WHERE restaurant_id == ?1 AND {BOOKED} \
+        ORDER BY ready_at DESC LIMIT ?2;"
+    );
+
+    let kitchen_slots = query_restaurant(conn, restaurant_id)?.kitchen_slots;
+    let quotes: Vec<String> = conn
+        .prepare(&query)
+        .map_err(handle_query_error)?
+        .query_map([restaurant_id, kitchen_slots], |row| row.get(0))
+        .map_err(handle_query_error)?
+        .collect::<Result<_, _>>()
+        .map_err(handle_query_error)?;
+
+    Ok(Kitchen::from_quotes(
+        kitchen_slots,
+        quotes.iter().map(String::as_str),
+    ))
+}
+
+/// Quotes every order still waiting for the kitchen again, after the queue
+/// ahead of them has changed. Only orders quoted from the oldest waiting
+/// order on can hold anything up, so older ones are left unread.
+fn reschedule(conn: &Connection, restaurant_id: i64) -> StorageResult<()> {
+    const HORIZON: &str = "SELECT MIN(ordered_at) FROM orders WHERE restaurant_id == ?1 \
+        AND removed_at IS NULL AND status == 'placed' AND finished_at IS NULL;";
+
+    let oldest_waiting: Option<String> = conn
+        .query_row(HORIZON, [restaurant_id], |row| row.get(0))
+        .map_err(handle_query_error)?;
+    let Some(horizon) = oldest_waiting
+        .as_deref()
+        .and_then(timing::parse_ready_at)
+        .map(timing::format_ready_at)
+    else {
+        return Ok(());
+    };
+
+    let query = format!(
+        "SELECT {PROJECTION_COLUMNS} FROM orders WHERE restaurant_id == ?1 AND {BOOKED} \
+        AND (ready_at >= ?2 OR status == 'placed');"
+    );
+    let orders: BTreeMap<i64, ProjectedOrder> = conn
+        .prepare(&query)
+        .map_err(handle_query_error)?
+        .query_map((restaurant_id, &horizon), projected_order_from_row)
+        .map_err(handle_query_error)?
+        .collect::<Result<_, _>>()
+        .map_err(handle_query_error)?;
+
+    let kitchen_slots = query_restaurant(conn, restaurant_id)?.kitchen_slots;
+    for (order_id, ready_at) in kitchen::reschedule(kitchen_slots, &orders) {
+        let table_id = orders[&order_id].table_id;
+        let change = OrderChange::Rescheduled { ready_at };
+        record_event(conn, restaurant_id, order_id, table_id, change)?;
+    }
+    Ok(())
+}
+
+fn query_menu_version(conn: &Connection, restaurant_id: i64) -> StorageResult<i64> {
+    const QUERY: &str = "SELECT version FROM menu_version WHERE restaurant_id == ?1;";
+
+    conn.query_row(QUERY, [restaurant_id], |row| row.get(0))
+        .map_err(handle_query_error)
+}
+
+fn bump_menu_version(conn: &Connection, restaurant_id: i64) -> StorageResult<()> {
+    const QUERY: &str = "UPDATE menu_version SET version = version + 1 WHERE restaurant_id == ?1;";
+
+    conn.execute(QUERY, [restaurant_id])
+        .map_err(handle_query_error)
+        .map(|_| ())
+}
+
+/// Fetches a single menu item, whether or not it has been retired
+fn query_menu_item(conn: &Connection, restaurant_id: i64, item_id: i64) -> StorageResult<MenuItem> {
+    const QUERY: &str = "SELECT id, name, prep_min_m, prep_max_m, station, modifiers, price \
+        FROM menu WHERE restaurant_id == ?1 AND id == ?2;";
+
+    conn.query_row(QUERY, [restaurant_id, item_id], menu_item_from_row)
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => {
+                StorageError::NotFound("menu item does not exist".to_string())
+            }
+            e => handle_query_error(e),
         })
+}
+
+fn table_from_row(row: &rusqlite::Row) -> rusqlite::Result<Table> {
+    Ok(Table {
+        id: row.get(0)?,
+        label: row.get(1)?,
+        section: row.get(2)?,
+        seats: row.get(3)?,
+        active: row.get(4)?,
+    })
+}
+
+/// Fetches a single registered table, active or not
+fn query_table(conn: &Connection, restaurant_id: i64, table_id: i64) -> StorageResult<Table> {
+    const QUERY: &str = "SELECT id, label, section, seats, active FROM tables \
+        WHERE restaurant_id == ?1 AND id == ?2;";
+
+    conn.query_row(QUERY, [restaurant_id, table_id], table_from_row)
+        .optional()
         .map_err(handle_query_error)?
-        .flatten()
-        .collect())
+        .ok_or_else(table_not_found)
+}
+
+fn restaurant_from_row(row: &rusqlite::Row) -> rusqlite::Result<Restaurant> {
+    Ok(Restaurant {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        time_zone: row.get(2)?,
+        kitchen_slots: row.get(3)?,
+        currency: row.get(4)?,
+        tax_rate_bps: row.get(5)?,
+    })
+}
+
+fn query_restaurant(conn: &Connection, restaurant_id: i64) -> StorageResult<Restaurant> {
+    const QUERY: &str = "SELECT id, name, time_zone, kitchen_slots, currency, tax_rate_bps \
+        FROM restaurants WHERE id == ?1;";
+
+    conn.query_row(QUERY, [restaurant_id], restaurant_from_row)
+        .optional()
+        .map_err(handle_query_error)?
+        .ok_or_else(restaurant_not_found)
+}
+
+fn party_from_row(row: &rusqlite::Row) -> rusqlite::Result<Party> {
+    Ok(Party {
+        id: row.get(0)?,
+        table_id: row.get(1)?,
+        guests: row.get(2)?,
+        seated_at: row.get(3)?,
+        closed_at: row.get(4)?,
+        check_split: from_sql_json(row.get(5)?)?,
+    })
 }
 
-/// Adds the passed in list of items onto the table's order, and returns
-/// the updated list of ordered items
-pub async fn order_items(
-    connection: &Arc<Mutex<Connection>>,
+/// Fetches a table's live orders which are to be paid for, oldest first
+fn query_bill_lines(
+    conn: &Connection,
+    restaurant_id: i64,
     table_id: i64,
-    items: OrderItemsRequest,
-) -> Result<Vec<TableOrder>, HttpError> {
-    let menu_items = menu_lookup(connection, &items.items).await?;
+) -> StorageResult<Vec<BillLine>> {
+    const QUERY: &str = "SELECT orders.id, orders.item_id, orders.ready_at, \
+        orders.ordered_at, orders.started_at, orders.prepared_at, orders.served_at, \
+        orders.cancelled_at, orders.station, orders.claimed_by, orders.quantity, \
+        orders.modifiers, orders.notes, orders.unit_price, orders.seat, COALESCE(menu.name, '') \
+        FROM orders LEFT JOIN menu ON menu.restaurant_id == orders.restaurant_id \
+        AND menu.id == orders.item_id \
+        WHERE orders.restaurant_id == ?1 AND orders.table_id == ?2 \
+        AND orders.removed_at IS NULL AND orders.finished_at IS NULL \
+        AND orders.status != 'cancelled' ORDER BY orders.id;";
 
-    // Item ID, PrepTime
-    let rows = items
-        .items
-        .iter()
-        .flat_map(|item_id| {
-            Some(format!(
-                "({table_id}, {item_id}, '{}')",
-                menu_items.get(item_id)?.get_random_prep_time()
-            ))
+    conn.prepare(QUERY)
+        .map_err(handle_query_error)?
+        .query_map([restaurant_id, table_id], |row| {
+            Ok(bill_line(table_order_from_row(row)?, row.get(15)?))
         })
-        .collect::<Vec<_>>()
-        .join(", ");
+        .map_err(handle_query_error)?
+        .collect::<Result<_, _>>()
+        .map_err(handle_query_error)
+}
+
+/// Reads a manager from the columns `id, name, active, pin_hash`
+fn manager_from_row(row: &rusqlite::Row) -> rusqlite::Result<ManagerRow> {
+    Ok(ManagerRow {
+        manager: Manager {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            active: row.get(2)?,
+        },
+        pin_hash: row.get(3)?,
+    })
+}
 
-    let query = format!("INSERT INTO orders (table_id, item_id, ready_at) VALUES {rows};");
+/// Reads an adjustment from the columns `id, party_id, table_id, order_id,
+/// kind, discount, reason, requested_by, approved_by, created_at`
+fn adjustment_from_row(row: &rusqlite::Row) -> rusqlite::Result<Adjustment> {
+    Ok(Adjustment {
+        id: row.get(0)?,
+        party_id: row.get(1)?,
+        table_id: row.get(2)?,
+        order_id: row.get(3)?,
+        kind: from_sql_enum(row.get(4)?)?,
+        discount: row
+            .get::<_, Option<String>>(5)?
+            .map(from_sql_json)
+            .transpose()?,
+        reason: row.get(6)?,
+        requested_by: row.get(7)?,
+        approved_by: row.get(8)?,
+        created_at: row.get(9)?,
+    })
+}
 
-    let _ = connection
-        .lock()
-        .await
-        .execute(&query, [])
+/// An adjustment's columns in the order `INSERT INTO adjustments` lists them
+fn adjustment_params(restaurant_id: i64, adjustment: &Adjustment) -> impl rusqlite::Params + '_ {
+    (
+        restaurant_id,
+        adjustment.id,
+        adjustment.party_id,
+        adjustment.table_id,
+        adjustment.order_id,
+        to_sql_enum(&adjustment.kind),
+        adjustment.discount.as_ref().map(to_sql_json),
+        &adjustment.reason,
+        &adjustment.requested_by,
+        &adjustment.approved_by,
+        &adjustment.created_at,
+    )
+}
+
+fn insert_adjustment(
+    conn: &Connection,
+    query: &str,
+    restaurant_id: i64,
+    adjustment: &Adjustment,
+) -> StorageResult<()> {
+    conn.execute(query, adjustment_params(restaurant_id, adjustment))
         .map_err(handle_query_error)?;
+    Ok(())
+}
+
+/// Fetches every adjustment made to a party's bill, oldest first
+fn query_party_adjustments(
+    conn: &Connection,
+    restaurant_id: i64,
+    party_id: i64,
+) -> StorageResult<Vec<Adjustment>> {
+    const QUERY: &str = "SELECT id, party_id, table_id, order_id, kind, discount, reason, \
+        requested_by, approved_by, created_at FROM adjustments \
+        WHERE restaurant_id == ?1 AND party_id == ?2 ORDER BY id;";
+
+    conn.prepare(QUERY)
+        .map_err(handle_query_error)?
+        .query_map([restaurant_id, party_id], adjustment_from_row)
+        .map_err(handle_query_error)?
+        .collect::<Result<_, _>>()
+        .map_err(handle_query_error)
+}
+
+/// Reads a payment from the columns `id, party_id, table_id, tender, amount,
+/// tip, status, reference, failure, created_at, updated_at`
+fn payment_from_row(row: &rusqlite::Row) -> rusqlite::Result<Payment> {
+    Ok(Payment {
+        id: row.get(0)?,
+        party_id: row.get(1)?,
+        table_id: row.get(2)?,
+        tender: from_sql_enum(row.get(3)?)?,
+        amount: row.get(4)?,
+        tip: row.get(5)?,
+        status: from_sql_enum(row.get(6)?)?,
+        reference: row.get(7)?,
+        failure: row.get(8)?,
+        created_at: row.get(9)?,
+        updated_at: row.get(10)?,
+    })
+}
+
+/// A payment's columns in the order `INSERT INTO payments` lists them
+fn payment_params(restaurant_id: i64, payment: &Payment) -> impl rusqlite::Params + '_ {
+    (
+        restaurant_id,
+        payment.id,
+        payment.party_id,
+        payment.table_id,
+        to_sql_enum(&payment.tender),
+        payment.amount,
+        payment.tip,
+        to_sql_enum(&payment.status),
+        &payment.reference,
+        &payment.failure,
+        &payment.created_at,
+        &payment.updated_at,
+    )
+}
+
+fn query_payment(conn: &Connection, restaurant_id: i64, payment_id: i64) -> StorageResult<Payment> {
+    const QUERY: &str = "SELECT id, party_id, table_id, tender, amount, tip, status, reference, \
+        failure, created_at, updated_at FROM payments WHERE restaurant_id == ?1 AND id == ?2;";
+
+    conn.query_row(QUERY, [restaurant_id, payment_id], payment_from_row)
+        .optional()
+        .map_err(handle_query_error)?
+        .ok_or_else(payment_not_found)
+}
 
-    get_tables_items(connection, table_id).await
+/// Fetches every payment tried for a party's bill, oldest first
+fn query_party_payments(
+    conn: &Connection,
+    restaurant_id: i64,
+    party_id: i64,
+) -> StorageResult<Vec<Payment>> {
+    const QUERY: &str = "SELECT id, party_id, table_id, tender, amount, tip, status, reference, \
+        failure, created_at, updated_at FROM payments \
+        WHERE restaurant_id == ?1 AND party_id == ?2 ORDER BY id;";
+
+    conn.prepare(QUERY)
+        .map_err(handle_query_error)?
+        .query_map([restaurant_id, party_id], payment_from_row)
+        .map_err(handle_query_error)?
+        .collect::<Result<_, _>>()
+        .map_err(handle_query_error)
 }
 
-/// Deletes the order, as long as the item and table id are correct
-pub async fn delete_table_item(
-    connection: &Arc<Mutex<Connection>>,
+/// What the party seated at the table owes, before any payments
+fn query_party_bill(
+    conn: &Connection,
+    restaurant_id: i64,
     table_id: i64,
-    order_id: i64,
-) -> Result<Vec<TableOrder>, HttpError> {
-    const QUERY: &str = "DELETE FROM orders WHERE id == ?1 AND table_id == ?2;";
-
-    // Fun story: This caused a deadlock
-    // match connection
-    //     .lock()
-    //     .await
-    //     .execute(QUERY, [order_id, table_id])
-    //     .map_err(handle_query_error)?
-    // {
-    //     // Tried to delete a non-existing row
-    //     0 => Err(HttpError {
-    //         status_code: StatusCode::NOT_FOUND,
-    //         body: "order id does not exist".to_string(),
-    //     }),
-
-    //     // Row deleted successfully, return the remaining rows
-    //     _ => get_tables_items(connection, table_id).await,
-    // }
-
-    let updated_rows = connection
-        .lock()
-        .await
-        .execute(QUERY, [order_id, table_id])
-        .map_err(handle_query_error)?;
+    party_id: i64,
+) -> StorageResult<Bill> {
+    Ok(billing::bill(
+        table_id,
+        &query_restaurant(conn, restaurant_id)?,
+        query_bill_lines(conn, restaurant_id, table_id)?,
+        query_party_adjustments(conn, restaurant_id, party_id)?,
+    ))
+}
 
-    match updated_rows {
-        // Tried to delete a non-existing row
-        0 => Err(HttpError {
-            status_code: StatusCode::NOT_FOUND,
-            body: "order id does not exist".to_string(),
-        }),
+/// Closes an open party once it has settled up, finishing its live orders,
+/// and returns when it was closed. The caller reschedules the kitchen.
+fn close_settled_party(
+    conn: &Connection,
+    restaurant_id: i64,
+    party: &Party,
+) -> StorageResult<String> {
+    const LIVE_ORDERS: &str = "SELECT id FROM orders WHERE restaurant_id == ?1 \
+        AND party_id == ?2 AND removed_at IS NULL AND finished_at IS NULL ORDER BY id;";
+    const CLOSE: &str = "UPDATE parties SET closed_at = ?3 WHERE restaurant_id == ?1 AND id == ?2;";
 
-        // Row deleted successfully, return the remaining rows
-        _ => get_tables_items(connection, table_id).await,
+    check_settled(
+        &query_party_bill(conn, restaurant_id, party.table_id, party.id)?,
+        &query_party_payments(conn, restaurant_id, party.id)?,
+    )?;
+
+    let finished = conn
+        .prepare(LIVE_ORDERS)
+        .map_err(handle_query_error)?
+        .query_map([restaurant_id, party.id], |row| row.get::<_, i64>(0))
+        .map_err(handle_query_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(handle_query_error)?;
+    for order_id in finished {
+        let change = OrderChange::Finished;
+        record_event(conn, restaurant_id, order_id, party.table_id, change)?;
     }
+
+    let closed_at = events::now();
+    conn.execute(CLOSE, (restaurant_id, party.id, &closed_at))
+        .map_err(handle_query_error)?;
+    Ok(closed_at)
+}
+
+/// Fetches a single party, open or closed
+fn query_party(conn: &Connection, restaurant_id: i64, party_id: i64) -> StorageResult<Party> {
+    const QUERY: &str =
+        "SELECT id, table_id, guests, seated_at, closed_at, check_split FROM parties \
+        WHERE restaurant_id == ?1 AND id == ?2;";
+
+    conn.query_row(QUERY, [restaurant_id, party_id], party_from_row)
+        .optional()
+        .map_err(handle_query_error)?
+        .ok_or_else(party_not_found)
 }
 
-/// Fetches a single order for the given order and table id.
-pub async fn get_table_item(
-    connetion: &Arc<Mutex<Connection>>,
+/// Fetches the party currently seated at a table, if any
+fn query_open_party(
+    conn: &Connection,
+    restaurant_id: i64,
     table_id: i64,
-    order_id: i64,
-) -> Result<TableOrder, HttpError> {
+) -> StorageResult<Option<Party>> {
     const QUERY: &str =
-        "SELECT id, item_id, ready_at FROM orders WHERE id == ?1 AND table_id == ?2;";
+        "SELECT id, table_id, guests, seated_at, closed_at, check_split FROM parties \
+        WHERE restaurant_id == ?1 AND table_id == ?2 AND closed_at IS NULL;";
 
-    connetion
-        .lock()
-        .await
-        .query_row(QUERY, [order_id, table_id], |row| {
-            Ok(TableOrder {
-                order_id: row.get(0)?,
-                item_id: row.get(1)?,
-                ready_at: row.get(2)?,
-            })
-        })
+    conn.query_row(QUERY, [restaurant_id, table_id], party_from_row)
+        .optional()
         .map_err(handle_query_error)
 }
 
-struct MenuItemRow {
-    prep_min_m: f64,
-    prep_max_m: f64,
+/// Fetches the Menu table and returns all of a restaurant's items which
+/// haven't been retired
+fn query_menu(conn: &Connection, restaurant_id: i64) -> StorageResult<Vec<MenuItem>> {
+    const QUERY: &str = "SELECT id, name, prep_min_m, prep_max_m, station, modifiers, price \
+        FROM menu WHERE restaurant_id == ?1 AND active == 1;";
+
+    Ok(conn
+        .prepare(QUERY)
+        .map_err(handle_query_error)?
+        .query_map([restaurant_id], menu_item_from_row)
+        .map_err(handle_query_error)?
+        .flatten()
+        .collect())
 }
 
-impl MenuItemRow {
-    fn get_random_prep_time(&self) -> String {
-        let range = self.prep_max_m - self.prep_min_m;
-        let mins = self.prep_min_m + fastrand::f64() * range;
-        let secs = mins.round() as i64;
+/// Reads a menu item from the columns `id, name, prep_min_m, prep_max_m, station,
+/// modifiers, price`
+fn menu_item_from_row(row: &rusqlite::Row) -> rusqlite::Result<MenuItem> {
+    Ok(MenuItem {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        prep_min_m: row.get(2)?,
+        prep_max_m: row.get(3)?,
+        station: from_sql_enum(row.get(4)?)?,
+        modifiers: from_sql_json(row.get(5)?)?,
+        price: row.get(6)?,
+    })
+}
 
-        (chrono::Utc::now() + Duration::seconds(secs))
-            .format("%d/%m/%Y %H:%M")
-            .to_string()
-    }
+/// Reads a table order from the columns `id, item_id, ready_at, ordered_at,
+/// started_at, prepared_at, served_at, cancelled_at, station, claimed_by,
+/// quantity, modifiers, notes, unit_price, seat`
+fn table_order_from_row(row: &rusqlite::Row) -> rusqlite::Result<TableOrder> {
+    let status_history = status_history_from_row(row, 3, 4)?;
+    Ok(TableOrder {
+        order_id: row.get(0)?,
+        item_id: row.get(1)?,
+        quantity: row.get(10)?,
+        modifiers: from_sql_json(row.get(11)?)?,
+        notes: row.get(12)?,
+        unit_price: row.get(13)?,
+        seat: row.get(14)?,
+        ready_at: row.get(2)?,
+        // Filled in for the restaurant's time zone as the response is sent
+        ready_at_local: String::new(),
+        remaining_secs: 0,
+        overdue: false,
+        status: events::current_status(&status_history),
+        status_history,
+        station: from_sql_enum(row.get(8)?)?,
+        claimed_by: row.get(9)?,
+    })
+}
+
+/// Fetches all orders which match the passed in table id and haven't been removed
+fn query_tables_items(
+    conn: &Connection,
+    restaurant_id: i64,
+    table_id: i64,
+) -> StorageResult<Vec<TableOrder>> {
+    const QUERY: &str = "SELECT id, item_id, ready_at, ordered_at, started_at, prepared_at, \
+        served_at, cancelled_at, station, claimed_by, quantity, modifiers, notes, unit_price, \
+        seat FROM orders \
+        WHERE restaurant_id == ?1 \
+        AND table_id == ?2 AND removed_at IS NULL AND finished_at IS NULL;";
+
+    Ok(conn
+        .prepare(QUERY)
+        .map_err(handle_query_error)?
+        .query_map([restaurant_id, table_id], table_order_from_row)
+        .map_err(handle_query_error)?
+        .flatten()
+        .collect())
+}
+
+/// Fetches a single order of the table which is still live
+fn query_table_item(
+    conn: &Connection,
+    restaurant_id: i64,
+    table_id: i64,
+    order_id: i64,
+) -> StorageResult<TableOrder> {
+    const QUERY: &str = "SELECT id, item_id, ready_at, ordered_at, started_at, prepared_at, \
+        served_at, cancelled_at, station, claimed_by, quantity, modifiers, notes, unit_price, \
+        seat FROM orders \
+        WHERE restaurant_id == ?1 \
+        AND id == ?2 AND table_id == ?3 AND removed_at IS NULL AND finished_at IS NULL;";
+
+    conn.query_row(
+        QUERY,
+        [restaurant_id, order_id, table_id],
+        table_order_from_row,
+    )
+    .optional()
+    .map_err(handle_query_error)?
+    .ok_or_else(|| StorageError::NotFound("order id does not exist".to_string()))
+}
+
+/// Selects the columns read by [`station_line_from_row`]
+const STATION_LINE_SELECT: &str = "SELECT orders.id, orders.item_id, orders.ready_at, \
+    orders.ordered_at, orders.started_at, orders.prepared_at, orders.served_at, \
+    orders.cancelled_at, orders.station, orders.claimed_by, orders.quantity, orders.modifiers, \
+    orders.notes, orders.unit_price, orders.seat, orders.table_id, \
+    COALESCE(tables.label, ''), COALESCE(menu.name, '') FROM orders \
+    LEFT JOIN tables ON tables.restaurant_id == orders.restaurant_id \
+    AND tables.id == orders.table_id \
+    LEFT JOIN menu ON menu.restaurant_id == orders.restaurant_id AND menu.id == orders.item_id";
+
+/// Matches every line still waiting at station `?2`: live orders which are
+/// placed or being made
+const STATION_WAITING: &str = "WHERE orders.restaurant_id == ?1 AND orders.station == ?2 \
+    AND orders.removed_at IS NULL AND orders.finished_at IS NULL \
+    AND orders.status IN ('placed', 'in_progress')";
+
+/// Reads a station line from the table order columns followed by
+/// `table_id, table_label, item_name`
+fn station_line_from_row(row: &rusqlite::Row) -> rusqlite::Result<StationLine> {
+    Ok(StationLine {
+        table_id: row.get(15)?,
+        table_label: row.get(16)?,
+        item_name: row.get(17)?,
+        order: table_order_from_row(row)?,
+    })
+}
+
+/// Fetches an order which is still waiting at the station
+fn query_station_line(
+    conn: &Connection,
+    restaurant_id: i64,
+    station: Station,
+    order_id: i64,
+) -> StorageResult<StationLine> {
+    let query = format!("{STATION_LINE_SELECT} {STATION_WAITING} AND orders.id == ?3;");
+
+    conn.query_row(
+        &query,
+        (restaurant_id, to_sql_enum(&station), order_id),
+        station_line_from_row,
+    )
+    .optional()
+    .map_err(handle_query_error)?
+    .ok_or_else(line_not_found)
 }
 
-async fn menu_lookup(
-    connection: &Arc<Mutex<Connection>>,
+/// Looks up the prep times, station, modifiers and price of every distinct
+/// item in `item_ids`. Ids that aren't on the menu, or have been retired, are missing
+/// from the returned map.
+fn menu_lookup(
+    conn: &Connection,
+    restaurant_id: i64,
     item_ids: &[i64],
-) -> Result<HashMap<i64, MenuItemRow>, HttpError> {
+) -> StorageResult<HashMap<i64, MenuItemRow>> {
+    const QUERY: &str = "SELECT prep_min_m, prep_max_m, station, modifiers, price FROM menu \
+        WHERE restaurant_id == ?1 AND id == ?2 AND active == 1;";
+
     // Users may order multiple of the same item,
     // so we need to dedup the list.
-    let mut ids = item_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+    let mut ids = item_ids.to_vec();
     ids.sort_unstable();
     ids.dedup();
 
-    let ids = ids.join(", ");
+    let mut lookup = conn.prepare(QUERY).map_err(handle_query_error)?;
 
-    let query = format!("SELECT id, prep_min_m, prep_max_m FROM menu WHERE id IN ({ids})");
-
-    Ok(connection
-        .lock()
-        .await
-        .prepare(&query)
-        .map_err(handle_query_error)?
-        .query_map([], |row| {
-            Ok((
-                row.get(0)?,
-                MenuItemRow {
-                    prep_min_m: row.get(1)?,
-                    prep_max_m: row.get(2)?,
-                },
-            ))
+    ids.into_iter()
+        .filter_map(|id| {
+            lookup
+                .query_row([restaurant_id, id], |row| {
+                    Ok(MenuItemRow {
+                        prep_min_m: row.get(0)?,
+                        prep_max_m: row.get(1)?,
+                        station: from_sql_enum(row.get(2)?)?,
+                        modifiers: from_sql_json(row.get(3)?)?,
+                        price: row.get(4)?,
+                    })
+                })
+                .optional()
+                .map_err(handle_query_error)
+                .transpose()
+                .map(|row| row.map(|row| (id, row)))
         })
-        .map_err(handle_query_error)?
-        .flatten()
-        .collect())
+        .collect()
 }
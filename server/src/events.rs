@@ -0,0 +1,379 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use shared::{
+    Modifier, OrderStatus, Removal, RemovalReason, Station, StatusChange, TableHistory, TableOrder,
+};
+
+use crate::timing;
+
+/// One entry of the append-only order log. The `orders` table is a projection
+/// of these events and can always be rebuilt from them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderEvent {
+    /// Position in the log, strictly increasing
+    pub seq: i64,
+    /// When the change happened, as an RFC 3339 UTC timestamp
+    pub at: String,
+    pub order_id: i64,
+    pub table_id: i64,
+    pub change: OrderChange,
+}
+
+/// What happened to an order. Stored as JSON, so new kinds of change can be
+/// added without touching the log's schema.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum OrderChange {
+    Placed {
+        item_id: i64,
+        ready_at: String,
+        /// Orders placed before parties existed have none
+        #[serde(default)]
+        party_id: Option<i64>,
+        /// How long the order takes to cook. Orders placed before the kitchen
+        /// was scheduled have none, and count the time until `ready_at`.
+        #[serde(default)]
+        prep_secs: Option<i64>,
+        /// Orders placed before items had a station were all made at the grill
+        #[serde(default)]
+        station: Station,
+        /// Orders placed before orders had lines were for a single item, as it
+        /// came, with no notes
+        #[serde(default = "shared::default_quantity")]
+        quantity: i64,
+        #[serde(default)]
+        modifiers: Vec<Modifier>,
+        #[serde(default)]
+        notes: Option<String>,
+        /// Price of one of the line, frozen as it was placed. Orders placed
+        /// before items had a price were free.
+        #[serde(default)]
+        unit_price: i64,
+        #[serde(default)]
+        seat: Option<i64>,
+    },
+    /// Events logged before removals were recorded have no details. Those
+    /// orders were deleted outright, so replaying them deletes the order too.
+    Removed {
+        #[serde(default)]
+        removed_by: Option<String>,
+        #[serde(default)]
+        reason: Option<RemovalReason>,
+    },
+    Restored,
+    /// The kitchen's queue changed ahead of the order, so it was quoted again
+    Rescheduled {
+        ready_at: String,
+    },
+    /// The kitchen moved the order on, e.g. from placed to in_progress
+    StatusChanged {
+        status: OrderStatus,
+    },
+    /// Someone at the order's station took it on, moving it to in_progress
+    Claimed {
+        claimed_by: String,
+    },
+    /// The order's party was closed, so it no longer shows for the table
+    Finished,
+    /// Moved out of the live orders by an end-of-day close-out
+    Archived {
+        business_date: String,
+    },
+}
+
+/// An order's current state, as derived from its events
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectedOrder {
+    pub table_id: i64,
+    pub item_id: i64,
+    pub ordered_at: String,
+    pub ready_at: String,
+    pub removal: Option<Removal>,
+    pub party_id: Option<i64>,
+    pub finished_at: Option<String>,
+    /// Starts with `placed` at `ordered_at`
+    pub status_history: Vec<StatusChange>,
+    pub prep_secs: i64,
+    pub station: Station,
+    pub claimed_by: Option<String>,
+    pub quantity: i64,
+    pub modifiers: Vec<Modifier>,
+    pub notes: Option<String>,
+    pub unit_price: i64,
+    pub seat: Option<i64>,
+}
+
+impl ProjectedOrder {
+    /// Whether the order still shows for its table
+    pub fn is_live(&self) -> bool {
+        self.removal.is_none() && self.finished_at.is_none()
+    }
+
+    pub fn status(&self) -> OrderStatus {
+        current_status(&self.status_history)
+    }
+
+    pub fn to_table_order(&self, order_id: i64) -> TableOrder {
+        TableOrder {
+            order_id,
+            item_id: self.item_id,
+            quantity: self.quantity,
+            modifiers: self.modifiers.clone(),
+            notes: self.notes.clone(),
+            unit_price: self.unit_price,
+            seat: self.seat,
+            ready_at: self.ready_at.clone(),
+            // Filled in for the restaurant's time zone as the response is sent
+            ready_at_local: String::new(),
+            remaining_secs: 0,
+            overdue: false,
+            status: self.status(),
+            status_history: self.status_history.clone(),
+            station: self.station,
+            claimed_by: self.claimed_by.clone(),
+        }
+    }
+}
+
+/// The status an order is in, given every status it has been in
+pub fn current_status(status_history: &[StatusChange]) -> OrderStatus {
+    status_history
+        .last()
+        .map_or(OrderStatus::Placed, |change| change.status)
+}
+
+/// The prep time of an order placed before the kitchen was scheduled: the
+/// whole seconds from when it was placed until it was quoted for
+pub fn legacy_prep_secs(ordered_at: &str, ready_at: &str) -> i64 {
+    let parse = |at: &str| chrono::DateTime::parse_from_rfc3339(at).map(|at| at.timestamp());
+    match (parse(ordered_at), parse(ready_at)) {
+        (Ok(ordered_at), Ok(ready_at)) => (ready_at - ordered_at).max(0),
+        _ => 0,
+    }
+}
+
+/// Applies a single event on top of the current state of every order
+pub fn apply(orders: &mut BTreeMap<i64, ProjectedOrder>, event: &OrderEvent) {
+    match &event.change {
+        OrderChange::Placed {
+            item_id,
+            ready_at,
+            party_id,
+            prep_secs,
+            station,
+            quantity,
+            modifiers,
+            notes,
+            unit_price,
+            seat,
+        } => {
+            orders.insert(
+                event.order_id,
+                ProjectedOrder {
+                    table_id: event.table_id,
+                    item_id: *item_id,
+                    ordered_at: event.at.clone(),
+                    ready_at: ready_at.clone(),
+                    removal: None,
+                    party_id: *party_id,
+                    finished_at: None,
+                    status_history: vec![StatusChange {
+                        status: OrderStatus::Placed,
+                        at: event.at.clone(),
+                    }],
+                    prep_secs: prep_secs.unwrap_or_else(|| legacy_prep_secs(&event.at, ready_at)),
+                    station: *station,
+                    claimed_by: None,
+                    quantity: *quantity,
+                    modifiers: modifiers.clone(),
+                    notes: notes.clone(),
+                    unit_price: *unit_price,
+                    seat: *seat,
+                },
+            );
+        }
+        OrderChange::Removed {
+            removed_by: Some(removed_by),
+            reason: Some(reason),
+        } => {
+            if let Some(order) = orders.get_mut(&event.order_id) {
+                order.removal = Some(Removal {
+                    removed_at: event.at.clone(),
+                    removed_by: removed_by.clone(),
+                    reason: *reason,
+                });
+            }
+        }
+        OrderChange::Removed { .. } => {
+            orders.remove(&event.order_id);
+        }
+        OrderChange::Restored => {
+            if let Some(order) = orders.get_mut(&event.order_id) {
+                order.removal = None;
+            }
+        }
+        OrderChange::Rescheduled { ready_at } => {
+            if let Some(order) = orders.get_mut(&event.order_id) {
+                order.ready_at = ready_at.clone();
+            }
+        }
+        OrderChange::StatusChanged { status } => {
+            if let Some(order) = orders.get_mut(&event.order_id) {
+                order.status_history.push(StatusChange {
+                    status: *status,
+                    at: event.at.clone(),
+                });
+            }
+        }
+        OrderChange::Claimed { claimed_by } => {
+            if let Some(order) = orders.get_mut(&event.order_id) {
+                order.status_history.push(StatusChange {
+                    status: OrderStatus::InProgress,
+                    at: event.at.clone(),
+                });
+                order.claimed_by = Some(claimed_by.clone());
+            }
+        }
+        OrderChange::Finished => {
+            if let Some(order) = orders.get_mut(&event.order_id) {
+                order.finished_at = Some(event.at.clone());
+            }
+        }
+        OrderChange::Archived { .. } => {
+            orders.remove(&event.order_id);
+        }
+    }
+}
+
+/// Whether an order removed at `removal` may still be restored, `window`
+/// after the fact at the latest
+pub fn can_restore(removal: &Removal, window: chrono::Duration) -> bool {
+    chrono::DateTime::parse_from_rfc3339(&removal.removed_at)
+        .map(|removed_at| chrono::Utc::now() <= removed_at + window)
+        .unwrap_or(false)
+}
+
+/// Replays the log from the start to work out the current state of every order
+pub fn project<'a>(
+    events: impl IntoIterator<Item = &'a OrderEvent>,
+) -> BTreeMap<i64, ProjectedOrder> {
+    events
+        .into_iter()
+        .fold(BTreeMap::new(), |mut orders, event| {
+            apply(&mut orders, event);
+            orders
+        })
+}
+
+/// Describes every difference between the orders the log says should exist
+/// and the orders actually stored
+pub fn diff(
+    expected: &BTreeMap<i64, ProjectedOrder>,
+    actual: &BTreeMap<i64, ProjectedOrder>,
+) -> Vec<String> {
+    let missing_or_different =
+        expected
+            .iter()
+            .filter_map(|(order_id, order)| match actual.get(order_id) {
+                None => Some(format!("order {order_id} is missing")),
+                Some(stored) if stored != order => Some(format!(
+                    "order {order_id} is stored as {stored:?} but the log says {order:?}"
+                )),
+                Some(_) => None,
+            });
+
+    let unexpected = actual
+        .keys()
+        .filter(|order_id| !expected.contains_key(order_id))
+        .map(|order_id| format!("order {order_id} is not in the log"));
+
+    missing_or_different.chain(unexpected).collect()
+}
+
+/// Writes a fresh event log which projects to exactly the orders in `tables`:
+/// each order is placed, moved through its status history, then removed again
+/// if it is currently removed, and finished if its party has left. Legacy
+/// `ready_at` values are rewritten as RFC 3339.
+/// Events are numbered from 1 in the order they happened.
+pub fn from_history(tables: &[TableHistory]) -> Vec<OrderEvent> {
+    let mut events = tables
+        .iter()
+        .flat_map(|table| {
+            table.orders.iter().flat_map(|order| {
+                let placed = OrderEvent {
+                    seq: 0,
+                    at: order.ordered_at.clone(),
+                    order_id: order.order_id,
+                    table_id: table.table_id,
+                    change: OrderChange::Placed {
+                        item_id: order.item_id,
+                        ready_at: timing::normalize_ready_at(&order.ready_at),
+                        party_id: order.party_id,
+                        prep_secs: order.prep_secs,
+                        station: order.station,
+                        quantity: order.quantity,
+                        modifiers: order.modifiers.clone(),
+                        notes: order.notes.clone(),
+                        unit_price: order.unit_price,
+                        seat: order.seat,
+                    },
+                };
+                // The first entry is the order being placed. An order someone
+                // claimed was moved to in_progress by that claim.
+                let status_changes = order
+                    .status_history
+                    .iter()
+                    .skip(1)
+                    .map(|change| OrderEvent {
+                        seq: 0,
+                        at: change.at.clone(),
+                        order_id: order.order_id,
+                        table_id: table.table_id,
+                        change: match (change.status, &order.claimed_by) {
+                            (OrderStatus::InProgress, Some(claimed_by)) => OrderChange::Claimed {
+                                claimed_by: claimed_by.clone(),
+                            },
+                            (status, _) => OrderChange::StatusChanged { status },
+                        },
+                    });
+                let removed = order.removal.as_ref().map(|removal| OrderEvent {
+                    seq: 0,
+                    at: removal.removed_at.clone(),
+                    order_id: order.order_id,
+                    table_id: table.table_id,
+                    change: OrderChange::Removed {
+                        removed_by: Some(removal.removed_by.clone()),
+                        reason: Some(removal.reason),
+                    },
+                });
+                let finished = order.finished_at.as_ref().map(|finished_at| OrderEvent {
+                    seq: 0,
+                    at: finished_at.clone(),
+                    order_id: order.order_id,
+                    table_id: table.table_id,
+                    change: OrderChange::Finished,
+                });
+                std::iter::once(placed)
+                    .chain(status_changes)
+                    .chain(removed)
+                    .chain(finished)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // Timestamps are compared parsed, as older ones use a different offset
+    // format. The sort is stable, so an order is always placed before its
+    // status changes, removal or finish even if they happened at the same instant.
+    events.sort_by_key(|event| chrono::DateTime::parse_from_rfc3339(&event.at).ok());
+    events
+        .iter_mut()
+        .zip(1..)
+        .for_each(|(event, seq)| event.seq = seq);
+
+    events
+}
+
+pub fn now() -> String {
+    chrono::Utc::now().to_rfc3339()
+}
@@ -0,0 +1,307 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+
+use chrono::{DateTime, Duration, Utc};
+use shared::OrderStatus;
+
+use crate::events::ProjectedOrder;
+use crate::timing;
+
+/// A kitchen which cooks a fixed number of orders at once. Orders are dealt
+/// oldest first into whichever slot frees up first, so every quote is the
+/// time the order's slot frees up plus its prep time.
+pub struct Kitchen {
+    /// When each slot is next free
+    slots: BinaryHeap<Reverse<DateTime<Utc>>>,
+}
+
+/// How an order takes part in the kitchen's schedule
+enum Booking {
+    /// Removed, cancelled, or finished before it was started: it needs no
+    /// cooking
+    None,
+    /// Being cooked or already done, so it holds a slot until its quote
+    Held(DateTime<Utc>),
+    /// Still waiting, so it is dealt a slot in turn
+    Waiting {
+        ordered_at: DateTime<Utc>,
+        prep_secs: i64,
+    },
+}
+
+impl Kitchen {
+    /// A kitchen whose slots are busy until the given times. Any slot not
+    /// given a time is free straight away.
+    pub fn new(slots: i64, busy_until: impl IntoIterator<Item = DateTime<Utc>>) -> Self {
+        let slot_count = slots.max(1) as usize;
+        let slots = busy_until
+            .into_iter()
+            .chain(std::iter::repeat(DateTime::<Utc>::MIN_UTC))
+            .take(slot_count)
+            .map(Reverse)
+            .collect();
+
+        Self { slots }
+    }
+
+    /// The kitchen as it stands once every booked order has had its slot.
+    /// Orders always go to the slot which frees up first, so each slot is
+    /// busy until one of the latest quotes, one per slot.
+    pub fn from_quotes<'a>(slots: i64, quotes: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut quotes = quotes.into_iter().collect::<Vec<_>>();
+        let slot_count = (slots.max(1) as usize).min(quotes.len());
+        if slot_count > 0 && slot_count < quotes.len() {
+            quotes.select_nth_unstable_by(slot_count - 1, |a, b| b.cmp(a));
+        }
+
+        let busy_until = quotes[..slot_count]
+            .iter()
+            .filter_map(|quote| timing::parse_ready_at(quote));
+        Self::new(slots, busy_until)
+    }
+
+    /// Queues an order which hasn't started and returns when it will be ready.
+    /// Quotes are whole seconds, the same as stored, so a quote worked out
+    /// again later comes out the same.
+    pub fn queue(&mut self, ordered_at: DateTime<Utc>, prep_secs: i64) -> DateTime<Utc> {
+        let mut slot = self
+            .slots
+            .peek_mut()
+            .expect("a kitchen has at least one slot");
+        let start = slot.0.max(ordered_at);
+        let ready_at = whole_seconds(start + Duration::seconds(prep_secs));
+
+        slot.0 = ready_at;
+        ready_at
+    }
+
+    /// Holds the first free slot until `ready_at`, for an order already past
+    /// the queue
+    fn hold(&mut self, ready_at: DateTime<Utc>) {
+        let mut slot = self
+            .slots
+            .peek_mut()
+            .expect("a kitchen has at least one slot");
+        slot.0 = slot.0.max(ready_at);
+    }
+}
+
+fn whole_seconds(at: DateTime<Utc>) -> DateTime<Utc> {
+    DateTime::from_timestamp(at.timestamp(), 0).unwrap_or(at)
+}
+
+fn booking(order: &ProjectedOrder) -> Booking {
+    if !is_booked(order) {
+        return Booking::None;
+    }
+
+    let parse = |at: &str| chrono::DateTime::parse_from_rfc3339(at).map(|at| at.to_utc());
+    match (
+        order.status(),
+        parse(&order.ordered_at),
+        parse(&order.ready_at),
+    ) {
+        (OrderStatus::Placed, Ok(ordered_at), _) => Booking::Waiting {
+            ordered_at,
+            prep_secs: order.prep_secs,
+        },
+        (_, _, Ok(ready_at)) => Booking::Held(ready_at),
+        _ => Booking::None,
+    }
+}
+
+/// Whether an order takes up a slot in the kitchen, now or once it is
+/// started. Cancelled orders don't, and nor do orders whose party left
+/// before they were started.
+pub fn is_booked(order: &ProjectedOrder) -> bool {
+    let needs_cooking = match order.status() {
+        OrderStatus::Cancelled => false,
+        OrderStatus::Placed => order.finished_at.is_none(),
+        _ => true,
+    };
+    order.removal.is_none() && needs_cooking
+}
+
+/// The earliest time any order still waiting was placed, as a `ready_at`
+/// string. Orders quoted before it can't hold up anything still waiting,
+/// so [`reschedule`] only needs orders quoted from then on, plus the waiting
+/// ones themselves.
+pub fn horizon<'a>(orders: impl IntoIterator<Item = &'a ProjectedOrder>) -> Option<String> {
+    orders
+        .into_iter()
+        .filter_map(|order| match booking(order) {
+            Booking::Waiting { ordered_at, .. } => Some(ordered_at),
+            _ => None,
+        })
+        .min()
+        .map(timing::format_ready_at)
+}
+
+/// Deals every waiting order into the kitchen again, oldest first, behind
+/// the orders already being cooked. Returns the waiting orders whose quote
+/// has changed, with the new quote.
+pub fn reschedule(slots: i64, orders: &BTreeMap<i64, ProjectedOrder>) -> Vec<(i64, String)> {
+    let Some(horizon) = horizon(orders.values()) else {
+        return Vec::new();
+    };
+
+    let mut held = Vec::new();
+    let mut waiting = Vec::new();
+    orders
+        .iter()
+        .for_each(|(&order_id, order)| match booking(order) {
+            Booking::None => {}
+            Booking::Held(_) if order.ready_at < horizon => {}
+            Booking::Held(ready_at) => held.push(ready_at),
+            Booking::Waiting {
+                ordered_at,
+                prep_secs,
+            } => waiting.push((ordered_at, order_id, prep_secs)),
+        });
+
+    // Every order being cooked holds its slot before any waiting one is
+    // dealt, the same as `Kitchen::from_quotes`, whatever their ids
+    held.sort_unstable();
+    waiting.sort_unstable();
+
+    let mut kitchen = Kitchen::new(slots, []);
+    held.into_iter().for_each(|ready_at| kitchen.hold(ready_at));
+    waiting
+        .into_iter()
+        .filter_map(|(ordered_at, order_id, prep_secs)| {
+            let ready_at = timing::format_ready_at(kitchen.queue(ordered_at, prep_secs));
+            (ready_at != orders[&order_id].ready_at).then_some((order_id, ready_at))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use shared::StatusChange;
+
+    use super::*;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    fn order(ordered_at: i64, ready_at: i64, status: OrderStatus) -> ProjectedOrder {
+        let mut status_history = vec![StatusChange {
+            status: OrderStatus::Placed,
+            at: timing::format_ready_at(at(ordered_at)),
+        }];
+        if status != OrderStatus::Placed {
+            status_history.push(StatusChange {
+                status,
+                at: timing::format_ready_at(at(ordered_at)),
+            });
+        }
+
+        ProjectedOrder {
+            table_id: 1,
+            item_id: 1,
+            ordered_at: timing::format_ready_at(at(ordered_at)),
+            ready_at: timing::format_ready_at(at(ready_at)),
+            removal: None,
+            party_id: None,
+            finished_at: None,
+            status_history,
+            prep_secs: 60,
+            station: Default::default(),
+            claimed_by: None,
+            quantity: 1,
+            modifiers: Vec::new(),
+            notes: None,
+            unit_price: 100,
+            seat: None,
+        }
+    }
+
+    #[test]
+    fn orders_being_cooked_hold_their_slot_before_waiting_ones_are_dealt() {
+        // Order 2 was started out of turn, ahead of the older order 1
+        let orders = BTreeMap::from([
+            (1, order(0, 60, OrderStatus::Placed)),
+            (2, order(10, 300, OrderStatus::InProgress)),
+        ]);
+
+        let quotes = reschedule(1, &orders);
+        assert_eq!(quotes, vec![(1, timing::format_ready_at(at(360)))]);
+    }
+
+    #[test]
+    fn waiting_orders_are_dealt_oldest_first() {
+        // Order 2 was placed first, e.g. after an import renumbered orders
+        let orders = BTreeMap::from([
+            (1, order(20, 0, OrderStatus::Placed)),
+            (2, order(10, 0, OrderStatus::Placed)),
+        ]);
+
+        let quotes = reschedule(1, &orders);
+        assert_eq!(
+            quotes,
+            vec![
+                (2, timing::format_ready_at(at(70))),
+                (1, timing::format_ready_at(at(130))),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_changed_quotes_are_returned() {
+        let mut removed = order(0, 500, OrderStatus::InProgress);
+        removed.removal = Some(shared::Removal {
+            removed_at: timing::format_ready_at(at(5)),
+            removed_by: "amy".to_string(),
+            reason: shared::RemovalReason::KitchenError,
+        });
+        let orders = BTreeMap::from([
+            (1, removed),
+            (2, order(0, 60, OrderStatus::Placed)),
+            (3, order(0, 0, OrderStatus::Cancelled)),
+        ]);
+
+        assert!(reschedule(1, &orders).is_empty());
+    }
+
+    #[test]
+    fn quotes_fill_the_slots_which_free_up_latest() {
+        let quotes = [at(10), at(50), at(30)].map(timing::format_ready_at);
+        let mut kitchen = Kitchen::from_quotes(2, quotes.iter().map(String::as_str));
+
+        assert_eq!(kitchen.queue(at(0), 60), at(90));
+        assert_eq!(kitchen.queue(at(0), 60), at(110));
+    }
+
+    #[test]
+    fn slots_without_quotes_are_free() {
+        let quotes = [timing::format_ready_at(at(100))];
+        let mut kitchen = Kitchen::from_quotes(3, quotes.iter().map(String::as_str));
+
+        assert_eq!(kitchen.queue(at(0), 60), at(60));
+        assert_eq!(kitchen.queue(at(0), 60), at(60));
+        assert_eq!(kitchen.queue(at(0), 60), at(120));
+    }
+
+    #[test]
+    fn rescheduling_matches_queueing_behind_the_quotes() {
+        let orders = BTreeMap::from([
+            (1, order(0, 40, OrderStatus::InProgress)),
+            (2, order(0, 90, OrderStatus::Ready)),
+            (3, order(5, 0, OrderStatus::Placed)),
+            (4, order(30, 200, OrderStatus::InProgress)),
+            (5, order(8, 0, OrderStatus::Placed)),
+        ]);
+
+        let held = [40, 90, 200].map(|secs| timing::format_ready_at(at(secs)));
+        let mut kitchen = Kitchen::from_quotes(2, held.iter().map(String::as_str));
+        let expected = [(3, 5), (5, 8)]
+            .map(|(order_id, ordered_at)| {
+                let ready_at = kitchen.queue(at(ordered_at), 60);
+                (order_id, timing::format_ready_at(ready_at))
+            })
+            .to_vec();
+
+        assert_eq!(reschedule(2, &orders), expected);
+    }
+}
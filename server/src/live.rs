@@ -0,0 +1,140 @@
+use std::convert::Infallible;
+
+use axum::extract::{Extension, Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::routing::get;
+use axum::Router;
+use shared::{LiveEvent, LiveFilter, Restaurant};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+
+use crate::events::{OrderChange, ProjectedOrder};
+use crate::storage::Storage;
+use crate::timing;
+use crate::{ServiceResponse, ServiceState};
+
+/// How many changes a follower can fall behind by before it misses some
+const FEED_CAPACITY: usize = 1024;
+
+/// Fans changes out to everyone following a restaurant's event stream, as
+/// the storage backend makes them
+#[derive(Clone)]
+pub struct LiveFeed {
+    sender: broadcast::Sender<LiveUpdate>,
+}
+
+/// A change to one restaurant
+#[derive(Debug, Clone)]
+pub struct LiveUpdate {
+    pub restaurant_id: i64,
+    pub event: LiveEvent,
+}
+
+impl LiveFeed {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(FEED_CAPACITY);
+        Self { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveUpdate> {
+        self.sender.subscribe()
+    }
+
+    /// Whether anyone is listening, so backends can skip building events
+    /// nobody would receive
+    pub fn is_followed(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+
+    pub fn publish(&self, restaurant_id: i64, event: LiveEvent) {
+        // Sending only fails when nobody is following, which is fine
+        let _ = self.sender.send(LiveUpdate {
+            restaurant_id,
+            event,
+        });
+    }
+}
+
+/// The live event for a change to an order, given the order as it stands
+/// afterwards. Orders which have left the live orders altogether are
+/// missing, which only leaves enough to say they are gone.
+pub fn order_event(
+    order_id: i64,
+    table_id: i64,
+    change: &OrderChange,
+    order: Option<&ProjectedOrder>,
+) -> Option<LiveEvent> {
+    let table_order = || order.map(|order| order.to_table_order(order_id));
+
+    match change {
+        OrderChange::Placed { .. } => Some(LiveEvent::OrderCreated {
+            table_id,
+            order: table_order()?,
+        }),
+        OrderChange::Removed { .. } | OrderChange::Finished | OrderChange::Archived { .. } => {
+            Some(LiveEvent::OrderRemoved {
+                table_id,
+                order_id,
+                station: order.map(|order| order.station),
+            })
+        }
+        OrderChange::StatusChanged { .. } | OrderChange::Claimed { .. } => {
+            Some(LiveEvent::OrderStatusChanged {
+                table_id,
+                order: table_order()?,
+            })
+        }
+        OrderChange::Restored | OrderChange::Rescheduled { .. } => Some(LiveEvent::OrderUpdated {
+            table_id,
+            order: table_order()?,
+        }),
+    }
+}
+
+/// Routes following a restaurant's changes as they happen
+pub fn routes<S: Storage>() -> Router<ServiceState<S>> {
+    Router::new().route("/events", get(follow_events::<S>))
+}
+
+// Client: follow the restaurant's orders and menu as server-sent events,
+// optionally only those for one table or station
+async fn follow_events<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Extension(restaurant): Extension<Restaurant>,
+    Path(restaurant_id): Path<i64>,
+    Query(filter): Query<LiveFilter>,
+) -> ServiceResponse<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    println!(
+        "follow_events {restaurant_id}, {:?}, {:?}",
+        filter.table_id, filter.station
+    );
+    let updates = BroadcastStream::new(state.storage.subscribe());
+
+    let events = updates.filter_map(move |update| {
+        let mut event = match update {
+            Ok(update) if update.restaurant_id == restaurant_id => update.event,
+            Ok(_) => return None,
+            Err(BroadcastStreamRecvError::Lagged(missed)) => LiveEvent::Lagged { missed },
+        };
+        if !filter.matches(&event) {
+            return None;
+        }
+
+        match &mut event {
+            LiveEvent::OrderCreated { order, .. }
+            | LiveEvent::OrderStatusChanged { order, .. }
+            | LiveEvent::OrderUpdated { order, .. } => timing::annotate([order], &restaurant),
+            _ => {}
+        }
+        let data = serde_json::to_string(&event).expect("live events serialize");
+        Some(Ok(Event::default().event(event.name()).data(data)))
+    });
+
+    Ok((
+        StatusCode::OK,
+        Sse::new(events).keep_alive(KeepAlive::default()),
+    ))
+}
@@ -1,38 +1,117 @@
+use axum::body::Body;
 use std::sync::Arc;
 
-use axum::body::Body;
-use axum::extract::{Path, State};
+use axum::extract::{Extension, Path, Query, State};
 use axum::http::{Response, StatusCode};
+use axum::middleware;
 use axum::response::IntoResponse;
-use axum::routing::get;
+use axum::routing::{get, post};
 use axum::Json;
 use axum::Router;
-use rusqlite::Connection;
-use shared::{Menu, OrderItemsRequest, TableOrder, TableResponse, SERVICE_URL};
-use tokio::sync::Mutex;
+use config::{Config, ProcessorBackend, StorageBackend};
+use processor::{MockProcessor, Processor, ProcessorError};
+use shared::{
+    Menu, OrderItemsRequest, RemoveOrderRequest, Restaurant, TableHistory, TableItemsQuery,
+    TableOrder, TableResponse, UpdateOrderStatusRequest, SERVICE_URL,
+};
+use storage::{parse_status_filter, Storage, StorageError};
 
+mod adjustments;
+mod admin;
+mod billing;
+mod catalog;
+mod closeout;
+mod config;
 mod db;
+mod events;
+mod kitchen;
+mod live;
+mod memory;
+mod menu_admin;
+mod migrations;
+mod parties;
+mod payments;
+mod pool;
+mod processor;
+mod restaurants;
+mod stations;
+mod storage;
+mod table_admin;
+mod timing;
 
 #[derive(Clone)]
-struct ServiceState {
-    conn: Arc<Mutex<Connection>>,
+struct ServiceState<S: Storage> {
+    storage: S,
+    config: Arc<Config>,
+    processor: Arc<dyn Processor>,
 }
 
 #[tokio::main]
 async fn main() {
+    let config = Config::from_env();
+
+    // A catalog file replaces the built-in default menu
+    let seed_menu = config.menu_catalog.is_none();
+
+    match config.storage {
+        StorageBackend::Sqlite => {
+            let storage = db::SqliteStorage::open(&config.db_path, config.db_pool_size, seed_menu);
+            run(storage, config).await
+        }
+        StorageBackend::Memory => run(memory::MemoryStorage::new(seed_menu), config).await,
+    }
+}
+
+/// Serves requests, or runs the admin command given on the command line
+async fn run<S: Storage>(storage: S, config: Config) {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    if args.is_empty() {
+        serve(storage, config).await
+    } else if !admin::run_command(storage, &config, &args).await {
+        eprintln!(
+            "Usage: server [rebuild-orders [--dry-run] | backup <file> | restore <file> \
+            | export <restaurant id> <file> | import <restaurant id> <file> \
+            | close-out [<business date>]]"
+        );
+        std::process::exit(2);
+    }
+}
+
+async fn serve<S: Storage>(storage: S, config: Config) {
+    if let Some(path) = config.menu_catalog.clone() {
+        let items = catalog::load(&path)
+            .unwrap_or_else(|e| panic!("Invalid menu catalog {}: {e}", path.display()));
+        let restaurant_id = config.menu_catalog_restaurant;
+        let changes = storage.apply_catalog(restaurant_id, items).await.unwrap();
+        println!(
+            "Loaded menu catalog {} into restaurant {restaurant_id}: {changes}",
+            path.display()
+        );
+
+        tokio::spawn(catalog::watch(path, storage.clone(), restaurant_id));
+    }
+
+    if config.scheduled_close_out {
+        tokio::spawn(closeout::schedule(
+            storage.clone(),
+            config.business_day_start,
+        ));
+    }
+
     // Setup the service state
+    let processor: Arc<dyn Processor> = match config.payment_processor {
+        ProcessorBackend::Mock => Arc::new(MockProcessor::new(
+            config.mock_processor_script.iter().copied(),
+        )),
+    };
     let state = ServiceState {
-        conn: Arc::new(Mutex::new(db::init_db())),
+        storage,
+        config: Arc::new(config),
+        processor,
     };
 
-    let service = Router::new()
-        .route("/menu", get(get_menu))
-        .route("/tables/:table_id", get(get_table).post(post_table))
-        .route(
-            "/tables/:table_id/:order_id",
-            get(get_table_item).delete(delete_table_item),
-        )
-        .with_state(state);
+    let service = app(state);
 
     println!("{SERVICE_URL}");
     let listener = tokio::net::TcpListener::bind(SERVICE_URL).await.unwrap();
@@ -41,6 +120,48 @@ async fn main() {
     axum::serve(listener, service).await.unwrap();
 }
 
+/// Routes every request to its handler
+fn app<S: Storage>(state: ServiceState<S>) -> Router {
+    // Everything a restaurant owns lives under its own prefix, and requests
+    // for a restaurant that doesn't exist never reach the handlers.
+    let restaurant = Router::new()
+        .route("/menu", get(get_menu::<S>))
+        .route(
+            "/tables/:table_id",
+            get(get_table::<S>).post(post_table::<S>),
+        )
+        .route("/tables/:table_id/history", get(get_table_history::<S>))
+        .route(
+            "/tables/:table_id/:order_id",
+            get(get_table_item::<S>)
+                .patch(patch_table_item::<S>)
+                .delete(delete_table_item::<S>),
+        )
+        .route(
+            "/tables/:table_id/:order_id/restore",
+            post(restore_table_item::<S>),
+        )
+        .merge(menu_admin::routes())
+        .merge(table_admin::routes())
+        .merge(billing::routes())
+        .merge(adjustments::routes())
+        .merge(payments::routes())
+        .merge(parties::routes())
+        .merge(stations::routes())
+        .merge(live::routes())
+        .merge(admin::restaurant_routes())
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            restaurants::require_restaurant::<S>,
+        ));
+
+    Router::new()
+        .merge(restaurants::routes())
+        .nest("/restaurants/:restaurant_id", restaurant)
+        .merge(admin::routes())
+        .with_state(state)
+}
+
 type ServiceResponse<T> = Result<(StatusCode, T), HttpError>;
 
 struct HttpError {
@@ -57,23 +178,75 @@ impl IntoResponse for HttpError {
     }
 }
 
+impl From<StorageError> for HttpError {
+    fn from(error: StorageError) -> Self {
+        let (status_code, body) = match error {
+            StorageError::NotFound(body) => (StatusCode::NOT_FOUND, body),
+            StorageError::BadRequest(body) => (StatusCode::BAD_REQUEST, body),
+            StorageError::Invalid(body) => (StatusCode::UNPROCESSABLE_ENTITY, body),
+            StorageError::Conflict(body) => (StatusCode::CONFLICT, body),
+            StorageError::Forbidden(body) => (StatusCode::FORBIDDEN, body),
+            StorageError::Rejected(rejection) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                serde_json::to_string(&rejection).unwrap(),
+            ),
+            StorageError::Internal(body) => (StatusCode::INTERNAL_SERVER_ERROR, body),
+        };
+
+        HttpError { status_code, body }
+    }
+}
+
+impl From<ProcessorError> for HttpError {
+    fn from(error: ProcessorError) -> Self {
+        let (status_code, body) = match error {
+            ProcessorError::Declined(reason) => (StatusCode::PAYMENT_REQUIRED, reason),
+            ProcessorError::TimedOut => (
+                StatusCode::GATEWAY_TIMEOUT,
+                "the payment processor didn't answer in time".to_string(),
+            ),
+        };
+
+        HttpError { status_code, body }
+    }
+}
+
 /// Queries the database and returns the contents of the menu table. Generally called
-/// at startup for each of the clients to populate their data.
-async fn get_menu(State(state): State<ServiceState>) -> ServiceResponse<Json<Menu>> {
-    println!("get_menu");
-    let items = db::get_menu(&state.conn).await?;
+/// at startup for each of the clients to populate their data. The menu version lets
+/// clients tell whether the menu changed since they last fetched it.
+async fn get_menu<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path(restaurant_id): Path<i64>,
+) -> ServiceResponse<Json<Menu>> {
+    println!("get_menu {restaurant_id}");
+    let menu = state.storage.get_menu(restaurant_id).await?;
 
-    Ok((StatusCode::OK, Json(Menu { items })))
+    Ok((StatusCode::OK, Json(menu)))
 }
 
 // Client: query the items still remaining for a table.
 // The application MUST, upon query request, show all items for a specified table number.
-async fn get_table(
-    State(state): State<ServiceState>,
-    Path(table_id): Path<i64>,
+// `?status=placed,in_progress` only lists the orders in one of those statuses.
+async fn get_table<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Extension(restaurant): Extension<Restaurant>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+    Query(query): Query<TableItemsQuery>,
 ) -> ServiceResponse<Json<TableResponse>> {
-    println!("get_table {table_id}");
-    let ordered_items = db::get_tables_items(&state.conn, table_id).await?;
+    println!("get_table {restaurant_id}, {table_id}");
+    let statuses = query
+        .status
+        .as_deref()
+        .map(parse_status_filter)
+        .transpose()?;
+    let mut ordered_items = state
+        .storage
+        .get_tables_items(restaurant_id, table_id)
+        .await?;
+    if let Some(statuses) = statuses {
+        ordered_items.retain(|order| statuses.contains(&order.status));
+    }
+    timing::annotate(&mut ordered_items, &restaurant);
 
     Ok((
         StatusCode::OK,
@@ -86,13 +259,19 @@ async fn get_table(
 
 // Client: add one or more items with a table number,
 // The application MUST, upon creation request, store the item, the table number, and how long the item will take to cook.
-async fn post_table(
-    State(state): State<ServiceState>,
-    Path(table_id): Path<i64>,
+// The orders belong to the party seated at the table, so a party has to be seated first.
+async fn post_table<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Extension(restaurant): Extension<Restaurant>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
     Json(payload): Json<OrderItemsRequest>,
 ) -> ServiceResponse<Json<TableResponse>> {
-    println!("post_table {table_id}");
-    let ordered_items = db::order_items(&state.conn, table_id, payload).await?;
+    println!("post_table {restaurant_id}, {table_id}");
+    let mut ordered_items = state
+        .storage
+        .order_items(restaurant_id, table_id, payload)
+        .await?;
+    timing::annotate(&mut ordered_items, &restaurant);
 
     Ok((
         StatusCode::CREATED,
@@ -105,12 +284,19 @@ async fn post_table(
 
 // Client: remove an item for a table,
 // The application MUST, upon deletion request, remove a specified item for a specified table number.
-async fn delete_table_item(
-    State(state): State<ServiceState>,
-    Path((table_id, order_id)): Path<(i64, i64)>,
+// The order is kept with who removed it and why, so it still shows in the table history.
+async fn delete_table_item<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Extension(restaurant): Extension<Restaurant>,
+    Path((restaurant_id, table_id, order_id)): Path<(i64, i64, i64)>,
+    Query(request): Query<RemoveOrderRequest>,
 ) -> ServiceResponse<Json<TableResponse>> {
-    println!("delete_table_item {table_id}, {order_id}");
-    let ordered_items = db::delete_table_item(&state.conn, table_id, order_id).await?;
+    println!("delete_table_item {restaurant_id}, {table_id}, {order_id}");
+    let mut ordered_items = state
+        .storage
+        .delete_table_item(restaurant_id, table_id, order_id, request)
+        .await?;
+    timing::annotate(&mut ordered_items, &restaurant);
 
     Ok((
         StatusCode::OK,
@@ -123,12 +309,209 @@ async fn delete_table_item(
 
 // Client: query a specific item remaining for a table
 // The application MUST, upon query request, show a specified item for a specified table number.
-async fn get_table_item(
-    State(state): State<ServiceState>,
-    Path((table_id, order_id)): Path<(i64, i64)>,
+async fn get_table_item<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Extension(restaurant): Extension<Restaurant>,
+    Path((restaurant_id, table_id, order_id)): Path<(i64, i64, i64)>,
+) -> ServiceResponse<Json<TableOrder>> {
+    println!("get_table_item {restaurant_id}, {table_id}, {order_id}");
+    let mut item = state
+        .storage
+        .get_table_item(restaurant_id, table_id, order_id)
+        .await?;
+    timing::annotate([&mut item], &restaurant);
+
+    Ok((StatusCode::OK, Json(item)))
+}
+
+// Kitchen: move an item for a table on to its next status, e.g. from in_progress to ready.
+// Moving backwards or out of served or cancelled is refused with a 409.
+async fn patch_table_item<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Extension(restaurant): Extension<Restaurant>,
+    Path((restaurant_id, table_id, order_id)): Path<(i64, i64, i64)>,
+    Json(payload): Json<UpdateOrderStatusRequest>,
 ) -> ServiceResponse<Json<TableOrder>> {
-    println!("get_table_item {table_id}, {order_id}");
-    let item = db::get_table_item(&state.conn, table_id, order_id).await?;
+    println!(
+        "patch_table_item {restaurant_id}, {table_id}, {order_id}, {}",
+        payload.status
+    );
+    let mut item = state
+        .storage
+        .update_order_status(restaurant_id, table_id, order_id, payload.status)
+        .await?;
+    timing::annotate([&mut item], &restaurant);
 
     Ok((StatusCode::OK, Json(item)))
 }
+
+// Client: undo the removal of an item for a table, within the configured undo window
+async fn restore_table_item<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Extension(restaurant): Extension<Restaurant>,
+    Path((restaurant_id, table_id, order_id)): Path<(i64, i64, i64)>,
+) -> ServiceResponse<Json<TableResponse>> {
+    println!("restore_table_item {restaurant_id}, {table_id}, {order_id}");
+    let mut ordered_items = state
+        .storage
+        .restore_table_item(restaurant_id, table_id, order_id, state.config.undo_window)
+        .await?;
+    timing::annotate(&mut ordered_items, &restaurant);
+
+    Ok((
+        StatusCode::OK,
+        Json(TableResponse {
+            table_id,
+            ordered_items,
+        }),
+    ))
+}
+
+// Client: query every item ever ordered for a table, including removed ones and why they were removed
+async fn get_table_history<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+) -> ServiceResponse<Json<TableHistory>> {
+    println!("get_table_history {restaurant_id}, {table_id}");
+    let orders = state
+        .storage
+        .get_table_history(restaurant_id, table_id)
+        .await?;
+
+    Ok((StatusCode::OK, Json(TableHistory { table_id, orders })))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Method, Request};
+    use serde::de::DeserializeOwned;
+    use serde_json::json;
+    use shared::{Party, TableHistory, TableResponse};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// The whole service, on a fresh in-memory restaurant with the default
+    /// menu and tables
+    fn test_app() -> Router {
+        app(ServiceState {
+            storage: memory::MemoryStorage::new(true),
+            config: Arc::new(Config::from_env()),
+            processor: Arc::new(MockProcessor::new([])),
+        })
+    }
+
+    async fn send(
+        app: &Router,
+        method: Method,
+        uri: &str,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, Vec<u8>) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json");
+        let body = body.map_or_else(Body::empty, |body| Body::from(body.to_string()));
+        let response = app
+            .clone()
+            .oneshot(request.body(body).unwrap())
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, bytes.to_vec())
+    }
+
+    fn parse<T: DeserializeOwned>(body: &[u8]) -> T {
+        serde_json::from_slice(body).unwrap()
+    }
+
+    fn order_ids(table: &TableResponse) -> Vec<i64> {
+        table
+            .ordered_items
+            .iter()
+            .map(|order| order.order_id)
+            .collect()
+    }
+
+    async fn seat_party(app: &Router, table_id: i64) -> Party {
+        let uri = format!("/restaurants/1/tables/{table_id}/party");
+        let (status, body) = send(app, Method::POST, &uri, Some(json!({"guests": 2}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        parse(&body)
+    }
+
+    #[tokio::test]
+    async fn ordering_adds_orders_to_the_table() {
+        let app = test_app();
+        seat_party(&app, 5).await;
+
+        let items = Some(json!({"items": [1, 2]}));
+        let (status, body) = send(&app, Method::POST, "/restaurants/1/tables/5", items).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(order_ids(&parse(&body)), vec![1, 2]);
+
+        let (status, body) = send(&app, Method::GET, "/restaurants/1/tables/5", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let table = parse::<TableResponse>(&body);
+        assert_eq!(order_ids(&table), vec![1, 2]);
+        assert_eq!(table.ordered_items[1].item_id, 2);
+    }
+
+    #[tokio::test]
+    async fn ordering_needs_a_party_and_a_known_item() {
+        let app = test_app();
+        let items = Some(json!({"items": [1]}));
+        let (status, _) = send(&app, Method::POST, "/restaurants/1/tables/5", items).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        seat_party(&app, 5).await;
+        let items = Some(json!({"items": [999]}));
+        let (status, _) = send(&app, Method::POST, "/restaurants/1/tables/5", items).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn removed_orders_leave_the_table_but_stay_in_its_history() {
+        let app = test_app();
+        seat_party(&app, 5).await;
+        let items = Some(json!({"items": [1, 2]}));
+        send(&app, Method::POST, "/restaurants/1/tables/5", items).await;
+
+        let uri = "/restaurants/1/tables/5/1?reason=kitchen_error&removed_by=amy";
+        let (status, body) = send(&app, Method::DELETE, uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(order_ids(&parse(&body)), vec![2]);
+
+        let (status, _) = send(&app, Method::GET, "/restaurants/1/tables/5/1", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = send(&app, Method::DELETE, uri, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let uri = "/restaurants/1/tables/5/history";
+        let (_, body) = send(&app, Method::GET, uri, None).await;
+        let history = parse::<TableHistory>(&body);
+        let removed = history
+            .orders
+            .iter()
+            .filter(|order| order.removal.is_some())
+            .map(|order| order.order_id)
+            .collect::<Vec<_>>();
+        assert_eq!(removed, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn unknown_restaurants_are_not_found() {
+        let app = test_app();
+
+        let (status, _) = send(&app, Method::GET, "/restaurants/99/menu", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let items = Some(json!({"items": [1]}));
+        let (status, _) = send(&app, Method::POST, "/restaurants/99/tables/5", items).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = send(&app, Method::GET, "/restaurants/99", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,1585 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use chrono::{Duration, NaiveDate, Utc};
+use shared::{
+    Adjustment, ArchivedOrder, Bill, BillLine, CheckSplit, ClaimLineRequest, CloseOutSummary,
+    DataExport, ImportReport, LiveEvent, Manager, Menu, MenuItem, MenuItemPatch, NewAdjustment,
+    NewManager, NewMenuItem, NewPayment, NewRestaurant, NewTable, OrderHistoryEntry,
+    OrderItemsRequest, OrderStatus, Party, PartyDetails, Payment, PaymentStatus, RebuildReport,
+    RemoveOrderRequest, Restaurant, RestaurantPatch, SeatPartyRequest, Station, StationLine, Table,
+    TableHistory, TableOrder, TablePatch, Tender,
+};
+
+use tokio::sync::broadcast;
+
+use crate::billing;
+use crate::catalog::CatalogChanges;
+use crate::closeout;
+use crate::events::{self, OrderChange, OrderEvent, ProjectedOrder};
+use crate::kitchen::{self, Kitchen};
+use crate::live::{self, LiveFeed, LiveUpdate};
+use crate::storage::{
+    apply_payment_change, apply_split_change, bill_line, check_adjustable, check_approval,
+    check_claimable, check_completable, check_party_open, check_payable, check_restorable,
+    check_settled, check_table_free, check_table_open, check_transition, check_voidable,
+    default_modifiers, default_tables, hash_pin, import_table_registry, line_not_found,
+    manager_not_found, no_open_party, party_not_found, party_required, patch_menu_item,
+    patch_restaurant, patch_table, payment_not_found, restaurant_not_found, table_not_found,
+    validate_adjustment, validate_claim, validate_import, validate_manager, validate_menu_item,
+    validate_order, validate_party, validate_payment, validate_removal, validate_restaurant,
+    validate_table, ManagerRow, MenuItemRow, PaymentChange, SplitChange, Storage, StorageError,
+    StorageResult, DEFAULT_CURRENCY, DEFAULT_KITCHEN_SLOTS, DEFAULT_RESTAURANT_ID,
+    DEFAULT_RESTAURANT_NAME, DEFAULT_TIME_ZONE, ITEMS_MCDONALDS,
+};
+use crate::timing;
+
+/// Storage kept entirely in process memory using plain collections. Nothing
+/// survives a restart, which makes it handy for tests and local experiments.
+#[derive(Clone)]
+pub struct MemoryStorage {
+    state: Arc<Mutex<MemoryState>>,
+    live: LiveFeed,
+}
+
+struct MemoryState {
+    restaurants: BTreeMap<i64, RestaurantData>,
+    next_restaurant_id: i64,
+}
+
+/// Everything belonging to one restaurant, kept apart from every other one
+struct RestaurantData {
+    name: String,
+    time_zone: String,
+    kitchen_slots: i64,
+    currency: String,
+    tax_rate_bps: i64,
+    menu: BTreeMap<i64, MenuEntry>,
+    next_menu_id: i64,
+    menu_version: i64,
+    tables: BTreeMap<i64, Table>,
+    next_table_id: i64,
+    parties: BTreeMap<i64, Party>,
+    next_party_id: i64,
+    managers: BTreeMap<i64, ManagerRow>,
+    next_manager_id: i64,
+    adjustments: BTreeMap<i64, Adjustment>,
+    next_adjustment_id: i64,
+    payments: BTreeMap<i64, Payment>,
+    next_payment_id: i64,
+    /// Projection of `events`, kept up to date as each event is recorded
+    orders: BTreeMap<i64, ProjectedOrder>,
+    events: Vec<OrderEvent>,
+    next_order_id: i64,
+    archived_orders: Vec<ArchivedOrder>,
+    close_outs: Vec<CloseOutSummary>,
+    /// Live events for the change in progress, collected only while someone
+    /// follows the live feed
+    outbox: Option<Vec<LiveEvent>>,
+}
+
+struct MenuEntry {
+    item: MenuItem,
+    /// Retired items are kept so old orders can still refer to them
+    active: bool,
+}
+
+/// Whether an order still shows in its station's queue
+fn is_waiting(order: &ProjectedOrder) -> bool {
+    order.is_live()
+        && matches!(
+            order.status(),
+            OrderStatus::Placed | OrderStatus::InProgress
+        )
+}
+
+fn to_history_entry(order_id: i64, order: &ProjectedOrder) -> OrderHistoryEntry {
+    OrderHistoryEntry {
+        order_id,
+        item_id: order.item_id,
+        ordered_at: order.ordered_at.clone(),
+        ready_at: order.ready_at.clone(),
+        removal: order.removal.clone(),
+        party_id: order.party_id,
+        finished_at: order.finished_at.clone(),
+        status: order.status(),
+        status_history: order.status_history.clone(),
+        prep_secs: Some(order.prep_secs),
+        station: order.station,
+        claimed_by: order.claimed_by.clone(),
+        quantity: order.quantity,
+        modifiers: order.modifiers.clone(),
+        notes: order.notes.clone(),
+        unit_price: order.unit_price,
+        seat: order.seat,
+    }
+}
+
+fn archived_history_entry(order: &ArchivedOrder) -> OrderHistoryEntry {
+    OrderHistoryEntry {
+        order_id: order.order_id,
+        item_id: order.item_id,
+        ordered_at: order.ordered_at.clone(),
+        ready_at: order.ready_at.clone(),
+        removal: order.removal.clone(),
+        party_id: order.party_id,
+        finished_at: order.finished_at.clone(),
+        status: order.status,
+        status_history: order.status_history.clone(),
+        prep_secs: order.prep_secs,
+        station: order.station,
+        claimed_by: None,
+        quantity: order.quantity,
+        modifiers: order.modifiers.clone(),
+        notes: order.notes.clone(),
+        unit_price: order.unit_price,
+        seat: order.seat,
+    }
+}
+
+impl MemoryStorage {
+    /// Creates storage holding only the default restaurant, filled with the
+    /// default menu if `seed_menu` is set
+    pub fn new(seed_menu: bool) -> Self {
+        let mut restaurant = RestaurantData::new(
+            DEFAULT_RESTAURANT_NAME.to_string(),
+            DEFAULT_TIME_ZONE.to_string(),
+            DEFAULT_KITCHEN_SLOTS,
+            DEFAULT_CURRENCY.to_string(),
+            0,
+        );
+
+        if seed_menu {
+            ITEMS_MCDONALDS
+                .iter()
+                .for_each(|&(name, prep_min_m, prep_max_m, station, price)| {
+                    restaurant.add_menu_item(NewMenuItem {
+                        name: name.to_string(),
+                        prep_min_m,
+                        prep_max_m,
+                        station,
+                        modifiers: default_modifiers(name),
+                        price,
+                    });
+                });
+        }
+        default_tables().into_iter().for_each(|table| {
+            restaurant.add_table(table);
+        });
+
+        let state = MemoryState {
+            restaurants: BTreeMap::from([(DEFAULT_RESTAURANT_ID, restaurant)]),
+            next_restaurant_id: DEFAULT_RESTAURANT_ID + 1,
+        };
+
+        Self {
+            state: Arc::new(Mutex::new(state)),
+            live: LiveFeed::new(),
+        }
+    }
+
+    /// Runs `f` against one restaurant's data, so it can never see another's
+    fn with_restaurant<T>(
+        &self,
+        restaurant_id: i64,
+        f: impl FnOnce(&mut RestaurantData) -> StorageResult<T>,
+    ) -> StorageResult<T> {
+        let mut state = self.state.lock().unwrap();
+        let restaurant = state
+            .restaurants
+            .get_mut(&restaurant_id)
+            .ok_or_else(restaurant_not_found)?;
+
+        restaurant.outbox = self.live.is_followed().then(Vec::new);
+        let result = f(restaurant);
+        for event in restaurant.outbox.take().into_iter().flatten() {
+            self.live.publish(restaurant_id, event);
+        }
+        result
+    }
+}
+
+impl RestaurantData {
+    fn new(
+        name: String,
+        time_zone: String,
+        kitchen_slots: i64,
+        currency: String,
+        tax_rate_bps: i64,
+    ) -> Self {
+        Self {
+            name,
+            time_zone,
+            kitchen_slots,
+            currency,
+            tax_rate_bps,
+            menu: BTreeMap::new(),
+            next_menu_id: 1,
+            menu_version: 1,
+            tables: BTreeMap::new(),
+            next_table_id: 1,
+            parties: BTreeMap::new(),
+            next_party_id: 1,
+            managers: BTreeMap::new(),
+            next_manager_id: 1,
+            adjustments: BTreeMap::new(),
+            next_adjustment_id: 1,
+            payments: BTreeMap::new(),
+            next_payment_id: 1,
+            orders: BTreeMap::new(),
+            events: Vec::new(),
+            next_order_id: 1,
+            archived_orders: Vec::new(),
+            close_outs: Vec::new(),
+            outbox: None,
+        }
+    }
+
+    fn to_restaurant(&self, id: i64) -> Restaurant {
+        Restaurant {
+            id,
+            name: self.name.clone(),
+            time_zone: self.time_zone.clone(),
+            kitchen_slots: self.kitchen_slots,
+            currency: self.currency.clone(),
+            tax_rate_bps: self.tax_rate_bps,
+        }
+    }
+
+    /// Appends a change to the order event log and applies it to the orders
+    fn record_event(&mut self, order_id: i64, table_id: i64, change: OrderChange) {
+        self.record_event_at(events::now(), order_id, table_id, change);
+    }
+
+    /// Same as `record_event`, for a change which happened at a given time
+    fn record_event_at(&mut self, at: String, order_id: i64, table_id: i64, change: OrderChange) {
+        let event = OrderEvent {
+            seq: self.events.len() as i64 + 1,
+            at,
+            order_id,
+            table_id,
+            change,
+        };
+
+        events::apply(&mut self.orders, &event);
+        if let Some(outbox) = &mut self.outbox {
+            let order = self.orders.get(&event.order_id);
+            outbox.extend(live::order_event(
+                event.order_id,
+                event.table_id,
+                &event.change,
+                order,
+            ));
+        }
+        self.events.push(event);
+    }
+
+    fn bump_menu_version(&mut self) {
+        self.menu_version += 1;
+        if let Some(outbox) = &mut self.outbox {
+            outbox.push(LiveEvent::MenuChanged {
+                version: self.menu_version,
+            });
+        }
+    }
+
+    fn add_menu_item(&mut self, item: NewMenuItem) -> MenuItem {
+        let id = self.next_menu_id;
+        self.next_menu_id += 1;
+
+        let item = MenuItem {
+            id,
+            name: item.name,
+            prep_min_m: item.prep_min_m,
+            prep_max_m: item.prep_max_m,
+            station: item.station,
+            modifiers: item.modifiers,
+            price: item.price,
+        };
+        self.menu.insert(
+            id,
+            MenuEntry {
+                item: item.clone(),
+                active: true,
+            },
+        );
+        item
+    }
+
+    fn add_table(&mut self, table: NewTable) -> Table {
+        let id = self.next_table_id;
+        self.next_table_id += 1;
+
+        let table = Table {
+            id,
+            label: table.label,
+            section: table.section,
+            seats: table.seats,
+            active: true,
+        };
+        self.tables.insert(id, table.clone());
+        table
+    }
+
+    /// Mirrors the unique index on table labels in the SQLite schema
+    fn check_label_free(&self, label: &str, except_id: i64) -> StorageResult<()> {
+        match self
+            .tables
+            .values()
+            .any(|table| table.label == label && table.id != except_id)
+        {
+            true => Err(StorageError::Conflict(format!(
+                "a table labelled '{label}' already exists"
+            ))),
+            false => Ok(()),
+        }
+    }
+
+    fn table(&self, table_id: i64) -> StorageResult<&Table> {
+        self.tables.get(&table_id).ok_or_else(table_not_found)
+    }
+
+    fn party(&self, party_id: i64) -> StorageResult<&Party> {
+        self.parties.get(&party_id).ok_or_else(party_not_found)
+    }
+
+    fn open_party(&self, table_id: i64) -> Option<&Party> {
+        self.parties
+            .values()
+            .find(|party| party.table_id == table_id && party.closed_at.is_none())
+    }
+
+    /// Mirrors the unique index on menu names in the SQLite schema
+    fn check_name_free(&self, name: &str, except_id: i64) -> StorageResult<()> {
+        match self
+            .menu
+            .values()
+            .any(|entry| entry.item.name == name && entry.item.id != except_id)
+        {
+            true => Err(StorageError::Conflict(format!(
+                "a menu item named '{name}' already exists"
+            ))),
+            false => Ok(()),
+        }
+    }
+
+    /// Quotes every order still waiting for the kitchen again, after the
+    /// queue ahead of them has changed
+    fn reschedule(&mut self) {
+        for (order_id, ready_at) in kitchen::reschedule(self.kitchen_slots, &self.orders) {
+            let table_id = self.orders[&order_id].table_id;
+            self.record_event(order_id, table_id, OrderChange::Rescheduled { ready_at });
+        }
+    }
+
+    /// A live order waiting at `station`, placed or in progress
+    fn waiting_line(&self, station: Station, order_id: i64) -> StorageResult<&ProjectedOrder> {
+        self.orders
+            .get(&order_id)
+            .filter(|order| order.station == station && is_waiting(order))
+            .ok_or_else(line_not_found)
+    }
+
+    fn station_line(&self, order_id: i64, order: &ProjectedOrder) -> StationLine {
+        StationLine {
+            table_id: order.table_id,
+            table_label: self
+                .tables
+                .get(&order.table_id)
+                .map(|table| table.label.clone())
+                .unwrap_or_default(),
+            item_name: self
+                .menu
+                .get(&order.item_id)
+                .map(|entry| entry.item.name.clone())
+                .unwrap_or_default(),
+            order: order.to_table_order(order_id),
+        }
+    }
+
+    fn tables_items(&self, table_id: i64) -> Vec<TableOrder> {
+        self.orders
+            .iter()
+            .filter(|(_, order)| order.table_id == table_id && order.is_live())
+            .map(|(&order_id, order)| order.to_table_order(order_id))
+            .collect()
+    }
+
+    fn party_adjustments(&self, party_id: i64) -> Vec<Adjustment> {
+        self.adjustments
+            .values()
+            .filter(|adjustment| adjustment.party_id == party_id)
+            .cloned()
+            .collect()
+    }
+
+    fn party_payments(&self, party_id: i64) -> Vec<Payment> {
+        self.payments
+            .values()
+            .filter(|payment| payment.party_id == party_id)
+            .cloned()
+            .collect()
+    }
+
+    /// What the party seated at the table owes, before any payments
+    /// Closes an open party once it has settled up, finishing its live
+    /// orders. The caller reschedules the kitchen.
+    fn close_settled_party(&mut self, restaurant_id: i64, party_id: i64) -> StorageResult<Party> {
+        let table_id = self.party(party_id)?.table_id;
+        check_settled(
+            &self.party_bill(restaurant_id, table_id, party_id),
+            &self.party_payments(party_id),
+        )?;
+
+        let finished = self
+            .orders
+            .iter()
+            .filter(|(_, order)| order.party_id == Some(party_id) && order.is_live())
+            .map(|(&order_id, _)| order_id)
+            .collect::<Vec<_>>();
+        for order_id in finished {
+            self.record_event(order_id, table_id, OrderChange::Finished);
+        }
+
+        let party = self.parties.get_mut(&party_id).unwrap();
+        party.closed_at = Some(events::now());
+        Ok(party.clone())
+    }
+
+    fn party_bill(&self, restaurant_id: i64, table_id: i64, party_id: i64) -> Bill {
+        billing::bill(
+            table_id,
+            &self.to_restaurant(restaurant_id),
+            self.bill_lines(table_id),
+            self.party_adjustments(party_id),
+        )
+    }
+
+    fn bill_lines(&self, table_id: i64) -> Vec<BillLine> {
+        self.tables_items(table_id)
+            .into_iter()
+            .filter(|order| order.status != OrderStatus::Cancelled)
+            .map(|order| {
+                let item_name = self
+                    .menu
+                    .get(&order.item_id)
+                    .map(|entry| entry.item.name.clone())
+                    .unwrap_or_default();
+                bill_line(order, item_name)
+            })
+            .collect()
+    }
+}
+
+impl Storage for MemoryStorage {
+    async fn get_restaurants(&self) -> StorageResult<Vec<Restaurant>> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .restaurants
+            .iter()
+            .map(|(&id, restaurant)| restaurant.to_restaurant(id))
+            .collect())
+    }
+
+    async fn get_restaurant(&self, restaurant_id: i64) -> StorageResult<Restaurant> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            Ok(restaurant.to_restaurant(restaurant_id))
+        })
+    }
+
+    async fn update_restaurant(
+        &self,
+        restaurant_id: i64,
+        patch: RestaurantPatch,
+    ) -> StorageResult<Restaurant> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            let fields = patch_restaurant(&restaurant.to_restaurant(restaurant_id), patch);
+            let fields = validate_restaurant(fields)?;
+
+            let kitchen_slots = fields.kitchen_slots.unwrap_or(DEFAULT_KITCHEN_SLOTS);
+            let kitchen_changed = kitchen_slots != restaurant.kitchen_slots;
+
+            restaurant.name = fields.name;
+            restaurant.time_zone = fields.time_zone.unwrap_or_default();
+            restaurant.kitchen_slots = kitchen_slots;
+            restaurant.tax_rate_bps = fields.tax_rate_bps.unwrap_or_default();
+            if kitchen_changed {
+                restaurant.reschedule();
+            }
+            Ok(restaurant.to_restaurant(restaurant_id))
+        })
+    }
+
+    async fn create_restaurant(&self, restaurant: NewRestaurant) -> StorageResult<Restaurant> {
+        let restaurant = validate_restaurant(restaurant)?;
+        let mut state = self.state.lock().unwrap();
+
+        let id = state.next_restaurant_id;
+        state.next_restaurant_id += 1;
+
+        let time_zone = restaurant.time_zone.unwrap_or_default();
+        let kitchen_slots = restaurant.kitchen_slots.unwrap_or(DEFAULT_KITCHEN_SLOTS);
+        let currency = restaurant.currency.unwrap_or_default();
+        let tax_rate_bps = restaurant.tax_rate_bps.unwrap_or_default();
+        let data = RestaurantData::new(
+            restaurant.name,
+            time_zone,
+            kitchen_slots,
+            currency,
+            tax_rate_bps,
+        );
+        let restaurant = data.to_restaurant(id);
+        state.restaurants.insert(id, data);
+        Ok(restaurant)
+    }
+
+    async fn get_menu(&self, restaurant_id: i64) -> StorageResult<Menu> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            Ok(Menu {
+                version: restaurant.menu_version,
+                items: restaurant
+                    .menu
+                    .values()
+                    .filter(|entry| entry.active)
+                    .map(|entry| entry.item.clone())
+                    .collect(),
+            })
+        })
+    }
+
+    async fn create_menu_item(
+        &self,
+        restaurant_id: i64,
+        item: NewMenuItem,
+    ) -> StorageResult<MenuItem> {
+        let name = item.name.trim().to_string();
+        let item = NewMenuItem { name, ..item };
+        validate_menu_item(&item)?;
+
+        self.with_restaurant(restaurant_id, |restaurant| {
+            restaurant.check_name_free(&item.name, 0)?;
+
+            let item = restaurant.add_menu_item(item);
+            restaurant.bump_menu_version();
+            Ok(item)
+        })
+    }
+
+    async fn update_menu_item(
+        &self,
+        restaurant_id: i64,
+        item_id: i64,
+        patch: MenuItemPatch,
+    ) -> StorageResult<MenuItem> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            let current = &restaurant
+                .menu
+                .get(&item_id)
+                .ok_or_else(|| StorageError::NotFound("menu item does not exist".to_string()))?
+                .item;
+            let item = patch_menu_item(current, patch);
+            validate_menu_item(&item)?;
+            restaurant.check_name_free(&item.name, item_id)?;
+
+            let entry = restaurant.menu.get_mut(&item_id).unwrap();
+            entry.item.name = item.name;
+            entry.item.prep_min_m = item.prep_min_m;
+            entry.item.prep_max_m = item.prep_max_m;
+            entry.item.station = item.station;
+            entry.item.modifiers = item.modifiers;
+            entry.item.price = item.price;
+            let item = entry.item.clone();
+
+            restaurant.bump_menu_version();
+            Ok(item)
+        })
+    }
+
+    async fn delete_menu_item(
+        &self,
+        restaurant_id: i64,
+        item_id: i64,
+        retire: bool,
+    ) -> StorageResult<()> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            if !restaurant.menu.contains_key(&item_id) {
+                return Err(StorageError::NotFound(
+                    "menu item does not exist".to_string(),
+                ));
+            }
+
+            let referenced = restaurant
+                .orders
+                .values()
+                .any(|order| order.item_id == item_id);
+            match (referenced, retire) {
+                (false, _) => {
+                    restaurant.menu.remove(&item_id);
+                }
+                (true, true) => restaurant.menu.get_mut(&item_id).unwrap().active = false,
+                (true, false) => {
+                    return Err(StorageError::Conflict(
+                        "menu item is referenced by orders, retire it instead".to_string(),
+                    ))
+                }
+            }
+
+            restaurant.bump_menu_version();
+            Ok(())
+        })
+    }
+
+    async fn apply_catalog(
+        &self,
+        restaurant_id: i64,
+        items: Vec<NewMenuItem>,
+    ) -> StorageResult<CatalogChanges> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            let mut changes = CatalogChanges::default();
+
+            for item in &items {
+                let existing = restaurant
+                    .menu
+                    .values_mut()
+                    .find(|entry| entry.item.name == item.name);
+
+                match existing {
+                    None => {
+                        restaurant.add_menu_item(item.clone());
+                        changes.added += 1;
+                    }
+                    Some(entry)
+                        if !entry.active
+                            || entry.item.prep_min_m != item.prep_min_m
+                            || entry.item.prep_max_m != item.prep_max_m
+                            || entry.item.station != item.station
+                            || entry.item.modifiers != item.modifiers
+                            || entry.item.price != item.price =>
+                    {
+                        entry.item.prep_min_m = item.prep_min_m;
+                        entry.item.prep_max_m = item.prep_max_m;
+                        entry.item.station = item.station;
+                        entry.item.modifiers = item.modifiers.clone();
+                        entry.item.price = item.price;
+                        entry.active = true;
+                        changes.updated += 1;
+                    }
+                    Some(_) => (),
+                }
+            }
+
+            let names = items.iter().map(|item| &item.name).collect::<HashSet<_>>();
+            restaurant
+                .menu
+                .values_mut()
+                .filter(|entry| entry.active && !names.contains(&entry.item.name))
+                .for_each(|entry| {
+                    entry.active = false;
+                    changes.retired += 1;
+                });
+
+            if changes.added + changes.updated + changes.retired > 0 {
+                restaurant.bump_menu_version();
+            }
+
+            Ok(changes)
+        })
+    }
+
+    async fn get_tables(&self, restaurant_id: i64) -> StorageResult<Vec<Table>> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            Ok(restaurant.tables.values().cloned().collect())
+        })
+    }
+
+    async fn get_table(&self, restaurant_id: i64, table_id: i64) -> StorageResult<Table> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            restaurant.table(table_id).cloned()
+        })
+    }
+
+    async fn create_table(&self, restaurant_id: i64, table: NewTable) -> StorageResult<Table> {
+        let table = validate_table(table)?;
+
+        self.with_restaurant(restaurant_id, |restaurant| {
+            restaurant.check_label_free(&table.label, 0)?;
+            Ok(restaurant.add_table(table))
+        })
+    }
+
+    async fn update_table(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        patch: TablePatch,
+    ) -> StorageResult<Table> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            let (fields, active) = patch_table(restaurant.table(table_id)?, patch);
+            let fields = validate_table(fields)?;
+            restaurant.check_label_free(&fields.label, table_id)?;
+
+            let table = Table {
+                id: table_id,
+                label: fields.label,
+                section: fields.section,
+                seats: fields.seats,
+                active,
+            };
+            restaurant.tables.insert(table_id, table.clone());
+            Ok(table)
+        })
+    }
+
+    async fn delete_table(&self, restaurant_id: i64, table_id: i64) -> StorageResult<()> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            restaurant.table(table_id)?;
+            if restaurant
+                .events
+                .iter()
+                .any(|event| event.table_id == table_id)
+                || restaurant
+                    .parties
+                    .values()
+                    .any(|party| party.table_id == table_id)
+            {
+                return Err(StorageError::Conflict(
+                    "table has been used, deactivate it instead".to_string(),
+                ));
+            }
+
+            restaurant.tables.remove(&table_id);
+            Ok(())
+        })
+    }
+
+    async fn seat_party(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        request: SeatPartyRequest,
+    ) -> StorageResult<Party> {
+        let request = validate_party(request)?;
+
+        self.with_restaurant(restaurant_id, |restaurant| {
+            check_table_open(restaurant.table(table_id)?)?;
+            check_table_free(restaurant.open_party(table_id))?;
+
+            let party = Party {
+                id: restaurant.next_party_id,
+                table_id,
+                guests: request.guests,
+                seated_at: events::now(),
+                closed_at: None,
+                check_split: CheckSplit::Single,
+            };
+            restaurant.next_party_id += 1;
+            restaurant.parties.insert(party.id, party.clone());
+            Ok(party)
+        })
+    }
+
+    async fn get_open_party(&self, restaurant_id: i64, table_id: i64) -> StorageResult<Party> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            restaurant.table(table_id)?;
+            restaurant
+                .open_party(table_id)
+                .cloned()
+                .ok_or_else(no_open_party)
+        })
+    }
+
+    async fn get_table_parties(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> StorageResult<Vec<Party>> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            restaurant.table(table_id)?;
+            Ok(restaurant
+                .parties
+                .values()
+                .filter(|party| party.table_id == table_id)
+                .cloned()
+                .collect())
+        })
+    }
+
+    async fn get_party(&self, restaurant_id: i64, party_id: i64) -> StorageResult<PartyDetails> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            let party = restaurant.party(party_id)?.clone();
+
+            let live = restaurant
+                .orders
+                .iter()
+                .filter(|(_, order)| order.party_id == Some(party_id))
+                .map(|(&order_id, order)| to_history_entry(order_id, order));
+            let archived = restaurant
+                .archived_orders
+                .iter()
+                .filter(|order| order.party_id == Some(party_id))
+                .map(archived_history_entry);
+            let mut orders = live.chain(archived).collect::<Vec<_>>();
+            orders.sort_by_key(|order| order.order_id);
+
+            Ok(PartyDetails { party, orders })
+        })
+    }
+
+    async fn close_party(&self, restaurant_id: i64, party_id: i64) -> StorageResult<Party> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            check_party_open(restaurant.party(party_id)?)?;
+            let party = restaurant.close_settled_party(restaurant_id, party_id)?;
+            restaurant.reschedule();
+            Ok(party)
+        })
+    }
+
+    async fn get_tables_items(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> StorageResult<Vec<TableOrder>> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            restaurant.table(table_id)?;
+            Ok(restaurant.tables_items(table_id))
+        })
+    }
+
+    async fn order_items(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        items: OrderItemsRequest,
+    ) -> StorageResult<Vec<TableOrder>> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            let table = restaurant.table(table_id)?;
+            check_table_open(table)?;
+            let seats = table.seats;
+            let party_id = restaurant
+                .open_party(table_id)
+                .ok_or_else(party_required)?
+                .id;
+
+            let lines = items.into_lines();
+            let menu_items = lines
+                .iter()
+                .map(|line| &line.item_id)
+                .filter_map(|item_id| {
+                    let item = &restaurant
+                        .menu
+                        .get(item_id)
+                        .filter(|entry| entry.active)?
+                        .item;
+                    Some((
+                        *item_id,
+                        MenuItemRow {
+                            prep_min_m: item.prep_min_m as f64,
+                            prep_max_m: item.prep_max_m as f64,
+                            station: item.station,
+                            modifiers: item.modifiers.clone(),
+                            price: item.price,
+                        },
+                    ))
+                })
+                .collect::<HashMap<_, _>>();
+            let lines =
+                validate_order(&lines, &menu_items, seats).map_err(StorageError::Rejected)?;
+
+            let quotes = restaurant
+                .orders
+                .values()
+                .filter(|order| kitchen::is_booked(order))
+                .map(|order| order.ready_at.as_str());
+            let mut kitchen = Kitchen::from_quotes(restaurant.kitchen_slots, quotes);
+            let ordered_at = Utc::now();
+
+            for line in lines {
+                let order_id = restaurant.next_order_id;
+                restaurant.next_order_id += 1;
+
+                let item = &menu_items[&line.item_id];
+                let prep_secs = item.line_prep_secs(&line.modifiers);
+                let change = OrderChange::Placed {
+                    item_id: line.item_id,
+                    ready_at: timing::format_ready_at(kitchen.queue(ordered_at, prep_secs)),
+                    party_id: Some(party_id),
+                    prep_secs: Some(prep_secs),
+                    station: item.station,
+                    quantity: line.quantity,
+                    modifiers: line.modifiers,
+                    notes: line.notes,
+                    unit_price: line.unit_price,
+                    seat: line.seat,
+                };
+                restaurant.record_event_at(ordered_at.to_rfc3339(), order_id, table_id, change);
+            }
+
+            Ok(restaurant.tables_items(table_id))
+        })
+    }
+
+    async fn delete_table_item(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        order_id: i64,
+        request: RemoveOrderRequest,
+    ) -> StorageResult<Vec<TableOrder>> {
+        let request = validate_removal(request)?;
+
+        self.with_restaurant(restaurant_id, |restaurant| {
+            match restaurant.orders.get(&order_id) {
+                Some(order) if order.table_id == table_id && order.is_live() => {
+                    let change = OrderChange::Removed {
+                        removed_by: Some(request.removed_by),
+                        reason: Some(request.reason),
+                    };
+                    restaurant.record_event(order_id, table_id, change);
+                    restaurant.reschedule();
+                    Ok(restaurant.tables_items(table_id))
+                }
+                _ => Err(StorageError::NotFound(
+                    "order id does not exist".to_string(),
+                )),
+            }
+        })
+    }
+
+    async fn restore_table_item(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        order_id: i64,
+        window: Duration,
+    ) -> StorageResult<Vec<TableOrder>> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            let order = restaurant
+                .orders
+                .get(&order_id)
+                .filter(|order| order.table_id == table_id)
+                .ok_or_else(|| StorageError::NotFound("order id does not exist".to_string()))?;
+            check_restorable(order.removal.as_ref(), window)?;
+            if let Some(party_id) = order.party_id {
+                check_party_open(restaurant.party(party_id)?)?;
+            }
+
+            restaurant.record_event(order_id, table_id, OrderChange::Restored);
+            restaurant.reschedule();
+            Ok(restaurant.tables_items(table_id))
+        })
+    }
+
+    async fn update_order_status(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        order_id: i64,
+        status: OrderStatus,
+    ) -> StorageResult<TableOrder> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            let order = restaurant
+                .orders
+                .get(&order_id)
+                .filter(|order| order.table_id == table_id && order.is_live())
+                .ok_or_else(|| StorageError::NotFound("order id does not exist".to_string()))?;
+            check_transition(order.status(), status)?;
+
+            restaurant.record_event(order_id, table_id, OrderChange::StatusChanged { status });
+            if status == OrderStatus::Cancelled {
+                restaurant.reschedule();
+            }
+            Ok(restaurant.orders[&order_id].to_table_order(order_id))
+        })
+    }
+
+    async fn get_station_queue(
+        &self,
+        restaurant_id: i64,
+        station: Station,
+    ) -> StorageResult<Vec<StationLine>> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            let mut queue = restaurant
+                .orders
+                .iter()
+                .filter(|(_, order)| order.station == station && is_waiting(order))
+                .collect::<Vec<_>>();
+            queue.sort_by(|(a_id, a), (b_id, b)| (&a.ready_at, a_id).cmp(&(&b.ready_at, b_id)));
+
+            Ok(queue
+                .into_iter()
+                .map(|(&order_id, order)| restaurant.station_line(order_id, order))
+                .collect())
+        })
+    }
+
+    async fn claim_line(
+        &self,
+        restaurant_id: i64,
+        station: Station,
+        order_id: i64,
+        request: ClaimLineRequest,
+    ) -> StorageResult<StationLine> {
+        let request = validate_claim(request)?;
+
+        self.with_restaurant(restaurant_id, |restaurant| {
+            let order = restaurant.waiting_line(station, order_id)?;
+            check_claimable(order.status(), order.claimed_by.as_deref())?;
+
+            let table_id = order.table_id;
+            let change = OrderChange::Claimed {
+                claimed_by: request.claimed_by,
+            };
+            restaurant.record_event(order_id, table_id, change);
+            Ok(restaurant.station_line(order_id, &restaurant.orders[&order_id]))
+        })
+    }
+
+    async fn complete_line(
+        &self,
+        restaurant_id: i64,
+        station: Station,
+        order_id: i64,
+    ) -> StorageResult<StationLine> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            let order = restaurant.waiting_line(station, order_id)?;
+            check_completable(order.status())?;
+
+            let table_id = order.table_id;
+            let change = OrderChange::StatusChanged {
+                status: OrderStatus::Ready,
+            };
+            restaurant.record_event(order_id, table_id, change);
+            Ok(restaurant.station_line(order_id, &restaurant.orders[&order_id]))
+        })
+    }
+
+    async fn get_table_history(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> StorageResult<Vec<OrderHistoryEntry>> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            Ok(restaurant
+                .orders
+                .iter()
+                .filter(|(_, order)| order.table_id == table_id)
+                .map(|(&order_id, order)| to_history_entry(order_id, order))
+                .collect())
+        })
+    }
+
+    async fn get_table_item(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        order_id: i64,
+    ) -> StorageResult<TableOrder> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            restaurant
+                .orders
+                .get(&order_id)
+                .filter(|order| order.table_id == table_id && order.is_live())
+                .map(|order| order.to_table_order(order_id))
+                .ok_or_else(|| StorageError::NotFound("order id does not exist".to_string()))
+        })
+    }
+
+    async fn get_bill_lines(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> StorageResult<Vec<BillLine>> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            restaurant.table(table_id)?;
+            Ok(restaurant.bill_lines(table_id))
+        })
+    }
+
+    async fn get_check_split(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> StorageResult<CheckSplit> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            restaurant.table(table_id)?;
+            Ok(restaurant
+                .open_party(table_id)
+                .map(|party| party.check_split.clone())
+                .unwrap_or_default())
+        })
+    }
+
+    async fn split_checks(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        change: SplitChange,
+    ) -> StorageResult<CheckSplit> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            restaurant.table(table_id)?;
+            let party = restaurant.open_party(table_id).ok_or_else(party_required)?;
+            let party_id = party.id;
+            let split =
+                apply_split_change(&party.check_split, &restaurant.bill_lines(table_id), change)?;
+
+            restaurant.parties.get_mut(&party_id).unwrap().check_split = split.clone();
+            Ok(split)
+        })
+    }
+
+    async fn get_managers(&self, restaurant_id: i64) -> StorageResult<Vec<Manager>> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            Ok(restaurant
+                .managers
+                .values()
+                .map(|row| row.manager.clone())
+                .collect())
+        })
+    }
+
+    async fn add_manager(&self, restaurant_id: i64, manager: NewManager) -> StorageResult<Manager> {
+        let manager = validate_manager(manager)?;
+        // Hashing is slow on purpose, so it is done before taking the lock
+        let pin_hash = hash_pin(&manager.pin);
+
+        self.with_restaurant(restaurant_id, |restaurant| {
+            // Mirrors the unique index on manager names in the SQLite schema
+            if restaurant
+                .managers
+                .values()
+                .any(|row| row.manager.name == manager.name)
+            {
+                return Err(StorageError::Conflict(format!(
+                    "a manager named '{}' already exists",
+                    manager.name
+                )));
+            }
+
+            let row = ManagerRow {
+                manager: Manager {
+                    id: restaurant.next_manager_id,
+                    name: manager.name,
+                    active: true,
+                },
+                pin_hash,
+            };
+            restaurant.next_manager_id += 1;
+            let manager = row.manager.clone();
+            restaurant.managers.insert(manager.id, row);
+            Ok(manager)
+        })
+    }
+
+    async fn retire_manager(&self, restaurant_id: i64, manager_id: i64) -> StorageResult<Manager> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            let row = restaurant
+                .managers
+                .get_mut(&manager_id)
+                .ok_or_else(manager_not_found)?;
+            row.manager.active = false;
+            Ok(row.manager.clone())
+        })
+    }
+
+    async fn get_adjustments(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> StorageResult<Vec<Adjustment>> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            restaurant.table(table_id)?;
+            Ok(restaurant
+                .open_party(table_id)
+                .map(|party| restaurant.party_adjustments(party.id))
+                .unwrap_or_default())
+        })
+    }
+
+    async fn add_adjustment(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        request: NewAdjustment,
+    ) -> StorageResult<Adjustment> {
+        let request = validate_adjustment(request)?;
+
+        self.with_restaurant(restaurant_id, |restaurant| {
+            restaurant.table(table_id)?;
+            let party_id = restaurant
+                .open_party(table_id)
+                .ok_or_else(party_required)?
+                .id;
+            let manager = restaurant
+                .managers
+                .values()
+                .find(|row| row.manager.name == request.approved_by);
+            check_approval(&request, manager)?;
+            check_adjustable(
+                &request,
+                &restaurant.bill_lines(table_id),
+                &restaurant.party_adjustments(party_id),
+            )?;
+            if let Some(order) = request.order_id.and_then(|id| restaurant.orders.get(&id)) {
+                check_voidable(&request, order.status())?;
+            }
+
+            let adjustment = Adjustment {
+                id: restaurant.next_adjustment_id,
+                party_id,
+                table_id,
+                order_id: request.order_id,
+                kind: request.kind,
+                discount: request.discount,
+                reason: request.reason,
+                requested_by: request.requested_by,
+                approved_by: request.approved_by,
+                created_at: events::now(),
+            };
+            restaurant.next_adjustment_id += 1;
+            restaurant
+                .adjustments
+                .insert(adjustment.id, adjustment.clone());
+            Ok(adjustment)
+        })
+    }
+
+    async fn get_payments(&self, restaurant_id: i64, table_id: i64) -> StorageResult<Vec<Payment>> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            restaurant.table(table_id)?;
+            Ok(restaurant
+                .open_party(table_id)
+                .map(|party| restaurant.party_payments(party.id))
+                .unwrap_or_default())
+        })
+    }
+
+    async fn get_payment(&self, restaurant_id: i64, payment_id: i64) -> StorageResult<Payment> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            restaurant
+                .payments
+                .get(&payment_id)
+                .cloned()
+                .ok_or_else(payment_not_found)
+        })
+    }
+
+    async fn add_payment(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        payment: NewPayment,
+    ) -> StorageResult<Payment> {
+        validate_payment(&payment)?;
+
+        self.with_restaurant(restaurant_id, |restaurant| {
+            restaurant.table(table_id)?;
+            let party_id = restaurant
+                .open_party(table_id)
+                .ok_or_else(party_required)?
+                .id;
+            check_payable(
+                &payment,
+                &restaurant.party_bill(restaurant_id, table_id, party_id),
+                &restaurant.party_payments(party_id),
+            )?;
+
+            let now = events::now();
+            let payment = Payment {
+                id: restaurant.next_payment_id,
+                party_id,
+                table_id,
+                tender: payment.tender,
+                amount: payment.amount,
+                tip: payment.tip,
+                status: match payment.tender {
+                    Tender::Cash => PaymentStatus::Captured,
+                    Tender::Card => PaymentStatus::Pending,
+                },
+                reference: None,
+                failure: None,
+                created_at: now.clone(),
+                updated_at: now,
+            };
+            restaurant.next_payment_id += 1;
+            restaurant.payments.insert(payment.id, payment.clone());
+            Ok(payment)
+        })
+    }
+
+    async fn update_payment(
+        &self,
+        restaurant_id: i64,
+        payment_id: i64,
+        change: PaymentChange,
+    ) -> StorageResult<Payment> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            let payment = restaurant
+                .payments
+                .get_mut(&payment_id)
+                .ok_or_else(payment_not_found)?;
+            *payment = apply_payment_change(payment.clone(), change, events::now())?;
+            Ok(payment.clone())
+        })
+    }
+
+    async fn rebuild_orders(&self, dry_run: bool) -> StorageResult<RebuildReport> {
+        let mut state = self.state.lock().unwrap();
+        let mut report = RebuildReport {
+            events_replayed: 0,
+            orders: 0,
+            mismatches: Vec::new(),
+            rebuilt: !dry_run,
+        };
+
+        for (restaurant_id, restaurant) in state.restaurants.iter_mut() {
+            let expected = events::project(&restaurant.events);
+            report.events_replayed += restaurant.events.len();
+            report.orders += expected.len();
+            report.mismatches.extend(
+                events::diff(&expected, &restaurant.orders)
+                    .into_iter()
+                    .map(|mismatch| format!("restaurant {restaurant_id}: {mismatch}")),
+            );
+
+            if !dry_run {
+                restaurant.orders = expected;
+            }
+        }
+
+        Ok(report)
+    }
+
+    async fn backup(&self, _path: PathBuf) -> StorageResult<()> {
+        Err(no_database_file())
+    }
+
+    async fn restore(&self, _path: PathBuf) -> StorageResult<()> {
+        Err(no_database_file())
+    }
+
+    async fn export_data(&self, restaurant_id: i64) -> StorageResult<DataExport> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            let (items, retired_items) = restaurant
+                .menu
+                .values()
+                .partition::<Vec<_>, _>(|entry| entry.active);
+            let items_of = |entries: Vec<&MenuEntry>| {
+                entries
+                    .into_iter()
+                    .map(|entry| entry.item.clone())
+                    .collect()
+            };
+
+            let mut tables = BTreeMap::<i64, Vec<OrderHistoryEntry>>::new();
+            restaurant.orders.iter().for_each(|(&order_id, order)| {
+                tables
+                    .entry(order.table_id)
+                    .or_default()
+                    .push(to_history_entry(order_id, order));
+            });
+
+            Ok(DataExport {
+                menu: Menu {
+                    version: restaurant.menu_version,
+                    items: items_of(items),
+                },
+                retired_items: items_of(retired_items),
+                tables: tables
+                    .into_iter()
+                    .map(|(table_id, orders)| TableHistory { table_id, orders })
+                    .collect(),
+                table_registry: restaurant.tables.values().cloned().collect(),
+                parties: restaurant.parties.values().cloned().collect(),
+                adjustments: restaurant.adjustments.values().cloned().collect(),
+                payments: restaurant.payments.values().cloned().collect(),
+            })
+        })
+    }
+
+    async fn import_data(
+        &self,
+        restaurant_id: i64,
+        data: DataExport,
+    ) -> StorageResult<ImportReport> {
+        validate_import(&data)?;
+        let registry = import_table_registry(&data);
+
+        self.with_restaurant(restaurant_id, |restaurant| {
+            let items = data.menu.items.into_iter().map(|item| (item, true));
+            let retired = data.retired_items.into_iter().map(|item| (item, false));
+            restaurant.menu = items
+                .chain(retired)
+                .map(|(item, active)| (item.id, MenuEntry { item, active }))
+                .collect();
+            restaurant.next_menu_id = restaurant.menu.keys().max().map_or(1, |id| id + 1);
+            let menu_changed = restaurant.menu_version != data.menu.version;
+            restaurant.menu_version = data.menu.version;
+
+            restaurant.tables = registry
+                .into_iter()
+                .map(|table| (table.id, table))
+                .collect();
+            restaurant.next_table_id = restaurant.tables.keys().max().map_or(1, |id| id + 1);
+
+            restaurant.parties = data
+                .parties
+                .into_iter()
+                .map(|party| (party.id, party))
+                .collect();
+            restaurant.next_party_id = restaurant.parties.keys().max().map_or(1, |id| id + 1);
+
+            restaurant.adjustments = data
+                .adjustments
+                .into_iter()
+                .map(|adjustment| (adjustment.id, adjustment))
+                .collect();
+            restaurant.next_adjustment_id =
+                restaurant.adjustments.keys().max().map_or(1, |id| id + 1);
+
+            restaurant.payments = data
+                .payments
+                .into_iter()
+                .map(|payment| (payment.id, payment))
+                .collect();
+            restaurant.next_payment_id = restaurant.payments.keys().max().map_or(1, |id| id + 1);
+
+            restaurant.events = events::from_history(&data.tables);
+            restaurant.orders = events::project(&restaurant.events);
+            restaurant.next_order_id = restaurant.orders.keys().max().map_or(1, |id| id + 1);
+
+            // Followers hear about the imported log as if it had just happened
+            if let Some(outbox) = &mut restaurant.outbox {
+                if menu_changed {
+                    outbox.push(LiveEvent::MenuChanged {
+                        version: restaurant.menu_version,
+                    });
+                }
+                outbox.extend(restaurant.events.iter().filter_map(|event| {
+                    let order = restaurant.orders.get(&event.order_id);
+                    live::order_event(event.order_id, event.table_id, &event.change, order)
+                }));
+            }
+
+            Ok(ImportReport {
+                menu_items: restaurant.menu.len(),
+                tables: restaurant.tables.len(),
+                parties: restaurant.parties.len(),
+                orders: restaurant.orders.len(),
+                adjustments: restaurant.adjustments.len(),
+                payments: restaurant.payments.len(),
+            })
+        })
+    }
+
+    async fn close_out(
+        &self,
+        restaurant_id: i64,
+        business_date: NaiveDate,
+    ) -> StorageResult<CloseOutSummary> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            // Parties which have settled up leave, and the rest stay open
+            // with their orders so they can still pay
+            let open_parties = restaurant
+                .parties
+                .values()
+                .filter(|party| party.closed_at.is_none())
+                .map(|party| party.id)
+                .collect::<Vec<_>>();
+            let mut unsettled_parties = Vec::new();
+            for party_id in open_parties {
+                match restaurant.close_settled_party(restaurant_id, party_id) {
+                    Ok(_) => {}
+                    Err(StorageError::Conflict(_)) => unsettled_parties.push(party_id),
+                    Err(e) => return Err(e),
+                }
+            }
+            restaurant.reschedule();
+
+            let is_unsettled = |party_id| unsettled_parties.contains(&party_id);
+            let orders = restaurant
+                .orders
+                .iter()
+                .filter(|(_, order)| {
+                    order.removal.is_some()
+                        || order.finished_at.is_some()
+                        || matches!(order.status(), OrderStatus::Served | OrderStatus::Cancelled)
+                })
+                .filter(|(_, order)| !order.party_id.is_some_and(is_unsettled))
+                .map(|(&order_id, order)| ArchivedOrder {
+                    business_date: business_date.to_string(),
+                    order_id,
+                    table_id: order.table_id,
+                    item_id: order.item_id,
+                    item_name: restaurant
+                        .menu
+                        .get(&order.item_id)
+                        .map(|entry| entry.item.name.clone())
+                        .unwrap_or_default(),
+                    ordered_at: order.ordered_at.clone(),
+                    ready_at: order.ready_at.clone(),
+                    removal: order.removal.clone(),
+                    party_id: order.party_id,
+                    finished_at: order.finished_at.clone(),
+                    status: order.status(),
+                    status_history: order.status_history.clone(),
+                    quantity: order.quantity,
+                    modifiers: order.modifiers.clone(),
+                    notes: order.notes.clone(),
+                    unit_price: order.unit_price,
+                    seat: order.seat,
+                    station: order.station,
+                    prep_secs: Some(order.prep_secs),
+                })
+                .collect::<Vec<_>>();
+
+            orders.iter().for_each(|order| {
+                let change = OrderChange::Archived {
+                    business_date: order.business_date.clone(),
+                };
+                restaurant.record_event(order.order_id, order.table_id, change);
+            });
+
+            let summary = closeout::summarize(business_date, &orders, unsettled_parties);
+            restaurant.archived_orders.extend(orders);
+            restaurant.close_outs.push(summary.clone());
+
+            Ok(summary)
+        })
+    }
+
+    async fn get_close_outs(&self, restaurant_id: i64) -> StorageResult<Vec<CloseOutSummary>> {
+        self.with_restaurant(restaurant_id, |restaurant| {
+            Ok(restaurant.close_outs.clone())
+        })
+    }
+
+    async fn get_archived_orders(
+        &self,
+        restaurant_id: i64,
+        business_date: NaiveDate,
+    ) -> StorageResult<Vec<ArchivedOrder>> {
+        let business_date = business_date.to_string();
+
+        self.with_restaurant(restaurant_id, |restaurant| {
+            Ok(restaurant
+                .archived_orders
+                .iter()
+                .filter(|order| order.business_date == business_date)
+                .cloned()
+                .collect())
+        })
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<LiveUpdate> {
+        self.live.subscribe()
+    }
+}
+
+fn no_database_file() -> StorageError {
+    StorageError::BadRequest(
+        "the memory backend has no database file, use a JSON export instead".to_string(),
+    )
+}
@@ -0,0 +1,97 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::routing::{patch, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use shared::{MenuItem, MenuItemPatch, NewMenuItem};
+
+use crate::storage::Storage;
+use crate::{ServiceResponse, ServiceState};
+
+/// Routes letting managers edit the menu while the service runs
+pub fn routes<S: Storage>() -> Router<ServiceState<S>> {
+    Router::new()
+        .route("/menu/items", post(create_menu_item::<S>))
+        .route(
+            "/menu/items/:item_id",
+            patch(patch_menu_item::<S>)
+                .put(put_menu_item::<S>)
+                .delete(delete_menu_item::<S>),
+        )
+}
+
+#[derive(Deserialize)]
+struct DeleteMenuItemParams {
+    /// Hide an item still referenced by orders instead of refusing to delete it
+    #[serde(default)]
+    retire: bool,
+}
+
+// Manager: add a new dish to the menu
+async fn create_menu_item<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path(restaurant_id): Path<i64>,
+    Json(payload): Json<NewMenuItem>,
+) -> ServiceResponse<Json<MenuItem>> {
+    println!("create_menu_item {restaurant_id}, {}", payload.name);
+    let item = state
+        .storage
+        .create_menu_item(restaurant_id, payload)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(item)))
+}
+
+// Manager: replace every field of a menu item
+async fn put_menu_item<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, item_id)): Path<(i64, i64)>,
+    Json(payload): Json<NewMenuItem>,
+) -> ServiceResponse<Json<MenuItem>> {
+    println!("put_menu_item {restaurant_id}, {item_id}");
+    let patch = MenuItemPatch {
+        name: Some(payload.name),
+        prep_min_m: Some(payload.prep_min_m),
+        prep_max_m: Some(payload.prep_max_m),
+        station: Some(payload.station),
+        modifiers: Some(payload.modifiers),
+        price: Some(payload.price),
+    };
+    let item = state
+        .storage
+        .update_menu_item(restaurant_id, item_id, patch)
+        .await?;
+
+    Ok((StatusCode::OK, Json(item)))
+}
+
+// Manager: change some fields of a menu item
+async fn patch_menu_item<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, item_id)): Path<(i64, i64)>,
+    Json(payload): Json<MenuItemPatch>,
+) -> ServiceResponse<Json<MenuItem>> {
+    println!("patch_menu_item {restaurant_id}, {item_id}");
+    let item = state
+        .storage
+        .update_menu_item(restaurant_id, item_id, payload)
+        .await?;
+
+    Ok((StatusCode::OK, Json(item)))
+}
+
+// Manager: take a dish off the menu. Items with orders are only retired with `?retire=true`,
+// otherwise the request is refused with a 409.
+async fn delete_menu_item<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, item_id)): Path<(i64, i64)>,
+    Query(params): Query<DeleteMenuItemParams>,
+) -> ServiceResponse<()> {
+    println!("delete_menu_item {restaurant_id}, {item_id}");
+    state
+        .storage
+        .delete_menu_item(restaurant_id, item_id, params.retire)
+        .await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
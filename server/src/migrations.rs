@@ -0,0 +1,457 @@
+use rusqlite::Connection;
+
+/// Ordered list of forward migrations. The schema version of each entry is its
+/// index + 1, so migrations must only ever be appended to the end of this list
+/// and never edited once released.
+const MIGRATIONS: &[&str] = &[
+    // 1: Initial menu & orders tables
+    r#"
+    CREATE TABLE menu (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        prep_min_m REAL NOT NULL,
+        prep_max_m REAL NOT NULL
+    );
+
+    CREATE TABLE orders (
+        id INTEGER PRIMARY KEY,
+        table_id INTEGER NOT NULL,
+        item_id INTEGER NOT NULL,
+        ready_at TEXT NOT NULL,
+        FOREIGN KEY (item_id) REFERENCES menu (id)
+    );
+    "#,
+    // 2: Menu items are retired instead of deleted, and matched by name
+    //    when a catalog file is applied
+    r#"
+    ALTER TABLE menu ADD COLUMN active INTEGER NOT NULL DEFAULT 1;
+    CREATE UNIQUE INDEX menu_name ON menu (name);
+    "#,
+    // 3: Menu version, bumped on every menu change
+    r#"
+    CREATE TABLE menu_version (
+        version INTEGER NOT NULL
+    );
+    INSERT INTO menu_version (version) VALUES (1);
+    "#,
+    // 4: Append-only order event log, which the orders table is a projection of.
+    //    Orders placed before the log existed get a placed event each.
+    r#"
+    CREATE TABLE order_events (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        at TEXT NOT NULL,
+        order_id INTEGER NOT NULL,
+        table_id INTEGER NOT NULL,
+        change TEXT NOT NULL
+    );
+    CREATE INDEX order_events_order ON order_events (order_id);
+
+    INSERT INTO order_events (at, order_id, table_id, change)
+        SELECT
+            strftime('%Y-%m-%dT%H:%M:%SZ', 'now'),
+            id,
+            table_id,
+            json_object('kind', 'placed', 'item_id', item_id, 'ready_at', ready_at)
+        FROM orders
+        ORDER BY id;
+    "#,
+    // 5: Orders are soft deleted with who removed them and why, and remember
+    //    when they were placed for the table history
+    r#"
+    ALTER TABLE orders ADD COLUMN ordered_at TEXT NOT NULL DEFAULT '';
+    ALTER TABLE orders ADD COLUMN removed_at TEXT;
+    ALTER TABLE orders ADD COLUMN removed_by TEXT;
+    ALTER TABLE orders ADD COLUMN removed_reason TEXT;
+
+    UPDATE orders SET ordered_at = (
+        SELECT at FROM order_events
+        WHERE order_events.order_id = orders.id
+        ORDER BY seq
+        LIMIT 1
+    );
+
+    CREATE INDEX orders_table ON orders (table_id);
+    "#,
+    // 6: Close-outs move each business day's orders into an archive, with a
+    //    summary of what was archived. Archived orders keep the item name so
+    //    they don't depend on the menu.
+    r#"
+    CREATE TABLE archived_orders (
+        order_id INTEGER NOT NULL,
+        business_date TEXT NOT NULL,
+        table_id INTEGER NOT NULL,
+        item_id INTEGER NOT NULL,
+        item_name TEXT NOT NULL,
+        ordered_at TEXT NOT NULL,
+        ready_at TEXT NOT NULL,
+        removed_at TEXT,
+        removed_by TEXT,
+        removed_reason TEXT
+    );
+    CREATE INDEX archived_orders_business_date ON archived_orders (business_date);
+
+    CREATE TABLE close_outs (
+        id INTEGER PRIMARY KEY,
+        business_date TEXT NOT NULL,
+        closed_at TEXT NOT NULL,
+        summary TEXT NOT NULL
+    );
+    "#,
+    // 7: Several restaurants share one server. Menus and orders are keyed by
+    //    restaurant, each numbering its items and orders from 1, so the two
+    //    tables are rebuilt with composite keys. Existing data belongs to the
+    //    default restaurant.
+    r#"
+    CREATE TABLE restaurants (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL
+    );
+    INSERT INTO restaurants (id, name) VALUES (1, 'Default');
+
+    CREATE TABLE menu_new (
+        restaurant_id INTEGER NOT NULL REFERENCES restaurants (id),
+        id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        prep_min_m REAL NOT NULL,
+        prep_max_m REAL NOT NULL,
+        active INTEGER NOT NULL DEFAULT 1,
+        PRIMARY KEY (restaurant_id, id)
+    );
+    INSERT INTO menu_new (restaurant_id, id, name, prep_min_m, prep_max_m, active)
+        SELECT 1, id, name, prep_min_m, prep_max_m, active FROM menu;
+
+    CREATE TABLE orders_new (
+        restaurant_id INTEGER NOT NULL,
+        id INTEGER NOT NULL,
+        table_id INTEGER NOT NULL,
+        item_id INTEGER NOT NULL,
+        ordered_at TEXT NOT NULL,
+        ready_at TEXT NOT NULL,
+        removed_at TEXT,
+        removed_by TEXT,
+        removed_reason TEXT,
+        PRIMARY KEY (restaurant_id, id),
+        FOREIGN KEY (restaurant_id, item_id) REFERENCES menu_new (restaurant_id, id)
+    );
+    INSERT INTO orders_new (restaurant_id, id, table_id, item_id, ordered_at, ready_at,
+            removed_at, removed_by, removed_reason)
+        SELECT 1, id, table_id, item_id, ordered_at, ready_at, removed_at, removed_by,
+            removed_reason
+        FROM orders;
+
+    DROP TABLE orders;
+    DROP TABLE menu;
+    ALTER TABLE menu_new RENAME TO menu;
+    ALTER TABLE orders_new RENAME TO orders;
+    CREATE UNIQUE INDEX menu_name ON menu (restaurant_id, name);
+    CREATE INDEX orders_table ON orders (restaurant_id, table_id);
+
+    ALTER TABLE menu_version ADD COLUMN restaurant_id INTEGER NOT NULL DEFAULT 1;
+    CREATE UNIQUE INDEX menu_version_restaurant ON menu_version (restaurant_id);
+
+    ALTER TABLE order_events ADD COLUMN restaurant_id INTEGER NOT NULL DEFAULT 1;
+    DROP INDEX order_events_order;
+    CREATE INDEX order_events_order ON order_events (restaurant_id, order_id);
+
+    ALTER TABLE archived_orders ADD COLUMN restaurant_id INTEGER NOT NULL DEFAULT 1;
+    DROP INDEX archived_orders_business_date;
+    CREATE INDEX archived_orders_business_date ON archived_orders (restaurant_id, business_date);
+
+    ALTER TABLE close_outs ADD COLUMN restaurant_id INTEGER NOT NULL DEFAULT 1;
+    "#,
+    // 8: Registry of each restaurant's tables, which orders must refer to.
+    //    Every table which already has orders is registered under a default
+    //    label, and the orders table is rebuilt to reference the registry.
+    r#"
+    CREATE TABLE tables (
+        restaurant_id INTEGER NOT NULL REFERENCES restaurants (id),
+        id INTEGER NOT NULL,
+        label TEXT NOT NULL,
+        section TEXT NOT NULL,
+        seats INTEGER NOT NULL,
+        active INTEGER NOT NULL DEFAULT 1,
+        PRIMARY KEY (restaurant_id, id)
+    );
+    CREATE UNIQUE INDEX tables_label ON tables (restaurant_id, label);
+    INSERT INTO tables (restaurant_id, id, label, section, seats)
+        SELECT DISTINCT restaurant_id, table_id, 'Table ' || table_id, 'Main', 4
+        FROM order_events;
+
+    CREATE TABLE orders_new (
+        restaurant_id INTEGER NOT NULL,
+        id INTEGER NOT NULL,
+        table_id INTEGER NOT NULL,
+        item_id INTEGER NOT NULL,
+        ordered_at TEXT NOT NULL,
+        ready_at TEXT NOT NULL,
+        removed_at TEXT,
+        removed_by TEXT,
+        removed_reason TEXT,
+        PRIMARY KEY (restaurant_id, id),
+        FOREIGN KEY (restaurant_id, item_id) REFERENCES menu (restaurant_id, id),
+        FOREIGN KEY (restaurant_id, table_id) REFERENCES tables (restaurant_id, id)
+    );
+    INSERT INTO orders_new SELECT restaurant_id, id, table_id, item_id, ordered_at, ready_at,
+        removed_at, removed_by, removed_reason FROM orders;
+
+    DROP TABLE orders;
+    ALTER TABLE orders_new RENAME TO orders;
+    CREATE INDEX orders_table ON orders (restaurant_id, table_id);
+    "#,
+    // 9: Parties of guests seated at a table own the orders placed for it.
+    //    Closing a party finishes its orders, which then no longer show for
+    //    the table. Orders from before parties belong to none.
+    r#"
+    CREATE TABLE parties (
+        restaurant_id INTEGER NOT NULL,
+        id INTEGER NOT NULL,
+        table_id INTEGER NOT NULL,
+        guests INTEGER NOT NULL,
+        seated_at TEXT NOT NULL,
+        closed_at TEXT,
+        PRIMARY KEY (restaurant_id, id),
+        FOREIGN KEY (restaurant_id, table_id) REFERENCES tables (restaurant_id, id)
+    );
+    CREATE UNIQUE INDEX parties_seated ON parties (restaurant_id, table_id)
+        WHERE closed_at IS NULL;
+
+    ALTER TABLE orders ADD COLUMN party_id INTEGER;
+    ALTER TABLE orders ADD COLUMN finished_at TEXT;
+    CREATE INDEX orders_party ON orders (restaurant_id, party_id);
+
+    ALTER TABLE archived_orders ADD COLUMN party_id INTEGER;
+    ALTER TABLE archived_orders ADD COLUMN finished_at TEXT;
+    CREATE INDEX archived_orders_party ON archived_orders (restaurant_id, party_id);
+    "#,
+    // 10: Orders move through placed, in_progress, ready and served, or get
+    //     cancelled. Each status records when the order entered it; placed
+    //     is `ordered_at`. Existing orders are placed.
+    r#"
+    ALTER TABLE orders ADD COLUMN status TEXT NOT NULL DEFAULT 'placed';
+    ALTER TABLE orders ADD COLUMN started_at TEXT;
+    ALTER TABLE orders ADD COLUMN prepared_at TEXT;
+    ALTER TABLE orders ADD COLUMN served_at TEXT;
+    ALTER TABLE orders ADD COLUMN cancelled_at TEXT;
+    CREATE INDEX orders_status ON orders (restaurant_id, status);
+
+    ALTER TABLE archived_orders ADD COLUMN status TEXT NOT NULL DEFAULT 'placed';
+    ALTER TABLE archived_orders ADD COLUMN started_at TEXT;
+    ALTER TABLE archived_orders ADD COLUMN prepared_at TEXT;
+    ALTER TABLE archived_orders ADD COLUMN served_at TEXT;
+    ALTER TABLE archived_orders ADD COLUMN cancelled_at TEXT;
+    "#,
+    // 11: Restaurants show times in their own time zone. `ready_at` was
+    //     written as "dd/mm/YYYY HH:MM" in UTC, which doesn't sort, and is
+    //     now an RFC 3339 UTC timestamp everywhere, event log included.
+    r#"
+    ALTER TABLE restaurants ADD COLUMN time_zone TEXT NOT NULL DEFAULT 'UTC';
+
+    UPDATE orders SET ready_at = substr(ready_at, 7, 4) || '-' || substr(ready_at, 4, 2)
+        || '-' || substr(ready_at, 1, 2) || 'T' || substr(ready_at, 12, 5) || ':00Z'
+        WHERE ready_at LIKE '__/__/____ __:__';
+    UPDATE archived_orders SET ready_at = substr(ready_at, 7, 4) || '-'
+        || substr(ready_at, 4, 2) || '-' || substr(ready_at, 1, 2) || 'T'
+        || substr(ready_at, 12, 5) || ':00Z'
+        WHERE ready_at LIKE '__/__/____ __:__';
+    UPDATE order_events SET change = json_set(change, '$.ready_at',
+            substr(change ->> '$.ready_at', 7, 4) || '-'
+            || substr(change ->> '$.ready_at', 4, 2) || '-'
+            || substr(change ->> '$.ready_at', 1, 2) || 'T'
+            || substr(change ->> '$.ready_at', 12, 5) || ':00Z')
+        WHERE change ->> '$.kind' == 'placed'
+            AND change ->> '$.ready_at' LIKE '__/__/____ __:__';
+
+    CREATE INDEX orders_ready_at ON orders (restaurant_id, ready_at);
+    "#,
+    // 12: Ready times come from a kitchen with a limited number of cooking
+    //     slots. Each order keeps the prep time it was dealt so it can be
+    //     quoted again; older orders count the time until they were ready.
+    r#"
+    ALTER TABLE restaurants ADD COLUMN kitchen_slots INTEGER NOT NULL DEFAULT 4;
+
+    ALTER TABLE orders ADD COLUMN prep_secs INTEGER NOT NULL DEFAULT 0;
+    UPDATE orders SET prep_secs = COALESCE(MAX(0, CAST(strftime('%s', ready_at) AS INTEGER)
+        - CAST(strftime('%s', ordered_at) AS INTEGER)), 0);
+    "#,
+    // 13: Menu items are made at a kitchen station and their orders queue
+    //     there, claimed by whoever is making them. Existing items and
+    //     orders stay on the grill.
+    r#"
+    ALTER TABLE menu ADD COLUMN station TEXT NOT NULL DEFAULT 'grill';
+
+    ALTER TABLE orders ADD COLUMN station TEXT NOT NULL DEFAULT 'grill';
+    ALTER TABLE orders ADD COLUMN claimed_by TEXT;
+    CREATE INDEX orders_station ON orders (restaurant_id, station, ready_at);
+    "#,
+    // 14: Menu items offer modifiers, and each order is a line holding a
+    //     quantity of one item, the modifiers chosen and notes for the
+    //     kitchen. Both modifier lists are JSON. Existing orders were for a
+    //     single item as it came.
+    r#"
+    ALTER TABLE menu ADD COLUMN modifiers TEXT NOT NULL DEFAULT '[]';
+
+    ALTER TABLE orders ADD COLUMN quantity INTEGER NOT NULL DEFAULT 1;
+    ALTER TABLE orders ADD COLUMN modifiers TEXT NOT NULL DEFAULT '[]';
+    ALTER TABLE orders ADD COLUMN notes TEXT;
+
+    ALTER TABLE archived_orders ADD COLUMN quantity INTEGER NOT NULL DEFAULT 1;
+    ALTER TABLE archived_orders ADD COLUMN modifiers TEXT NOT NULL DEFAULT '[]';
+    ALTER TABLE archived_orders ADD COLUMN notes TEXT;
+    "#,
+    // 15: Menu items have a price, in minor units of their restaurant's
+    //     currency, and each order keeps the price it was placed at.
+    //     Restaurants add tax to bills. Existing items and orders were free,
+    //     and existing restaurants charge USD with no tax.
+    r#"
+    ALTER TABLE restaurants ADD COLUMN currency TEXT NOT NULL DEFAULT 'USD';
+    ALTER TABLE restaurants ADD COLUMN tax_rate_bps INTEGER NOT NULL DEFAULT 0;
+
+    ALTER TABLE menu ADD COLUMN price INTEGER NOT NULL DEFAULT 0;
+
+    ALTER TABLE orders ADD COLUMN unit_price INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE archived_orders ADD COLUMN unit_price INTEGER NOT NULL DEFAULT 0;
+    "#,
+    // 16: A party's bill can be split into several checks, stored as JSON.
+    //     Existing parties have a single check.
+    r#"
+    ALTER TABLE parties ADD COLUMN check_split TEXT NOT NULL DEFAULT '{"kind":"single"}';
+    "#,
+    // 17: Order lines can say which seat at the table they are for. Existing
+    //     orders don't know.
+    r#"
+    ALTER TABLE orders ADD COLUMN seat INTEGER;
+    ALTER TABLE archived_orders ADD COLUMN seat INTEGER;
+    "#,
+    // 18: Managers, who approve discounts, comps and voids with a PIN kept
+    //     only as a salted hash. Each adjustment belongs to the party whose
+    //     bill it changes, and names the order it is for unless it is a
+    //     discount on the whole bill. Orders aren't referenced by key, as
+    //     close-outs move them to the archive.
+    r#"
+    CREATE TABLE managers (
+        restaurant_id INTEGER NOT NULL REFERENCES restaurants (id),
+        id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        pin_hash TEXT NOT NULL,
+        active INTEGER NOT NULL DEFAULT 1,
+        PRIMARY KEY (restaurant_id, id)
+    );
+    CREATE UNIQUE INDEX managers_name ON managers (restaurant_id, name);
+
+    CREATE TABLE adjustments (
+        restaurant_id INTEGER NOT NULL,
+        id INTEGER NOT NULL,
+        party_id INTEGER NOT NULL,
+        table_id INTEGER NOT NULL,
+        order_id INTEGER,
+        kind TEXT NOT NULL,
+        discount TEXT,
+        reason TEXT NOT NULL,
+        requested_by TEXT NOT NULL,
+        approved_by TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        PRIMARY KEY (restaurant_id, id),
+        FOREIGN KEY (restaurant_id, party_id) REFERENCES parties (restaurant_id, id)
+    );
+    CREATE INDEX adjustments_party ON adjustments (restaurant_id, party_id);
+    "#,
+    // 19: Payments towards a party's bill, kept whatever became of them so
+    //     declines and refunds can be traced.
+    r#"
+    CREATE TABLE payments (
+        restaurant_id INTEGER NOT NULL,
+        id INTEGER NOT NULL,
+        party_id INTEGER NOT NULL,
+        table_id INTEGER NOT NULL,
+        tender TEXT NOT NULL,
+        amount INTEGER NOT NULL,
+        tip INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        reference TEXT,
+        failure TEXT,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        PRIMARY KEY (restaurant_id, id),
+        FOREIGN KEY (restaurant_id, party_id) REFERENCES parties (restaurant_id, id)
+    );
+    CREATE INDEX payments_party ON payments (restaurant_id, party_id);
+    "#,
+    // 20: Archived orders keep the station they were made at and how long
+    //     the kitchen was told they take. Orders archived before this are
+    //     put down to their item's station, and their prep time isn't known.
+    r#"
+    ALTER TABLE archived_orders ADD COLUMN station TEXT NOT NULL DEFAULT 'grill';
+    ALTER TABLE archived_orders ADD COLUMN prep_secs INTEGER;
+    UPDATE archived_orders SET station = COALESCE((SELECT station FROM menu
+        WHERE menu.restaurant_id == archived_orders.restaurant_id
+        AND menu.id == archived_orders.item_id), 'grill');
+    "#,
+];
+
+const VERSION_TABLE_QUERY: &str = r#"
+    CREATE TABLE IF NOT EXISTS schema_version (
+        version INTEGER PRIMARY KEY,
+        applied_at TEXT NOT NULL
+    );
+"#;
+
+/// The schema version a fully migrated database is at.
+pub fn latest_version() -> usize {
+    MIGRATIONS.len()
+}
+
+/// Brings the database up to the latest schema version, applying each pending
+/// migration in its own transaction. A brand new database is instead built
+/// and then filled by `seed` in a single transaction, so a boot which dies
+/// part way leaves nothing behind and the next one starts afresh. Returns the
+/// version the database was at before migrating, where 0 means the database
+/// was brand new.
+pub fn run(
+    conn: &mut Connection,
+    seed: impl FnOnce(&Connection) -> rusqlite::Result<()>,
+) -> rusqlite::Result<usize> {
+    conn.execute_batch(VERSION_TABLE_QUERY)?;
+
+    let current: usize = conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version;",
+        [],
+        |row| row.get(0),
+    )?;
+
+    if current > latest_version() {
+        panic!(
+            "Database schema version {current} is newer than this server supports ({})",
+            latest_version()
+        );
+    }
+
+    if current == 0 {
+        let tx = conn.transaction()?;
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            apply(&tx, index + 1, migration)?;
+        }
+        seed(&tx)?;
+        tx.commit()?;
+        println!("Applied migrations 1 to {}", latest_version());
+        return Ok(current);
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+        let version = index + 1;
+        let tx = conn.transaction()?;
+        apply(&tx, version, migration)?;
+        tx.commit()?;
+        println!("Applied migration {version}");
+    }
+
+    Ok(current)
+}
+
+fn apply(conn: &Connection, version: usize, migration: &str) -> rusqlite::Result<()> {
+    conn.execute_batch(migration)?;
+    conn.execute(
+        "INSERT INTO schema_version (version, applied_at) VALUES (?1, ?2);",
+        (version, chrono::Utc::now().to_rfc3339()),
+    )?;
+    Ok(())
+}
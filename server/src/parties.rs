@@ -0,0 +1,85 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use shared::{Party, PartyDetails, SeatPartyRequest};
+
+use crate::storage::Storage;
+use crate::{ServiceResponse, ServiceState};
+
+/// Routes seating guest parties at tables and checking them out
+pub fn routes<S: Storage>() -> Router<ServiceState<S>> {
+    Router::new()
+        .route(
+            "/tables/:table_id/party",
+            get(get_open_party::<S>).post(seat_party::<S>),
+        )
+        .route("/tables/:table_id/parties", get(get_table_parties::<S>))
+        .route("/parties/:party_id", get(get_party::<S>))
+        .route("/parties/:party_id/close", post(close_party::<S>))
+}
+
+// Client: seat a party of guests at a free table, so orders can be placed for it
+async fn seat_party<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+    Json(payload): Json<SeatPartyRequest>,
+) -> ServiceResponse<Json<Party>> {
+    println!("seat_party {restaurant_id}, {table_id}, {}", payload.guests);
+    let party = state
+        .storage
+        .seat_party(restaurant_id, table_id, payload)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(party)))
+}
+
+// Client: query the party currently seated at a table
+async fn get_open_party<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+) -> ServiceResponse<Json<Party>> {
+    println!("get_open_party {restaurant_id}, {table_id}");
+    let party = state
+        .storage
+        .get_open_party(restaurant_id, table_id)
+        .await?;
+
+    Ok((StatusCode::OK, Json(party)))
+}
+
+// Manager: list every party ever seated at a table, oldest first
+async fn get_table_parties<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+) -> ServiceResponse<Json<Vec<Party>>> {
+    println!("get_table_parties {restaurant_id}, {table_id}");
+    let parties = state
+        .storage
+        .get_table_parties(restaurant_id, table_id)
+        .await?;
+
+    Ok((StatusCode::OK, Json(parties)))
+}
+
+// Manager: query a party with every order it placed, including archived ones
+async fn get_party<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, party_id)): Path<(i64, i64)>,
+) -> ServiceResponse<Json<PartyDetails>> {
+    println!("get_party {restaurant_id}, {party_id}");
+    let party = state.storage.get_party(restaurant_id, party_id).await?;
+
+    Ok((StatusCode::OK, Json(party)))
+}
+
+// Client: check the party out, finishing all its remaining orders and freeing the table
+async fn close_party<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, party_id)): Path<(i64, i64)>,
+) -> ServiceResponse<Json<Party>> {
+    println!("close_party {restaurant_id}, {party_id}");
+    let party = state.storage.close_party(restaurant_id, party_id).await?;
+
+    Ok((StatusCode::OK, Json(party)))
+}
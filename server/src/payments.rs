@@ -0,0 +1,226 @@
+use std::future::Future;
+
+use axum::extract::{Extension, Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use shared::{
+    Bill, CapturePaymentRequest, NewPayment, Payment, PaymentStatus, Restaurant, TablePayments,
+};
+
+use crate::billing;
+use crate::processor::{self, ProcessorError};
+use crate::storage::{PaymentChange, Storage, StorageError};
+use crate::{HttpError, ServiceResponse, ServiceState};
+
+/// Routes settling tables' bills
+pub fn routes<S: Storage>() -> Router<ServiceState<S>> {
+    Router::new()
+        .route(
+            "/tables/:table_id/payments",
+            get(get_payments::<S>).post(add_payment::<S>),
+        )
+        .route("/payments/:payment_id/capture", post(capture_payment::<S>))
+        .route("/payments/:payment_id/void", post(void_payment::<S>))
+        .route("/payments/:payment_id/refund", post(refund_payment::<S>))
+}
+
+// Client: show the payments made towards the table's bill and what is still owed
+async fn get_payments<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Extension(restaurant): Extension<Restaurant>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+) -> ServiceResponse<Json<TablePayments>> {
+    println!("get_payments {restaurant_id}, {table_id}");
+    let bill = billing::fetch_bill(&state.storage, &restaurant, restaurant_id, table_id).await?;
+    let payments = state.storage.get_payments(restaurant_id, table_id).await?;
+
+    Ok((StatusCode::OK, Json(table_payments(bill, payments))))
+}
+
+// Client: pay towards the table's bill. Cash is taken straight away, while
+// cards are authorized by the processor and captured afterwards.
+async fn add_payment<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Extension(restaurant): Extension<Restaurant>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+    Json(payload): Json<NewPayment>,
+) -> ServiceResponse<Json<Payment>> {
+    println!(
+        "add_payment {restaurant_id}, {table_id}, {:?}, {}, {}",
+        payload.tender, payload.amount, payload.tip
+    );
+    let payment = state
+        .storage
+        .add_payment(restaurant_id, table_id, payload)
+        .await?;
+    if payment.status != PaymentStatus::Pending {
+        return Ok((StatusCode::CREATED, Json(payment)));
+    }
+
+    let currency = restaurant.currency;
+    detached(authorize(state, restaurant_id, payment, currency)).await
+}
+
+/// Has the processor authorize a pending card payment and records its
+/// answer, whichever it is
+async fn authorize<S: Storage>(
+    state: ServiceState<S>,
+    restaurant_id: i64,
+    payment: Payment,
+    currency: String,
+) -> ServiceResponse<Json<Payment>> {
+    let answer = state
+        .processor
+        .authorize(payment.amount + payment.tip, &currency);
+    let (change, error) = match processor::call(answer, state.config.processor_timeout).await {
+        Ok(reference) => (PaymentChange::Authorized { reference }, None),
+        Err(ProcessorError::Declined(reason)) => (
+            PaymentChange::Declined {
+                reason: reason.clone(),
+            },
+            Some(ProcessorError::Declined(reason)),
+        ),
+        Err(ProcessorError::TimedOut) => (PaymentChange::TimedOut, Some(ProcessorError::TimedOut)),
+    };
+    let payment = state
+        .storage
+        .update_payment(restaurant_id, payment.id, change)
+        .await?;
+
+    match error {
+        Some(error) => Err(HttpError::from(error)),
+        None => Ok((StatusCode::CREATED, Json(payment))),
+    }
+}
+
+// Client: take an authorized card payment, with the tip as finally given
+async fn capture_payment<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, payment_id)): Path<(i64, i64)>,
+    Json(payload): Json<CapturePaymentRequest>,
+) -> ServiceResponse<Json<Payment>> {
+    println!(
+        "capture_payment {restaurant_id}, {payment_id}, {:?}",
+        payload.tip
+    );
+    let payment = state.storage.get_payment(restaurant_id, payment_id).await?;
+    let tip = payload.tip.unwrap_or(payment.tip);
+    change_payment(
+        state,
+        restaurant_id,
+        payment,
+        PaymentChange::Captured { tip },
+    )
+    .await
+}
+
+// Client: let go of an authorized card payment without taking it
+async fn void_payment<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, payment_id)): Path<(i64, i64)>,
+) -> ServiceResponse<Json<Payment>> {
+    println!("void_payment {restaurant_id}, {payment_id}");
+    let payment = state.storage.get_payment(restaurant_id, payment_id).await?;
+    change_payment(state, restaurant_id, payment, PaymentChange::Voided).await
+}
+
+// Manager: give a captured payment back, even once the party has left
+async fn refund_payment<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, payment_id)): Path<(i64, i64)>,
+) -> ServiceResponse<Json<Payment>> {
+    println!("refund_payment {restaurant_id}, {payment_id}");
+    let payment = state.storage.get_payment(restaurant_id, payment_id).await?;
+    change_payment(state, restaurant_id, payment, PaymentChange::Refunded).await
+}
+
+/// Claims a payment for `change`, so a second request to change it is turned
+/// away rather than reaching the processor too, then has the change made
+async fn change_payment<S: Storage>(
+    state: ServiceState<S>,
+    restaurant_id: i64,
+    payment: Payment,
+    change: PaymentChange,
+) -> ServiceResponse<Json<Payment>> {
+    let claim = change
+        .claim()
+        .expect("only captures, voids and refunds are asked for");
+    let payment = state
+        .storage
+        .update_payment(restaurant_id, payment.id, claim)
+        .await?;
+
+    detached(finish_change(state, restaurant_id, payment, change)).await
+}
+
+/// Has the processor make `change` to a claimed card payment, then records
+/// it. The payment goes back to where it was when the processor declines,
+/// and is left unconfirmed when it doesn't answer, as the change may still
+/// have been made. Cash never went through the processor, so it is only
+/// recorded.
+async fn finish_change<S: Storage>(
+    state: ServiceState<S>,
+    restaurant_id: i64,
+    payment: Payment,
+    change: PaymentChange,
+) -> ServiceResponse<Json<Payment>> {
+    if let Some(reference) = &payment.reference {
+        let processor = &state.processor;
+        let answer = match &change {
+            PaymentChange::Captured { tip } => processor.capture(reference, payment.amount + tip),
+            PaymentChange::Refunded => processor.refund(reference, payment.amount + payment.tip),
+            PaymentChange::Voided => processor.void(reference),
+            _ => unreachable!("the processor only answers authorizations when they're made"),
+        };
+        if let Err(error) = processor::call(answer, state.config.processor_timeout).await {
+            let outcome = match error {
+                ProcessorError::Declined(_) => PaymentChange::Abandoned,
+                ProcessorError::TimedOut => PaymentChange::Unconfirmed,
+            };
+            state
+                .storage
+                .update_payment(restaurant_id, payment.id, outcome)
+                .await?;
+            return Err(HttpError::from(error));
+        }
+    }
+
+    let payment = state
+        .storage
+        .update_payment(restaurant_id, payment.id, change)
+        .await?;
+
+    Ok((StatusCode::OK, Json(payment)))
+}
+
+/// Runs `task` on a task of its own, so what the processor made of a payment
+/// is still recorded when the client hangs up first. Otherwise the payment
+/// would be left part way, and the party could never leave.
+async fn detached<T: Send + 'static>(
+    task: impl Future<Output = ServiceResponse<T>> + Send + 'static,
+) -> ServiceResponse<T> {
+    tokio::spawn(task)
+        .await
+        .map_err(|e| HttpError::from(StorageError::Internal(e.to_string())))?
+}
+
+/// Totals what the table's payments have settled of `bill`
+fn table_payments(bill: Bill, payments: Vec<Payment>) -> TablePayments {
+    let (paid, tips) = payments
+        .iter()
+        .filter(|payment| payment.status == PaymentStatus::Captured)
+        .fold((0, 0), |(paid, tips), payment| {
+            (paid + payment.amount, tips + payment.tip)
+        });
+
+    TablePayments {
+        table_id: bill.table_id,
+        currency: bill.currency,
+        total: bill.total,
+        paid,
+        tips,
+        balance: bill.total - paid,
+        payments,
+    }
+}
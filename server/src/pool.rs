@@ -0,0 +1,146 @@
+use std::sync::{Arc, Mutex};
+
+use rusqlite::Connection;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::storage::{StorageError, StorageResult};
+
+/// A fixed-size pool of connections to one SQLite file. Queries run on tokio's
+/// blocking thread pool, so slow SQLite work never stalls the async runtime,
+/// and separate connections let independent reads run at the same time.
+pub struct Pool {
+    path: Arc<str>,
+    idle: Arc<Mutex<Vec<Connection>>>,
+    /// One per idle connection, so holding a permit means one is there to take
+    permits: Arc<Semaphore>,
+}
+
+impl Pool {
+    /// Wraps already opened connections. `path` is used to replace any
+    /// connection lost to a panicking query.
+    pub fn new(path: &str, connections: Vec<Connection>) -> Self {
+        Self {
+            path: path.into(),
+            permits: Arc::new(Semaphore::new(connections.len())),
+            idle: Arc::new(Mutex::new(connections)),
+        }
+    }
+
+    /// Opens a connection to `path` configured for concurrent use: WAL journaling
+    /// so readers don't block the writer, and a busy timeout so writers queue up
+    /// behind each other instead of failing straight away.
+    pub fn connect(path: &str) -> rusqlite::Result<Connection> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            r#"
+            PRAGMA journal_mode = WAL;
+            PRAGMA synchronous = NORMAL;
+            PRAGMA busy_timeout = 5000;
+            PRAGMA foreign_keys = ON;
+            "#,
+        )?;
+        Ok(conn)
+    }
+
+    /// Runs `query` against a pooled connection on the blocking thread pool,
+    /// waiting for a connection to free up if all of them are busy. The
+    /// connection goes back to the pool once the query finishes, even if the
+    /// caller stopped waiting for it.
+    pub async fn run<T, F>(&self, query: F) -> StorageResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> StorageResult<T> + Send + 'static,
+    {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| StorageError::Internal(e.to_string()))?;
+        let conn = self.idle.lock().unwrap().pop().ok_or_else(|| {
+            StorageError::Internal("no idle connection despite holding a permit".to_string())
+        })?;
+        let mut lease = Lease {
+            conn: Some(conn),
+            permit: Some(permit),
+            pool: self.idle.clone(),
+            path: self.path.clone(),
+        };
+
+        tokio::task::spawn_blocking(move || query(lease.conn.as_mut().unwrap()))
+            .await
+            .map_err(|e| StorageError::Internal(e.to_string()))?
+    }
+}
+
+/// A connection taken from the pool along with its permit. It moves into the
+/// blocking task, so both go back together whenever the task is done with
+/// them, whether or not anyone is still waiting on its result.
+struct Lease {
+    conn: Option<Connection>,
+    permit: Option<OwnedSemaphorePermit>,
+    pool: Arc<Mutex<Vec<Connection>>>,
+    path: Arc<str>,
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        // A panicking query may have left its connection part way through a
+        // transaction, so a fresh one takes its place
+        let conn = match self.conn.take() {
+            Some(conn) if !std::thread::panicking() => Ok(conn),
+            _ => Pool::connect(&self.path),
+        };
+
+        match conn {
+            Ok(conn) => self.pool.lock().unwrap().push(conn),
+            // Without a connection to give back the pool shrinks by one, and
+            // so do its permits
+            Err(e) => {
+                println!("Failed to replace a pooled connection: {e}");
+                if let Some(permit) = self.permit.take() {
+                    permit.forget();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn cancelled_queries_give_their_connection_back() {
+        let pool = Pool::new(":memory:", vec![Pool::connect(":memory:").unwrap()]);
+
+        for _ in 0..3 {
+            let slow = pool.run(|_| {
+                std::thread::sleep(Duration::from_millis(50));
+                Ok(())
+            });
+            assert!(tokio::time::timeout(Duration::from_millis(5), slow)
+                .await
+                .is_err());
+        }
+
+        let answer = pool
+            .run(|conn| {
+                conn.query_row("SELECT 42;", [], |row| row.get::<_, i64>(0))
+                    .map_err(|e| StorageError::Internal(e.to_string()))
+            })
+            .await;
+        assert_eq!(answer.ok(), Some(42));
+    }
+
+    #[tokio::test]
+    async fn panicking_queries_are_replaced() {
+        let pool = Pool::new(":memory:", vec![Pool::connect(":memory:").unwrap()]);
+
+        let panicked = pool.run(|_| -> StorageResult<()> { panic!("query failed") });
+        assert!(matches!(panicked.await, Err(StorageError::Internal(_))));
+        assert!(pool.run(|_| Ok(())).await.is_ok());
+    }
+}
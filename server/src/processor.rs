@@ -0,0 +1,124 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub type ProcessorResult<T> = Result<T, ProcessorError>;
+
+/// An answer the processor is working on. Boxed, so the processor can be
+/// chosen at startup and kept behind a trait object.
+pub type ProcessorFuture<'a, T> = Pin<Box<dyn Future<Output = ProcessorResult<T>> + Send + 'a>>;
+
+#[derive(Debug)]
+pub enum ProcessorError {
+    /// The processor turned the request down, giving its reason
+    Declined(String),
+    /// The processor didn't answer in time, so whether it acted is unknown
+    TimedOut,
+}
+
+/// Takes card payments. Amounts are in the restaurant's minor units and
+/// include any tip.
+pub trait Processor: Send + Sync {
+    /// Holds `amount` on the card, answering with the processor's reference
+    /// for the hold
+    fn authorize(&self, amount: i64, currency: &str) -> ProcessorFuture<'_, String>;
+
+    /// Takes `amount` from what `reference` holds
+    fn capture(&self, reference: &str, amount: i64) -> ProcessorFuture<'_, ()>;
+
+    /// Gives back `amount` of what `reference` took
+    fn refund(&self, reference: &str, amount: i64) -> ProcessorFuture<'_, ()>;
+
+    /// Lets go of the hold without taking anything
+    fn void(&self, reference: &str) -> ProcessorFuture<'_, ()>;
+}
+
+/// Waits for the processor's answer, giving up after `timeout`
+pub async fn call<T>(answer: ProcessorFuture<'_, T>, timeout: Duration) -> ProcessorResult<T> {
+    tokio::time::timeout(timeout, answer)
+        .await
+        .unwrap_or(Err(ProcessorError::TimedOut))
+}
+
+/// How the mock processor answers one call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockOutcome {
+    Approve,
+    Decline,
+    /// Never answers, so the call times out
+    Timeout,
+}
+
+impl FromStr for MockOutcome {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "approve" => Ok(MockOutcome::Approve),
+            "decline" => Ok(MockOutcome::Decline),
+            "timeout" => Ok(MockOutcome::Timeout),
+            other => Err(format!("unknown mock processor outcome '{other}'")),
+        }
+    }
+}
+
+/// A processor kept in process, which never touches the network. Each call
+/// takes the next outcome from its script, and once the script runs out
+/// every call is approved.
+pub struct MockProcessor {
+    script: Mutex<VecDeque<MockOutcome>>,
+    next_reference: AtomicU64,
+}
+
+impl MockProcessor {
+    pub fn new(script: impl IntoIterator<Item = MockOutcome>) -> Self {
+        Self {
+            script: Mutex::new(script.into_iter().collect()),
+            next_reference: AtomicU64::new(1),
+        }
+    }
+
+    /// Answers the next call as the script says, with `approved` if it is
+    /// approved
+    fn answer<T: Send + 'static>(&self, approved: T) -> ProcessorFuture<'_, T> {
+        let outcome = self
+            .script
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or(MockOutcome::Approve);
+
+        Box::pin(async move {
+            match outcome {
+                MockOutcome::Approve => Ok(approved),
+                MockOutcome::Decline => Err(ProcessorError::Declined(
+                    "declined by the mock processor".to_string(),
+                )),
+                MockOutcome::Timeout => std::future::pending().await,
+            }
+        })
+    }
+}
+
+impl Processor for MockProcessor {
+    fn authorize(&self, _amount: i64, _currency: &str) -> ProcessorFuture<'_, String> {
+        let reference = self.next_reference.fetch_add(1, Ordering::Relaxed);
+        self.answer(format!("mock_{reference}"))
+    }
+
+    fn capture(&self, _reference: &str, _amount: i64) -> ProcessorFuture<'_, ()> {
+        self.answer(())
+    }
+
+    fn refund(&self, _reference: &str, _amount: i64) -> ProcessorFuture<'_, ()> {
+        self.answer(())
+    }
+
+    fn void(&self, _reference: &str) -> ProcessorFuture<'_, ()> {
+        self.answer(())
+    }
+}
@@ -0,0 +1,92 @@
+use axum::extract::{Path, Request, State};
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::Response;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Deserialize;
+use shared::{NewRestaurant, Restaurant, RestaurantPatch};
+
+use crate::storage::Storage;
+use crate::{HttpError, ServiceResponse, ServiceState};
+
+/// Routes listing and adding the restaurants hosted by this server
+pub fn routes<S: Storage>() -> Router<ServiceState<S>> {
+    Router::new()
+        .route(
+            "/restaurants",
+            get(get_restaurants::<S>).post(create_restaurant::<S>),
+        )
+        .route(
+            "/restaurants/:restaurant_id",
+            get(get_restaurant::<S>).patch(patch_restaurant::<S>),
+        )
+}
+
+#[derive(Deserialize)]
+pub struct RestaurantPath {
+    restaurant_id: i64,
+}
+
+/// Refuses requests under `/restaurants/:restaurant_id` for a restaurant
+/// which doesn't exist, before they reach any handler. Handlers which need
+/// the restaurant itself, e.g. for its time zone, can take it as an `Extension`.
+pub async fn require_restaurant<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path(path): Path<RestaurantPath>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, HttpError> {
+    let restaurant = state.storage.get_restaurant(path.restaurant_id).await?;
+    request.extensions_mut().insert(restaurant);
+
+    Ok(next.run(request).await)
+}
+
+// Admin: list every restaurant
+async fn get_restaurants<S: Storage>(
+    State(state): State<ServiceState<S>>,
+) -> ServiceResponse<Json<Vec<Restaurant>>> {
+    println!("get_restaurants");
+    let restaurants = state.storage.get_restaurants().await?;
+
+    Ok((StatusCode::OK, Json(restaurants)))
+}
+
+// Admin: query a single restaurant
+async fn get_restaurant<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path(restaurant_id): Path<i64>,
+) -> ServiceResponse<Json<Restaurant>> {
+    println!("get_restaurant {restaurant_id}");
+    let restaurant = state.storage.get_restaurant(restaurant_id).await?;
+
+    Ok((StatusCode::OK, Json(restaurant)))
+}
+
+// Admin: rename a restaurant or change the time zone it shows times in
+async fn patch_restaurant<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path(restaurant_id): Path<i64>,
+    Json(payload): Json<RestaurantPatch>,
+) -> ServiceResponse<Json<Restaurant>> {
+    println!("patch_restaurant {restaurant_id}");
+    let restaurant = state
+        .storage
+        .update_restaurant(restaurant_id, payload)
+        .await?;
+
+    Ok((StatusCode::OK, Json(restaurant)))
+}
+
+// Admin: add a restaurant, starting with an empty menu and no orders, in UTC unless
+// a time zone is given
+async fn create_restaurant<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Json(payload): Json<NewRestaurant>,
+) -> ServiceResponse<Json<Restaurant>> {
+    println!("create_restaurant {}", payload.name);
+    let restaurant = state.storage.create_restaurant(payload).await?;
+
+    Ok((StatusCode::CREATED, Json(restaurant)))
+}
@@ -0,0 +1,76 @@
+use axum::extract::{Extension, Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use shared::{ClaimLineRequest, Restaurant, Station, StationLine};
+
+use crate::storage::Storage;
+use crate::timing;
+use crate::{ServiceResponse, ServiceState};
+
+/// Routes the kitchen stations work their queues through
+pub fn routes<S: Storage>() -> Router<ServiceState<S>> {
+    Router::new()
+        .route("/stations/:station_id/queue", get(get_station_queue::<S>))
+        .route(
+            "/stations/:station_id/queue/:order_id/claim",
+            post(claim_line::<S>),
+        )
+        .route(
+            "/stations/:station_id/queue/:order_id/complete",
+            post(complete_line::<S>),
+        )
+}
+
+// Kitchen: list the orders waiting at a station, the soonest due first
+async fn get_station_queue<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Extension(restaurant): Extension<Restaurant>,
+    Path((restaurant_id, station)): Path<(i64, Station)>,
+) -> ServiceResponse<Json<Vec<StationLine>>> {
+    println!("get_station_queue {restaurant_id}, {station}");
+    let mut queue = state
+        .storage
+        .get_station_queue(restaurant_id, station)
+        .await?;
+    timing::annotate(queue.iter_mut().map(|line| &mut line.order), &restaurant);
+
+    Ok((StatusCode::OK, Json(queue)))
+}
+
+// Kitchen: claim a waiting order, starting it on behalf of whoever is making it.
+// An order someone else has claimed is refused with a 409.
+async fn claim_line<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Extension(restaurant): Extension<Restaurant>,
+    Path((restaurant_id, station, order_id)): Path<(i64, Station, i64)>,
+    Json(payload): Json<ClaimLineRequest>,
+) -> ServiceResponse<Json<StationLine>> {
+    println!(
+        "claim_line {restaurant_id}, {station}, {order_id}, {}",
+        payload.claimed_by
+    );
+    let mut line = state
+        .storage
+        .claim_line(restaurant_id, station, order_id, payload)
+        .await?;
+    timing::annotate([&mut line.order], &restaurant);
+
+    Ok((StatusCode::OK, Json(line)))
+}
+
+// Kitchen: mark a claimed order as ready, taking it off the station's queue
+async fn complete_line<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Extension(restaurant): Extension<Restaurant>,
+    Path((restaurant_id, station, order_id)): Path<(i64, Station, i64)>,
+) -> ServiceResponse<Json<StationLine>> {
+    println!("complete_line {restaurant_id}, {station}, {order_id}");
+    let mut line = state
+        .storage
+        .complete_line(restaurant_id, station, order_id)
+        .await?;
+    timing::annotate([&mut line.order], &restaurant);
+
+    Ok((StatusCode::OK, Json(line)))
+}
@@ -0,0 +1,2565 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+
+use chrono::{Duration, NaiveDate};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{constant_time, pbkdf2};
+use sha2::{Digest, Sha256};
+use shared::{
+    format_money, Adjustment, AdjustmentKind, ArchivedOrder, Bill, BillLine, CheckAssignment,
+    CheckSplit, ClaimLineRequest, CloseOutSummary, DataExport, Discount, ImportReport, Manager,
+    Menu, MenuItem, MenuItemPatch, Modifier, NewAdjustment, NewManager, NewMenuItem, NewPayment,
+    NewRestaurant, NewTable, OrderHistoryEntry, OrderItemsRequest, OrderLine, OrderRejection,
+    OrderStatus, Party, PartyDetails, Payment, PaymentStatus, RebuildReport, RejectedItem,
+    RejectionReason, Removal, RemoveOrderRequest, Restaurant, RestaurantPatch, SeatPartyRequest,
+    Station, StationLine, Table, TableOrder, TablePatch,
+};
+use tokio::sync::broadcast;
+
+use crate::catalog::CatalogChanges;
+use crate::events;
+use crate::live::LiveUpdate;
+use crate::timing;
+
+/// The restaurant every fresh database starts with, which data from before
+/// restaurants existed belongs to
+pub const DEFAULT_RESTAURANT_ID: i64 = 1;
+pub const DEFAULT_RESTAURANT_NAME: &str = "Default";
+/// Time zone of restaurants which weren't given one
+pub const DEFAULT_TIME_ZONE: &str = "UTC";
+/// How many orders a restaurant's kitchen cooks at once unless told otherwise
+pub const DEFAULT_KITCHEN_SLOTS: i64 = 4;
+/// Currency of restaurants which weren't given one
+pub const DEFAULT_CURRENCY: &str = "USD";
+/// The highest tax rate a restaurant can charge, in basis points
+pub const MAX_TAX_RATE_BPS: i64 = 10_000;
+/// The most a menu item or modifier can cost, in minor units, which keeps
+/// every bill well clear of overflowing
+pub const MAX_PRICE: i64 = 1_000_000_000;
+/// The most checks a party's bill can be split into
+pub const MAX_CHECKS: i64 = 50;
+
+/// How many tables the default restaurant of a fresh database starts with,
+/// labelled "Table 1" onwards in the main section
+pub const DEFAULT_TABLE_COUNT: i64 = 20;
+pub const DEFAULT_TABLE_SECTION: &str = "Main";
+pub const DEFAULT_TABLE_SEATS: i64 = 4;
+
+/// The menu every fresh database starts with, priced in the default currency:
+/// (name, prep_min_m, prep_max_m, station, price)
+pub const ITEMS_MCDONALDS: &[(&str, f32, f32, Station, i64)] = &[
+    ("Big Mac", 6.0, 12.0, Station::Grill, 599),
+    ("Quarter Pounder with Cheese", 5.0, 8.0, Station::Grill, 649),
+    ("Cheeseburger", 5.0, 8.0, Station::Grill, 279),
+    ("McChicken", 8.0, 12.0, Station::Fryer, 399),
+    ("Filet-O-Fish", 5.0, 8.0, Station::Fryer, 529),
+    (
+        "Chicken McNuggets (10 pieces)",
+        6.0,
+        10.0,
+        Station::Fryer,
+        599,
+    ),
+    ("French Fries (Medium)", 5.0, 7.0, Station::Fryer, 379),
+    ("French Fries (Large)", 5.0, 7.0, Station::Fryer, 449),
+    ("McFlurry", 5.0, 15.0, Station::Dessert, 449),
+    ("Apple Pie", 5.0, 9.0, Station::Dessert, 179),
+    ("Egg McMuffin", 5.0, 7.0, Station::Grill, 479),
+    ("Sausage McMuffin", 5.0, 7.0, Station::Grill, 399),
+    ("Bacon, Egg & Cheese Biscuit", 5.0, 7.0, Station::Grill, 499),
+    ("Iced Coffee", 5.0, 6.0, Station::Drinks, 299),
+    ("McCafe Latte", 5.0, 6.0, Station::Drinks, 399),
+];
+
+/// Modifiers offered on the default menu:
+/// (item name, modifier name, price_delta, prep_delta_m)
+pub const MODIFIERS_MCDONALDS: &[(&str, &str, i64, f32)] = &[
+    ("Big Mac", "No pickles", 0, 0.0),
+    ("Big Mac", "Extra sauce", 30, 0.0),
+    ("Big Mac", "Extra patty", 150, 2.0),
+    ("Quarter Pounder with Cheese", "No cheese", -30, 0.0),
+    ("Quarter Pounder with Cheese", "No onions", 0, 0.0),
+    ("Cheeseburger", "No pickles", 0, 0.0),
+    ("McFlurry", "Extra topping", 50, 1.0),
+    ("Iced Coffee", "Oat milk", 40, 0.0),
+    ("McCafe Latte", "Oat milk", 40, 0.0),
+    ("McCafe Latte", "Extra shot", 60, 0.5),
+];
+
+/// The most of one item a single order line can hold
+pub const MAX_LINE_QUANTITY: i64 = 99;
+/// The longest notes an order line can carry, in characters
+pub const MAX_NOTES_CHARS: usize = 200;
+
+/// Errors a storage backend can report, independent of which backend is in use.
+#[derive(Debug)]
+pub enum StorageError {
+    /// The requested record does not exist.
+    NotFound(String),
+    /// The request can never succeed as written.
+    BadRequest(String),
+    /// The request was understood, but some of its contents were refused.
+    Rejected(OrderRejection),
+    /// The request is well formed but its values are not acceptable.
+    Invalid(String),
+    /// The request clashes with the current state of the data.
+    Conflict(String),
+    /// The credentials given don't allow the request.
+    Forbidden(String),
+    /// The backend itself failed.
+    Internal(String),
+}
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+/// Everything the HTTP layer needs from persistence. Each backend implements
+/// this once, and the handlers stay generic over it. Menus and orders belong
+/// to one restaurant, and each restaurant numbers its menu items and orders
+/// separately, so every call about them is scoped by `restaurant_id`.
+pub trait Storage: Clone + Send + Sync + 'static {
+    /// Fetches every restaurant hosted by this server
+    fn get_restaurants(&self) -> impl Future<Output = StorageResult<Vec<Restaurant>>> + Send;
+
+    /// Fetches a single restaurant, failing with `NotFound` if it doesn't exist
+    fn get_restaurant(
+        &self,
+        restaurant_id: i64,
+    ) -> impl Future<Output = StorageResult<Restaurant>> + Send;
+
+    /// Changes the fields set in `patch` on a restaurant. Changing how many
+    /// orders its kitchen cooks at once quotes every waiting order again.
+    fn update_restaurant(
+        &self,
+        restaurant_id: i64,
+        patch: RestaurantPatch,
+    ) -> impl Future<Output = StorageResult<Restaurant>> + Send;
+
+    /// Adds a new restaurant with an empty menu and no orders
+    fn create_restaurant(
+        &self,
+        restaurant: NewRestaurant,
+    ) -> impl Future<Output = StorageResult<Restaurant>> + Send;
+
+    /// Fetches every item currently on the menu, along with the menu version
+    fn get_menu(&self, restaurant_id: i64) -> impl Future<Output = StorageResult<Menu>> + Send;
+
+    /// Adds a new item to the menu
+    fn create_menu_item(
+        &self,
+        restaurant_id: i64,
+        item: NewMenuItem,
+    ) -> impl Future<Output = StorageResult<MenuItem>> + Send;
+
+    /// Changes the fields set in `patch` on an existing menu item
+    fn update_menu_item(
+        &self,
+        restaurant_id: i64,
+        item_id: i64,
+        patch: MenuItemPatch,
+    ) -> impl Future<Output = StorageResult<MenuItem>> + Send;
+
+    /// Removes an item from the menu. Items still referenced by orders can't
+    /// be deleted, but with `retire` set they are hidden from the menu instead.
+    fn delete_menu_item(
+        &self,
+        restaurant_id: i64,
+        item_id: i64,
+        retire: bool,
+    ) -> impl Future<Output = StorageResult<()>> + Send;
+
+    /// Brings the menu in line with a catalog. Items are matched by name: new
+    /// names are added, and names missing from the catalog are retired rather
+    /// than deleted so existing orders keep pointing at a valid item.
+    fn apply_catalog(
+        &self,
+        restaurant_id: i64,
+        items: Vec<NewMenuItem>,
+    ) -> impl Future<Output = StorageResult<CatalogChanges>> + Send;
+
+    /// Fetches every table in the restaurant's registry, active or not
+    fn get_tables(
+        &self,
+        restaurant_id: i64,
+    ) -> impl Future<Output = StorageResult<Vec<Table>>> + Send;
+
+    /// Fetches a single registered table
+    fn get_table(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> impl Future<Output = StorageResult<Table>> + Send;
+
+    /// Registers a new, active table. Labels are unique within a restaurant.
+    fn create_table(
+        &self,
+        restaurant_id: i64,
+        table: NewTable,
+    ) -> impl Future<Output = StorageResult<Table>> + Send;
+
+    /// Changes the fields set in `patch` on a registered table
+    fn update_table(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        patch: TablePatch,
+    ) -> impl Future<Output = StorageResult<Table>> + Send;
+
+    /// Removes a table from the registry. Tables which ever had a party or
+    /// orders can't be deleted, only deactivated, so their history stays attached.
+    fn delete_table(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> impl Future<Output = StorageResult<()>> + Send;
+
+    /// Fetches all orders which match the passed in table id. Fails with
+    /// `NotFound` if the table isn't registered.
+    fn get_tables_items(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> impl Future<Output = StorageResult<Vec<TableOrder>>> + Send;
+
+    /// Seats a party at an active table which doesn't already have one
+    fn seat_party(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        request: SeatPartyRequest,
+    ) -> impl Future<Output = StorageResult<Party>> + Send;
+
+    /// Fetches the party currently seated at the table
+    fn get_open_party(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> impl Future<Output = StorageResult<Party>> + Send;
+
+    /// Fetches every party ever seated at the table, oldest first
+    fn get_table_parties(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> impl Future<Output = StorageResult<Vec<Party>>> + Send;
+
+    /// Fetches a party, open or closed, with every order it placed
+    fn get_party(
+        &self,
+        restaurant_id: i64,
+        party_id: i64,
+    ) -> impl Future<Output = StorageResult<PartyDetails>> + Send;
+
+    /// Closes an open party, finishing all of its live orders so the table is
+    /// free for the next party
+    fn close_party(
+        &self,
+        restaurant_id: i64,
+        party_id: i64,
+    ) -> impl Future<Output = StorageResult<Party>> + Send;
+
+    /// Adds the passed in list of items onto the table's order, and returns
+    /// the updated list of ordered items. Either every item is stored, or none
+    /// are and the rejection lists what was wrong. The table must be registered
+    /// and active, and the orders belong to the party seated at it.
+    fn order_items(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        items: OrderItemsRequest,
+    ) -> impl Future<Output = StorageResult<Vec<TableOrder>>> + Send;
+
+    /// Removes the order, as long as the item and table id are correct, and
+    /// returns the remaining orders for the table. The order is kept, with who
+    /// removed it and why, for the table history.
+    fn delete_table_item(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        order_id: i64,
+        request: RemoveOrderRequest,
+    ) -> impl Future<Output = StorageResult<Vec<TableOrder>>> + Send;
+
+    /// Undoes the removal of an order, as long as it was removed no longer
+    /// than `window` ago and its party hasn't left, and returns the table's orders
+    fn restore_table_item(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        order_id: i64,
+        window: Duration,
+    ) -> impl Future<Output = StorageResult<Vec<TableOrder>>> + Send;
+
+    /// Moves a live order of the table to `status` and returns it. Orders only
+    /// move forward through their lifecycle, anything else is a conflict.
+    fn update_order_status(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        order_id: i64,
+        status: OrderStatus,
+    ) -> impl Future<Output = StorageResult<TableOrder>> + Send;
+
+    /// Fetches the orders waiting at a kitchen station, placed or in
+    /// progress, soonest due first
+    fn get_station_queue(
+        &self,
+        restaurant_id: i64,
+        station: Station,
+    ) -> impl Future<Output = StorageResult<Vec<StationLine>>> + Send;
+
+    /// Takes on a placed order waiting at the station, moving it to
+    /// in_progress under `request.claimed_by`
+    fn claim_line(
+        &self,
+        restaurant_id: i64,
+        station: Station,
+        order_id: i64,
+        request: ClaimLineRequest,
+    ) -> impl Future<Output = StorageResult<StationLine>> + Send;
+
+    /// Marks an order claimed at the station as ready, taking it off the queue
+    fn complete_line(
+        &self,
+        restaurant_id: i64,
+        station: Station,
+        order_id: i64,
+    ) -> impl Future<Output = StorageResult<StationLine>> + Send;
+
+    /// Fetches every order placed for the table, including removed ones
+    fn get_table_history(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> impl Future<Output = StorageResult<Vec<OrderHistoryEntry>>> + Send;
+
+    /// Fetches a single order for the given order and table id.
+    fn get_table_item(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        order_id: i64,
+    ) -> impl Future<Output = StorageResult<TableOrder>> + Send;
+
+    /// Fetches the table's live orders which are to be paid for, i.e. all
+    /// but cancelled ones, each named after its menu item
+    fn get_bill_lines(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> impl Future<Output = StorageResult<Vec<BillLine>>> + Send;
+
+    /// Fetches how the bill of the party seated at the table is split. A
+    /// table with nobody seated has a single check.
+    fn get_check_split(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> impl Future<Output = StorageResult<CheckSplit>> + Send;
+
+    /// Changes how the bill of the party seated at the table is split,
+    /// checked against the table's bill lines as they are at the time
+    fn split_checks(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        change: SplitChange,
+    ) -> impl Future<Output = StorageResult<CheckSplit>> + Send;
+
+    /// Fetches the restaurant's managers, including ones who have left
+    fn get_managers(
+        &self,
+        restaurant_id: i64,
+    ) -> impl Future<Output = StorageResult<Vec<Manager>>> + Send;
+
+    /// Adds a manager, storing only a hash of their PIN. Names are unique
+    /// within a restaurant.
+    fn add_manager(
+        &self,
+        restaurant_id: i64,
+        manager: NewManager,
+    ) -> impl Future<Output = StorageResult<Manager>> + Send;
+
+    /// Stops a manager approving anything more. What they approved before
+    /// still names them.
+    fn retire_manager(
+        &self,
+        restaurant_id: i64,
+        manager_id: i64,
+    ) -> impl Future<Output = StorageResult<Manager>> + Send;
+
+    /// Fetches the adjustments made to the bill of the party seated at the
+    /// table, oldest first. A table with nobody seated has none.
+    fn get_adjustments(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> impl Future<Output = StorageResult<Vec<Adjustment>>> + Send;
+
+    /// Makes a discount, comp or void to the bill of the party seated at the
+    /// table once its manager's approval has been checked
+    fn add_adjustment(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        request: NewAdjustment,
+    ) -> impl Future<Output = StorageResult<Adjustment>> + Send;
+
+    /// Fetches the payments tried for the bill of the party seated at the
+    /// table, oldest first. A table with nobody seated has none.
+    fn get_payments(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+    ) -> impl Future<Output = StorageResult<Vec<Payment>>> + Send;
+
+    /// Fetches a single payment, whether or not its party has left
+    fn get_payment(
+        &self,
+        restaurant_id: i64,
+        payment_id: i64,
+    ) -> impl Future<Output = StorageResult<Payment>> + Send;
+
+    /// Records a payment towards the bill of the party seated at the table.
+    /// Cash is captured straight away, while card payments are pending until
+    /// the processor answers. No more can be paid than is left owing once
+    /// every payment still going through is counted.
+    fn add_payment(
+        &self,
+        restaurant_id: i64,
+        table_id: i64,
+        payment: NewPayment,
+    ) -> impl Future<Output = StorageResult<Payment>> + Send;
+
+    /// Moves a payment on once the processor has answered
+    fn update_payment(
+        &self,
+        restaurant_id: i64,
+        payment_id: i64,
+        change: PaymentChange,
+    ) -> impl Future<Output = StorageResult<Payment>> + Send;
+
+    /// Replays the order event log and compares the result with the stored
+    /// orders. Unless `dry_run` is set, the orders are then replaced with
+    /// what the log says they should be.
+    fn rebuild_orders(
+        &self,
+        dry_run: bool,
+    ) -> impl Future<Output = StorageResult<RebuildReport>> + Send;
+
+    /// Writes a consistent copy of the whole database to a new file at
+    /// `path`, without blocking other queries while it runs
+    fn backup(&self, path: PathBuf) -> impl Future<Output = StorageResult<()>> + Send;
+
+    /// Replaces the whole database with a backup taken by `backup`, then
+    /// migrates it to the latest schema
+    fn restore(&self, path: PathBuf) -> impl Future<Output = StorageResult<()>> + Send;
+
+    /// Copies the menu and every order into a portable snapshot
+    fn export_data(
+        &self,
+        restaurant_id: i64,
+    ) -> impl Future<Output = StorageResult<DataExport>> + Send;
+
+    /// Replaces the menu and every order with a snapshot taken by
+    /// `export_data`. The order event log is rewritten to match it.
+    fn import_data(
+        &self,
+        restaurant_id: i64,
+        data: DataExport,
+    ) -> impl Future<Output = StorageResult<ImportReport>> + Send;
+
+    /// Moves every order that is done with, i.e. finished, served, removed or
+    /// cancelled, into the archive stamped with `business_date` and records a
+    /// summary of them. Orders still placed, cooking or waiting to be served
+    /// stay live. Parties still seated are closed once they have settled up,
+    /// as by `close_party`. The rest are left open along with their orders,
+    /// and listed in the summary.
+    fn close_out(
+        &self,
+        restaurant_id: i64,
+        business_date: NaiveDate,
+    ) -> impl Future<Output = StorageResult<CloseOutSummary>> + Send;
+
+    /// Fetches the summary of every close-out, oldest first
+    fn get_close_outs(
+        &self,
+        restaurant_id: i64,
+    ) -> impl Future<Output = StorageResult<Vec<CloseOutSummary>>> + Send;
+
+    /// Fetches the orders archived for a business day
+    fn get_archived_orders(
+        &self,
+        restaurant_id: i64,
+        business_date: NaiveDate,
+    ) -> impl Future<Output = StorageResult<Vec<ArchivedOrder>>> + Send;
+
+    /// Follows every change to orders and menus, across all restaurants, from
+    /// now on
+    fn subscribe(&self) -> broadcast::Receiver<LiveUpdate>;
+}
+
+/// The modifier set the default menu gives an item
+pub fn default_modifiers(item_name: &str) -> Vec<Modifier> {
+    MODIFIERS_MCDONALDS
+        .iter()
+        .filter(|&&(item, ..)| item == item_name)
+        .map(|&(_, name, price_delta, prep_delta_m)| Modifier {
+            name: name.to_string(),
+            price_delta,
+            prep_delta_m,
+        })
+        .collect()
+}
+
+/// The parts of a menu item needed to place an order for it
+pub struct MenuItemRow {
+    pub prep_min_m: f64,
+    pub prep_max_m: f64,
+    pub station: Station,
+    pub modifiers: Vec<Modifier>,
+    pub price: i64,
+}
+
+impl MenuItemRow {
+    /// Picks how long an order for the item takes to cook, within its range
+    pub fn get_random_prep_secs(&self) -> i64 {
+        let range = self.prep_max_m - self.prep_min_m;
+        let mins = self.prep_min_m + fastrand::f64() * range;
+        (mins * 60.0).round() as i64
+    }
+
+    /// Picks how long a line of the item takes to cook, with what its
+    /// modifiers add or take off. A line is cooked in one go however many
+    /// it holds.
+    pub fn line_prep_secs(&self, modifiers: &[Modifier]) -> i64 {
+        let delta_m = modifiers
+            .iter()
+            .map(|modifier| modifier.prep_delta_m as f64)
+            .sum::<f64>();
+        (self.get_random_prep_secs() + (delta_m * 60.0).round() as i64).max(0)
+    }
+
+    /// What one of the item costs with `modifiers`. Discounts never take it
+    /// below free.
+    pub fn unit_price(&self, modifiers: &[Modifier]) -> i64 {
+        let delta = modifiers
+            .iter()
+            .map(|modifier| modifier.price_delta)
+            .sum::<i64>();
+        (self.price + delta).max(0)
+    }
+}
+
+/// An order line which has been accepted, with its modifiers copied from
+/// the item's modifier set, its notes tidied up and its price worked out
+#[derive(Debug)]
+pub struct PlacedLine {
+    pub item_id: i64,
+    pub quantity: i64,
+    pub modifiers: Vec<Modifier>,
+    pub notes: Option<String>,
+    pub unit_price: i64,
+    pub seat: Option<i64>,
+}
+
+/// A live order as it appears on its table's bill
+pub fn bill_line(order: TableOrder, item_name: String) -> BillLine {
+    BillLine {
+        order_id: order.order_id,
+        item_id: order.item_id,
+        item_name,
+        quantity: order.quantity,
+        line_total: order.unit_price * order.quantity,
+        unit_price: order.unit_price,
+        seat: order.seat,
+        adjustment: None,
+        discount: 0,
+        net_total: order.unit_price * order.quantity,
+        modifiers: order.modifiers,
+    }
+}
+
+/// A change to how a party's bill is split into checks
+#[derive(Debug, Clone)]
+pub enum SplitChange {
+    /// Share the whole bill between this many checks
+    Evenly(i64),
+    /// Put these orders on one check, which may be a new one
+    Move { order_ids: Vec<i64>, check: i64 },
+    /// Give each seat with orders its own check
+    BySeat,
+    /// Go back to a single check
+    Join,
+}
+
+/// Works out how a party's bill is split once `change` is made. Moving
+/// orders off an even split starts again from a single check.
+pub fn apply_split_change(
+    current: &CheckSplit,
+    lines: &[BillLine],
+    change: SplitChange,
+) -> StorageResult<CheckSplit> {
+    match change {
+        SplitChange::Evenly(checks) => match (1..=MAX_CHECKS).contains(&checks) {
+            true => Ok(CheckSplit::Even { checks }),
+            false => Err(StorageError::Invalid(format!(
+                "a bill can be split into 1 to {MAX_CHECKS} checks"
+            ))),
+        },
+        SplitChange::Move { order_ids, check } => {
+            let (checks, mut assignments) = match current {
+                CheckSplit::ByOrder {
+                    checks,
+                    assignments,
+                } => (*checks, assignments.clone()),
+                _ => (1, Vec::new()),
+            };
+            if order_ids.is_empty() {
+                return Err(StorageError::BadRequest(
+                    "order_ids must list at least one order".to_string(),
+                ));
+            }
+            let highest = (checks + 1).min(MAX_CHECKS);
+            if !(1..=highest).contains(&check) {
+                return Err(StorageError::Invalid(format!(
+                    "orders can only be moved to checks 1 to {highest}"
+                )));
+            }
+            let unknown = order_ids
+                .iter()
+                .filter(|order_id| !lines.iter().any(|line| line.order_id == **order_id))
+                .map(|order_id| order_id.to_string())
+                .collect::<Vec<_>>();
+            if !unknown.is_empty() {
+                return Err(StorageError::Invalid(format!(
+                    "orders {} are not on the table's bill",
+                    unknown.join(", ")
+                )));
+            }
+
+            // Orders which have since been cancelled or finished are dropped
+            assignments.retain(|assignment| {
+                !order_ids.contains(&assignment.order_id)
+                    && lines
+                        .iter()
+                        .any(|line| line.order_id == assignment.order_id)
+            });
+            assignments.extend(
+                order_ids
+                    .into_iter()
+                    .map(|order_id| CheckAssignment { order_id, check }),
+            );
+            assignments.sort_by_key(|assignment| assignment.order_id);
+            assignments.dedup();
+            Ok(CheckSplit::ByOrder {
+                checks: checks.max(check),
+                assignments,
+            })
+        }
+        SplitChange::BySeat => {
+            let mut seats = lines
+                .iter()
+                .filter_map(|line| line.seat)
+                .collect::<Vec<_>>();
+            seats.sort_unstable();
+            seats.dedup();
+            if seats.len() as i64 > MAX_CHECKS {
+                return Err(StorageError::Invalid(format!(
+                    "a bill can be split into at most {MAX_CHECKS} checks"
+                )));
+            }
+
+            // Checks follow the seats in order, and orders without a seat
+            // stay on check 1
+            let assignments = lines
+                .iter()
+                .filter_map(|line| {
+                    let seat = line.seat?;
+                    let check = seats.binary_search(&seat).ok()? as i64 + 1;
+                    Some(CheckAssignment {
+                        order_id: line.order_id,
+                        check,
+                    })
+                })
+                .collect::<Vec<_>>();
+            Ok(CheckSplit::ByOrder {
+                checks: (seats.len() as i64).max(1),
+                assignments,
+            })
+        }
+        SplitChange::Join => Ok(CheckSplit::Single),
+    }
+}
+
+/// Describes everything wrong with a stored check split
+fn check_split_problems(party_id: i64, split: &CheckSplit) -> Vec<String> {
+    let (checks, assignments) = match split {
+        CheckSplit::Single => return Vec::new(),
+        CheckSplit::Even { checks } => (*checks, None),
+        CheckSplit::ByOrder {
+            checks,
+            assignments,
+        } => (*checks, Some(assignments)),
+    };
+    let mut problems = Vec::new();
+    if !(1..=MAX_CHECKS).contains(&checks) {
+        problems.push(format!(
+            "party {party_id} has its bill split into {checks} checks"
+        ));
+    }
+    let mut order_ids = HashSet::new();
+    for CheckAssignment { order_id, check } in assignments.into_iter().flatten() {
+        if !order_ids.insert(order_id) {
+            problems.push(format!(
+                "party {party_id} has order {order_id} on more than one check"
+            ));
+        }
+        if !(1..=checks).contains(check) {
+            problems.push(format!(
+                "party {party_id} has order {order_id} on check {check}, which does not exist"
+            ));
+        }
+    }
+    problems
+}
+
+/// Checks every line of an order against the menu items it refers to and
+/// the table's `seats`, so an order is only placed when all of it can be.
+pub fn validate_order(
+    lines: &[OrderLine],
+    menu_items: &HashMap<i64, MenuItemRow>,
+    seats: i64,
+) -> Result<Vec<PlacedLine>, OrderRejection> {
+    if lines.is_empty() {
+        return Err(OrderRejection {
+            message: "order contains no items".to_string(),
+            rejected_items: Vec::new(),
+        });
+    }
+
+    let mut placed = Vec::new();
+    let mut rejected_items = Vec::new();
+    let mut rejected_lines = 0;
+
+    for (line, order_line) in lines.iter().enumerate() {
+        match place_line(order_line, menu_items, seats) {
+            Ok(line) => placed.push(line),
+            Err(problems) => {
+                rejected_lines += 1;
+                rejected_items.extend(problems.into_iter().map(|(reason, modifier)| {
+                    RejectedItem {
+                        line,
+                        item_id: order_line.item_id,
+                        reason,
+                        modifier,
+                    }
+                }));
+            }
+        }
+    }
+
+    match rejected_items.is_empty() {
+        true => Ok(placed),
+        false => Err(OrderRejection {
+            message: format!("{rejected_lines} item(s) could not be ordered"),
+            rejected_items,
+        }),
+    }
+}
+
+/// Checks a single order line, listing every problem with it along with the
+/// modifier each is about
+fn place_line(
+    order_line: &OrderLine,
+    menu_items: &HashMap<i64, MenuItemRow>,
+    seats: i64,
+) -> Result<PlacedLine, Vec<(RejectionReason, Option<String>)>> {
+    let Some(item) = menu_items.get(&order_line.item_id) else {
+        return Err(vec![(RejectionReason::UnknownItem, None)]);
+    };
+    let mut problems = Vec::new();
+
+    if !(1..=MAX_LINE_QUANTITY).contains(&order_line.quantity) {
+        problems.push((RejectionReason::InvalidQuantity, None));
+    }
+
+    let mut modifiers = Vec::<Modifier>::new();
+    for name in &order_line.modifiers {
+        let name = name.trim();
+        match item
+            .modifiers
+            .iter()
+            .find(|modifier| modifier.name.trim().to_lowercase() == name.to_lowercase())
+        {
+            None => problems.push((RejectionReason::UnknownModifier, Some(name.to_string()))),
+            Some(modifier) if modifiers.contains(modifier) => problems.push((
+                RejectionReason::DuplicateModifier,
+                Some(modifier.name.clone()),
+            )),
+            Some(modifier) => modifiers.push(modifier.clone()),
+        }
+    }
+
+    let notes = order_line
+        .notes
+        .as_deref()
+        .map(str::trim)
+        .filter(|notes| !notes.is_empty());
+    if notes.is_some_and(|notes| notes.chars().count() > MAX_NOTES_CHARS) {
+        problems.push((RejectionReason::NotesTooLong, None));
+    }
+
+    if order_line
+        .seat
+        .is_some_and(|seat| !(1..=seats).contains(&seat))
+    {
+        problems.push((RejectionReason::InvalidSeat, None));
+    }
+
+    match problems.is_empty() {
+        true => Ok(PlacedLine {
+            item_id: order_line.item_id,
+            quantity: order_line.quantity,
+            unit_price: item.unit_price(&modifiers),
+            modifiers,
+            notes: notes.map(str::to_string),
+            seat: order_line.seat,
+        }),
+        false => Err(problems),
+    }
+}
+
+/// Lists everything wrong with a single menu item's fields
+pub fn menu_item_problems(item: &NewMenuItem) -> Vec<String> {
+    let name = &item.name;
+    let mut problems = Vec::new();
+
+    if name.trim().is_empty() {
+        problems.push("an item has an empty name".to_string());
+    }
+
+    if !(item.prep_min_m.is_finite() && item.prep_min_m >= 0.0) {
+        problems.push(format!("'{name}' has an invalid prep_min_m"));
+    }
+
+    if !item.prep_max_m.is_finite() {
+        problems.push(format!("'{name}' has an invalid prep_max_m"));
+    } else if item.prep_min_m > item.prep_max_m {
+        problems.push(format!("'{name}' has prep_min_m above prep_max_m"));
+    }
+
+    if !(0..=MAX_PRICE).contains(&item.price) {
+        problems.push(format!("'{name}' has an invalid price"));
+    }
+
+    let mut modifier_names = HashSet::new();
+    for modifier in &item.modifiers {
+        let modifier_name = modifier.name.trim();
+        if modifier_name.is_empty() {
+            problems.push(format!("'{name}' has a modifier with an empty name"));
+        } else if !modifier_names.insert(modifier_name.to_lowercase()) {
+            problems.push(format!(
+                "'{name}' has modifier '{modifier_name}' more than once"
+            ));
+        }
+        if !modifier.prep_delta_m.is_finite() {
+            problems.push(format!(
+                "'{name}' modifier '{modifier_name}' has an invalid prep_delta_m"
+            ));
+        }
+        if !(-MAX_PRICE..=MAX_PRICE).contains(&modifier.price_delta) {
+            problems.push(format!(
+                "'{name}' modifier '{modifier_name}' has an invalid price_delta"
+            ));
+        }
+    }
+
+    problems
+}
+
+/// Checks a menu item created or edited through the API
+pub fn validate_menu_item(item: &NewMenuItem) -> StorageResult<()> {
+    let problems = menu_item_problems(item);
+
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(StorageError::Invalid(problems.join("; "))),
+    }
+}
+
+/// Applies a patch on top of an item's current fields
+pub fn patch_menu_item(item: &MenuItem, patch: MenuItemPatch) -> NewMenuItem {
+    NewMenuItem {
+        name: patch
+            .name
+            .map_or_else(|| item.name.clone(), |name| name.trim().to_string()),
+        prep_min_m: patch.prep_min_m.unwrap_or(item.prep_min_m),
+        prep_max_m: patch.prep_max_m.unwrap_or(item.prep_max_m),
+        station: patch.station.unwrap_or(item.station),
+        modifiers: patch.modifiers.unwrap_or_else(|| item.modifiers.clone()),
+        price: patch.price.unwrap_or(item.price),
+    }
+}
+
+/// The tables a fresh database gives the default restaurant
+pub fn default_tables() -> Vec<NewTable> {
+    (1..=DEFAULT_TABLE_COUNT)
+        .map(|number| NewTable {
+            label: format!("Table {number}"),
+            section: DEFAULT_TABLE_SECTION.to_string(),
+            seats: DEFAULT_TABLE_SEATS,
+        })
+        .collect()
+}
+
+/// Lists everything wrong with a single table's fields
+pub fn table_problems(table: &NewTable) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if table.label.trim().is_empty() {
+        problems.push("a table has an empty label".to_string());
+    }
+    if table.seats < 1 {
+        problems.push(format!("'{}' needs at least one seat", table.label));
+    }
+
+    problems
+}
+
+/// Checks a table registered or edited through the API, trimming its label
+/// and section
+pub fn validate_table(mut table: NewTable) -> StorageResult<NewTable> {
+    table.label = table.label.trim().to_string();
+    table.section = table.section.trim().to_string();
+    let problems = table_problems(&table);
+
+    match problems.is_empty() {
+        true => Ok(table),
+        false => Err(StorageError::Invalid(problems.join("; "))),
+    }
+}
+
+/// Applies a patch on top of a table's current fields, returning the new
+/// fields and whether the table is active
+pub fn patch_table(table: &Table, patch: TablePatch) -> (NewTable, bool) {
+    let fields = NewTable {
+        label: patch.label.unwrap_or_else(|| table.label.clone()),
+        section: patch.section.unwrap_or_else(|| table.section.clone()),
+        seats: patch.seats.unwrap_or(table.seats),
+    };
+
+    (fields, patch.active.unwrap_or(table.active))
+}
+
+/// Checks an order can be placed for a table
+pub fn check_table_open(table: &Table) -> StorageResult<()> {
+    match table.active {
+        true => Ok(()),
+        false => Err(StorageError::Conflict(format!(
+            "table '{}' is not active",
+            table.label
+        ))),
+    }
+}
+
+/// Checks an order may move from `current` to `next`
+pub fn check_transition(current: OrderStatus, next: OrderStatus) -> StorageResult<()> {
+    match current.can_become(next) {
+        true => Ok(()),
+        false => Err(StorageError::Conflict(format!(
+            "order is {current} and can't become {next}"
+        ))),
+    }
+}
+
+/// Checks that an order waiting at a station can be claimed
+pub fn check_claimable(status: OrderStatus, claimed_by: Option<&str>) -> StorageResult<()> {
+    match (status, claimed_by) {
+        (OrderStatus::InProgress, Some(claimed_by)) => Err(StorageError::Conflict(format!(
+            "order has already been claimed by {claimed_by}"
+        ))),
+        (status, _) => check_transition(status, OrderStatus::InProgress),
+    }
+}
+
+/// Checks that an order waiting at a station can be completed
+pub fn check_completable(status: OrderStatus) -> StorageResult<()> {
+    match status {
+        OrderStatus::Placed => Err(StorageError::Conflict(
+            "order has to be claimed before it is completed".to_string(),
+        )),
+        status => check_transition(status, OrderStatus::Ready),
+    }
+}
+
+/// Checks that a claim says who made it, trimming the name given
+pub fn validate_claim(mut request: ClaimLineRequest) -> StorageResult<ClaimLineRequest> {
+    request.claimed_by = request.claimed_by.trim().to_string();
+    match request.claimed_by.is_empty() {
+        true => Err(StorageError::BadRequest(
+            "claimed_by must name who is making the order".to_string(),
+        )),
+        false => Ok(request),
+    }
+}
+
+pub fn line_not_found() -> StorageError {
+    StorageError::NotFound("order is not waiting at this station".to_string())
+}
+
+/// Parses the comma separated statuses of a `GET /tables/:table_id` filter
+pub fn parse_status_filter(filter: &str) -> StorageResult<Vec<OrderStatus>> {
+    filter
+        .split(',')
+        .map(|status| status.trim().parse().map_err(StorageError::BadRequest))
+        .collect()
+}
+
+/// Whether an imported order's status history is one it could have reached:
+/// placed first, then only allowed transitions, ending at its status
+fn valid_status_history(order: &OrderHistoryEntry) -> bool {
+    let statuses = order
+        .status_history
+        .iter()
+        .map(|change| change.status)
+        .collect::<Vec<_>>();
+
+    match statuses.first() {
+        None => order.status == OrderStatus::Placed,
+        Some(first) => {
+            *first == OrderStatus::Placed
+                && statuses.windows(2).all(|pair| pair[0].can_become(pair[1]))
+                && statuses.last() == Some(&order.status)
+        }
+    }
+}
+
+/// Checks a party has at least one guest
+pub fn validate_party(request: SeatPartyRequest) -> StorageResult<SeatPartyRequest> {
+    match request.guests >= 1 {
+        true => Ok(request),
+        false => Err(StorageError::Invalid(
+            "a party needs at least one guest".to_string(),
+        )),
+    }
+}
+
+/// Checks a table is free for a new party
+pub fn check_table_free(open_party: Option<&Party>) -> StorageResult<()> {
+    match open_party {
+        Some(party) => Err(StorageError::Conflict(format!(
+            "table already has party {} seated",
+            party.id
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Checks a party hasn't left yet
+pub fn check_party_open(party: &Party) -> StorageResult<()> {
+    match party.closed_at {
+        Some(_) => Err(StorageError::Conflict(format!(
+            "party {} has already left",
+            party.id
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Orders can only be placed for a party seated at the table
+pub fn party_required() -> StorageError {
+    StorageError::Conflict("table has no party seated, seat one first".to_string())
+}
+
+pub fn no_open_party() -> StorageError {
+    StorageError::NotFound("table has no party seated".to_string())
+}
+
+pub fn payment_not_found() -> StorageError {
+    StorageError::NotFound("payment does not exist".to_string())
+}
+
+pub fn manager_not_found() -> StorageError {
+    StorageError::NotFound("manager does not exist".to_string())
+}
+
+pub fn party_not_found() -> StorageError {
+    StorageError::NotFound("party does not exist".to_string())
+}
+
+pub fn table_not_found() -> StorageError {
+    StorageError::NotFound("table does not exist".to_string())
+}
+
+/// Checks a new restaurant has a name, a known time zone, at least one
+/// cooking slot, a currency code and a tax rate no higher than 100%,
+/// trimming the name and filling in defaults for the rest
+pub fn validate_restaurant(mut restaurant: NewRestaurant) -> StorageResult<NewRestaurant> {
+    restaurant.name = restaurant.name.trim().to_string();
+    let time_zone = restaurant.time_zone.as_deref().unwrap_or(DEFAULT_TIME_ZONE);
+
+    let mut problems = Vec::new();
+    if restaurant.name.is_empty() {
+        problems.push("a restaurant needs a name".to_string());
+    }
+    match timing::parse_time_zone(time_zone.trim()) {
+        Ok(time_zone) => restaurant.time_zone = Some(time_zone.name().to_string()),
+        Err(problem) => problems.push(problem),
+    }
+    let kitchen_slots = *restaurant
+        .kitchen_slots
+        .get_or_insert(DEFAULT_KITCHEN_SLOTS);
+    if kitchen_slots < 1 {
+        problems.push("a kitchen needs at least one cooking slot".to_string());
+    }
+    let currency = restaurant
+        .currency
+        .get_or_insert_with(|| DEFAULT_CURRENCY.to_string());
+    *currency = currency.trim().to_uppercase();
+    if !(currency.len() == 3 && currency.chars().all(|c| c.is_ascii_uppercase())) {
+        problems.push(format!("'{currency}' is not an ISO 4217 currency code"));
+    }
+    let tax_rate_bps = *restaurant.tax_rate_bps.get_or_insert(0);
+    if !(0..=MAX_TAX_RATE_BPS).contains(&tax_rate_bps) {
+        problems.push("a tax rate must be between 0 and 10000 basis points".to_string());
+    }
+
+    match problems.is_empty() {
+        true => Ok(restaurant),
+        false => Err(StorageError::Invalid(problems.join("; "))),
+    }
+}
+
+/// Applies a patch on top of a restaurant's current fields
+pub fn patch_restaurant(restaurant: &Restaurant, patch: RestaurantPatch) -> NewRestaurant {
+    NewRestaurant {
+        name: patch.name.unwrap_or_else(|| restaurant.name.clone()),
+        time_zone: patch
+            .time_zone
+            .or_else(|| Some(restaurant.time_zone.clone())),
+        kitchen_slots: patch.kitchen_slots.or(Some(restaurant.kitchen_slots)),
+        currency: Some(restaurant.currency.clone()),
+        tax_rate_bps: patch.tax_rate_bps.or(Some(restaurant.tax_rate_bps)),
+    }
+}
+
+pub fn restaurant_not_found() -> StorageError {
+    StorageError::NotFound("restaurant does not exist".to_string())
+}
+
+/// Checks that a removal says who made it, trimming the name given
+pub fn validate_removal(mut request: RemoveOrderRequest) -> StorageResult<RemoveOrderRequest> {
+    request.removed_by = request.removed_by.trim().to_string();
+    match request.removed_by.is_empty() {
+        true => Err(StorageError::BadRequest(
+            "removed_by must name who removed the order".to_string(),
+        )),
+        false => Ok(request),
+    }
+}
+
+/// Checks that an order's removal can still be undone
+pub fn check_restorable(removal: Option<&Removal>, window: Duration) -> StorageResult<()> {
+    match removal {
+        None => Err(StorageError::Conflict(
+            "order has not been removed".to_string(),
+        )),
+        Some(removal) if !events::can_restore(removal, window) => Err(StorageError::Conflict(
+            "order was removed too long ago to restore".to_string(),
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
+/// A manager as stored, along with the hash of their PIN
+pub struct ManagerRow {
+    pub manager: Manager,
+    pub pin_hash: String,
+}
+
+/// Checks a new manager has a name and a PIN of 4 to 12 digits, trimming
+/// the name
+pub fn validate_manager(mut manager: NewManager) -> StorageResult<NewManager> {
+    manager.name = manager.name.trim().to_string();
+    if manager.name.is_empty() {
+        return Err(StorageError::BadRequest(
+            "a manager needs a name".to_string(),
+        ));
+    }
+    match (4..=12).contains(&manager.pin.len()) && manager.pin.bytes().all(|b| b.is_ascii_digit()) {
+        true => Ok(manager),
+        false => Err(StorageError::Invalid(
+            "a PIN must be 4 to 12 digits".to_string(),
+        )),
+    }
+}
+
+/// Names PIN hashes made with PBKDF2-HMAC-SHA256
+const PIN_HASH_SCHEME: &str = "pbkdf2-sha256";
+
+/// How many PBKDF2 rounds a PIN goes through. PINs are short, so each guess
+/// at one from a leaked hash has to be made slow.
+const PIN_HASH_ROUNDS: NonZeroU32 = match NonZeroU32::new(600_000) {
+    Some(rounds) => rounds,
+    None => unreachable!(),
+};
+
+/// Hashes a PIN with PBKDF2 and a fresh random salt, stored as
+/// `pbkdf2-sha256$rounds$salt$hash`
+pub fn hash_pin(pin: &str) -> String {
+    let mut salt = [0; 16];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .expect("the system can generate random numbers");
+    let mut hash = [0; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        PIN_HASH_ROUNDS,
+        &salt,
+        pin.as_bytes(),
+        &mut hash,
+    );
+
+    format!(
+        "{PIN_HASH_SCHEME}${PIN_HASH_ROUNDS}${}${}",
+        to_hex(&salt),
+        to_hex(&hash)
+    )
+}
+
+/// Whether `pin` is the one `pin_hash` was made from, compared in constant
+/// time. Hashes made before PINs went through PBKDF2 are a single SHA-256 of
+/// the salt and PIN, stored as `salt$hash`.
+pub fn pin_matches(pin: &str, pin_hash: &str) -> bool {
+    match pin_hash.split('$').collect::<Vec<_>>()[..] {
+        [PIN_HASH_SCHEME, rounds, salt, hash] => {
+            let (Ok(rounds), Some(salt), Some(hash)) =
+                (rounds.parse(), from_hex(salt), from_hex(hash))
+            else {
+                return false;
+            };
+            pbkdf2::verify(
+                pbkdf2::PBKDF2_HMAC_SHA256,
+                rounds,
+                &salt,
+                pin.as_bytes(),
+                &hash,
+            )
+            .is_ok()
+        }
+        [salt, hash] => {
+            let digest = Sha256::new()
+                .chain_update(salt)
+                .chain_update(pin)
+                .finalize();
+            from_hex(hash)
+                .is_some_and(|hash| constant_time::verify_slices_are_equal(&digest, &hash).is_ok())
+        }
+        _ => false,
+    }
+}
+
+/// Whether a PIN hash is weaker than `hash_pin` makes them today, so should
+/// be replaced the next time its PIN is given
+pub fn pin_hash_outdated(pin_hash: &str) -> bool {
+    match pin_hash.split('$').collect::<Vec<_>>()[..] {
+        [PIN_HASH_SCHEME, rounds, _, _] => rounds
+            .parse::<NonZeroU32>()
+            .map_or(true, |rounds| rounds < PIN_HASH_ROUNDS),
+        _ => true,
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Checks an adjustment has a reason, says who asked for it and who
+/// approved it, and gives what its kind needs, trimming the text it holds.
+/// Comps and voids must be approved by someone other than who asked.
+pub fn validate_adjustment(mut request: NewAdjustment) -> StorageResult<NewAdjustment> {
+    request.reason = request.reason.trim().to_string();
+    request.requested_by = request.requested_by.trim().to_string();
+    request.approved_by = request.approved_by.trim().to_string();
+
+    for (field, value) in [
+        ("reason", &request.reason),
+        ("requested_by", &request.requested_by),
+        ("approved_by", &request.approved_by),
+    ] {
+        if value.is_empty() {
+            return Err(StorageError::BadRequest(format!(
+                "{field} must not be empty"
+            )));
+        }
+    }
+
+    match (request.kind, request.discount, request.order_id) {
+        (AdjustmentKind::Discount, None, _) => Err(StorageError::BadRequest(
+            "a discount must say how much it takes off".to_string(),
+        )),
+        (AdjustmentKind::Discount, Some(Discount::Percent { bps }), _)
+            if !(1..=10_000).contains(&bps) =>
+        {
+            Err(StorageError::Invalid(
+                "a percentage discount must be 1 to 10000 basis points".to_string(),
+            ))
+        }
+        (AdjustmentKind::Discount, Some(Discount::Fixed { amount }), _)
+            if !(1..=MAX_PRICE).contains(&amount) =>
+        {
+            Err(StorageError::Invalid(format!(
+                "a fixed discount must be 1 to {MAX_PRICE}"
+            )))
+        }
+        (AdjustmentKind::Discount, Some(_), _) => Ok(request),
+        (_, Some(_), _) => Err(StorageError::BadRequest(
+            "only discounts take a discount amount".to_string(),
+        )),
+        (_, None, None) => Err(StorageError::BadRequest(
+            "comps and voids must name an order_id".to_string(),
+        )),
+        (_, None, Some(_)) if request.approved_by == request.requested_by => {
+            Err(StorageError::Invalid(
+                "comps and voids must be approved by someone other than who asked".to_string(),
+            ))
+        }
+        (_, None, Some(_)) => Ok(request),
+    }
+}
+
+/// Checks the manager an adjustment names can approve it. Comps and voids
+/// also need the manager's PIN.
+pub fn check_approval(request: &NewAdjustment, manager: Option<&ManagerRow>) -> StorageResult<()> {
+    let manager = manager.filter(|row| row.manager.active);
+    match request.kind {
+        AdjustmentKind::Discount => match manager {
+            Some(_) => Ok(()),
+            None => Err(StorageError::Invalid(format!(
+                "'{}' is not a manager",
+                request.approved_by
+            ))),
+        },
+        AdjustmentKind::Comp | AdjustmentKind::Void => match (manager, &request.pin) {
+            (Some(row), Some(pin)) if pin_matches(pin, &row.pin_hash) => Ok(()),
+            _ => Err(StorageError::Forbidden(
+                "comps and voids need the approving manager's name and PIN".to_string(),
+            )),
+        },
+    }
+}
+
+/// Checks an adjustment's order is on the table's bill and hasn't been
+/// adjusted yet
+pub fn check_adjustable(
+    request: &NewAdjustment,
+    lines: &[BillLine],
+    adjustments: &[Adjustment],
+) -> StorageResult<()> {
+    let Some(order_id) = request.order_id else {
+        return Ok(());
+    };
+    if !lines.iter().any(|line| line.order_id == order_id) {
+        return Err(StorageError::Invalid(format!(
+            "order {order_id} is not on the table's bill"
+        )));
+    }
+    match adjustments
+        .iter()
+        .find(|adjustment| adjustment.order_id == Some(order_id))
+    {
+        Some(adjustment) => Err(StorageError::Conflict(format!(
+            "order {order_id} already has adjustment {}",
+            adjustment.id
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Checks a void is for an order which has been cooked. One that hasn't is
+/// removed from the table instead, or comped.
+pub fn check_voidable(request: &NewAdjustment, status: OrderStatus) -> StorageResult<()> {
+    match (request.kind, status) {
+        (AdjustmentKind::Void, OrderStatus::Ready | OrderStatus::Served) => Ok(()),
+        (AdjustmentKind::Void, status) => Err(StorageError::Invalid(format!(
+            "order {} is {status}, only ready or served orders can be voided",
+            request.order_id.unwrap_or_default()
+        ))),
+        (AdjustmentKind::Discount | AdjustmentKind::Comp, _) => Ok(()),
+    }
+}
+
+/// What the processor, or the server for cash, made of a payment
+#[derive(Debug, Clone)]
+pub enum PaymentChange {
+    /// The processor held the amount under this reference
+    Authorized {
+        reference: String,
+    },
+    /// The processor turned the authorization down
+    Declined {
+        reason: String,
+    },
+    /// The processor didn't answer the authorization in time
+    TimedOut,
+    /// The processor is about to be asked to capture, void or refund the
+    /// payment. Claiming it first turns away any other request to change it
+    /// until the processor has answered.
+    Capturing {
+        tip: i64,
+    },
+    Voiding,
+    Refunding,
+    /// The processor declined the capture, void or refund, so the payment
+    /// goes back to where it was
+    Abandoned,
+    /// The processor didn't answer the capture, void or refund in time, so
+    /// it may or may not have been made
+    Unconfirmed,
+    /// Taken, with the tip as finally given
+    Captured {
+        tip: i64,
+    },
+    Voided,
+    Refunded,
+}
+
+impl PaymentChange {
+    /// The claim the processor is asked to make the change under, for
+    /// captures, voids and refunds
+    pub fn claim(&self) -> Option<PaymentChange> {
+        match self {
+            PaymentChange::Captured { tip } => Some(PaymentChange::Capturing { tip: *tip }),
+            PaymentChange::Voided => Some(PaymentChange::Voiding),
+            PaymentChange::Refunded => Some(PaymentChange::Refunding),
+            _ => None,
+        }
+    }
+
+    /// The statuses the change can be made from
+    fn made_from(&self) -> &'static [PaymentStatus] {
+        match self {
+            PaymentChange::Authorized { .. }
+            | PaymentChange::Declined { .. }
+            | PaymentChange::TimedOut => &[PaymentStatus::Pending],
+            // Asking again is how an unconfirmed change is found out
+            PaymentChange::Capturing { .. } => {
+                &[PaymentStatus::Authorized, PaymentStatus::CaptureUnconfirmed]
+            }
+            PaymentChange::Voiding => &[PaymentStatus::Authorized, PaymentStatus::VoidUnconfirmed],
+            PaymentChange::Refunding => {
+                &[PaymentStatus::Captured, PaymentStatus::RefundUnconfirmed]
+            }
+            PaymentChange::Abandoned | PaymentChange::Unconfirmed => &[
+                PaymentStatus::Capturing,
+                PaymentStatus::Voiding,
+                PaymentStatus::Refunding,
+            ],
+            PaymentChange::Captured { .. } => &[PaymentStatus::Capturing],
+            PaymentChange::Voided => &[PaymentStatus::Voiding],
+            PaymentChange::Refunded => &[PaymentStatus::Refunding],
+        }
+    }
+
+    /// The status a payment in status `from` is left in
+    fn leads_to(&self, from: PaymentStatus) -> PaymentStatus {
+        match self {
+            PaymentChange::Authorized { .. } => PaymentStatus::Authorized,
+            PaymentChange::Declined { .. } => PaymentStatus::Declined,
+            PaymentChange::TimedOut => PaymentStatus::TimedOut,
+            PaymentChange::Capturing { .. } => PaymentStatus::Capturing,
+            PaymentChange::Voiding => PaymentStatus::Voiding,
+            PaymentChange::Refunding => PaymentStatus::Refunding,
+            PaymentChange::Abandoned => match from {
+                PaymentStatus::Capturing | PaymentStatus::Voiding => PaymentStatus::Authorized,
+                PaymentStatus::Refunding => PaymentStatus::Captured,
+                other => other,
+            },
+            PaymentChange::Unconfirmed => match from {
+                PaymentStatus::Capturing => PaymentStatus::CaptureUnconfirmed,
+                PaymentStatus::Voiding => PaymentStatus::VoidUnconfirmed,
+                PaymentStatus::Refunding => PaymentStatus::RefundUnconfirmed,
+                other => other,
+            },
+            PaymentChange::Captured { .. } => PaymentStatus::Captured,
+            PaymentChange::Voided => PaymentStatus::Voided,
+            PaymentChange::Refunded => PaymentStatus::Refunded,
+        }
+    }
+
+    /// The status the change is working towards, which claims name after
+    /// the change they are made for
+    fn goal(&self, from: PaymentStatus) -> PaymentStatus {
+        match self {
+            PaymentChange::Capturing { .. } => PaymentStatus::Captured,
+            PaymentChange::Voiding => PaymentStatus::Voided,
+            PaymentChange::Refunding => PaymentStatus::Refunded,
+            change => change.leads_to(from),
+        }
+    }
+}
+
+/// How a payment's status reads in messages
+fn payment_status_name(status: PaymentStatus) -> &'static str {
+    match status {
+        PaymentStatus::Pending => "pending",
+        PaymentStatus::Authorized => "authorized",
+        PaymentStatus::Captured => "captured",
+        PaymentStatus::Voided => "voided",
+        PaymentStatus::Refunded => "refunded",
+        PaymentStatus::Declined => "declined",
+        PaymentStatus::TimedOut => "timed out",
+        PaymentStatus::Capturing => "being captured",
+        PaymentStatus::Voiding => "being voided",
+        PaymentStatus::Refunding => "being refunded",
+        PaymentStatus::CaptureUnconfirmed => "not confirmed as captured",
+        PaymentStatus::VoidUnconfirmed => "not confirmed as voided",
+        PaymentStatus::RefundUnconfirmed => "not confirmed as refunded",
+    }
+}
+
+/// Whether a payment holds part of the bill, so it can't be paid again
+fn payment_holds(payment: &Payment) -> bool {
+    matches!(
+        payment.status,
+        PaymentStatus::Pending
+            | PaymentStatus::Authorized
+            | PaymentStatus::Captured
+            | PaymentStatus::Capturing
+            | PaymentStatus::Voiding
+            | PaymentStatus::Refunding
+            | PaymentStatus::CaptureUnconfirmed
+            | PaymentStatus::VoidUnconfirmed
+            | PaymentStatus::RefundUnconfirmed
+    )
+}
+
+pub fn validate_payment(payment: &NewPayment) -> StorageResult<()> {
+    if !(1..=MAX_PRICE).contains(&payment.amount) {
+        return Err(StorageError::Invalid(format!(
+            "a payment must be 1 to {MAX_PRICE}"
+        )));
+    }
+    validate_tip(payment.tip)
+}
+
+fn validate_tip(tip: i64) -> StorageResult<()> {
+    match (0..=MAX_PRICE).contains(&tip) {
+        true => Ok(()),
+        false => Err(StorageError::Invalid(format!(
+            "a tip must be 0 to {MAX_PRICE}"
+        ))),
+    }
+}
+
+/// Checks a payment isn't for more than is left owing on `bill`, once every
+/// payment still going through is counted
+pub fn check_payable(payment: &NewPayment, bill: &Bill, payments: &[Payment]) -> StorageResult<()> {
+    let held = payments
+        .iter()
+        .filter(|payment| payment_holds(payment))
+        .map(|payment| payment.amount)
+        .sum::<i64>();
+    let owing = (bill.total - held).max(0);
+    match payment.amount <= owing {
+        true => Ok(()),
+        false => Err(StorageError::Invalid(format!(
+            "only {} is left to pay",
+            format_money(owing, &bill.currency)
+        ))),
+    }
+}
+
+/// The payment as it stands once `change` is made, failing if the payment
+/// has got past where the change can be made from
+pub fn apply_payment_change(
+    mut payment: Payment,
+    change: PaymentChange,
+    now: String,
+) -> StorageResult<Payment> {
+    check_payment_change(&payment, &change)?;
+
+    payment.status = change.leads_to(payment.status);
+    match change {
+        PaymentChange::Authorized { reference } => payment.reference = Some(reference),
+        PaymentChange::Declined { reason } => payment.failure = Some(reason),
+        PaymentChange::TimedOut | PaymentChange::Unconfirmed => {
+            payment.failure = Some("the processor didn't answer in time".to_string())
+        }
+        PaymentChange::Captured { tip } => {
+            payment.tip = tip;
+            payment.failure = None;
+        }
+        PaymentChange::Voided | PaymentChange::Refunded | PaymentChange::Abandoned => {
+            payment.failure = None
+        }
+        PaymentChange::Capturing { .. } | PaymentChange::Voiding | PaymentChange::Refunding => {}
+    }
+    payment.updated_at = now;
+    Ok(payment)
+}
+
+/// Checks `change` can be made to the payment as it stands. A capture can
+/// change the tip, but can't take more than was authorized.
+fn check_payment_change(payment: &Payment, change: &PaymentChange) -> StorageResult<()> {
+    if let PaymentChange::Capturing { tip } | PaymentChange::Captured { tip } = change {
+        validate_tip(*tip)?;
+        // The tip is only replaced once captured, so until then it is the
+        // one authorized
+        if *tip > payment.tip {
+            return Err(StorageError::Invalid(format!(
+                "payment {} was authorized for {}, so it can't be captured for {}",
+                payment.id,
+                payment.amount + payment.tip,
+                payment.amount + tip
+            )));
+        }
+    }
+    let status = payment_status_name(payment.status);
+    let goal = change.goal(payment.status);
+    match payment.status {
+        current if change.made_from().contains(&current) => Ok(()),
+        current if current == goal || current == change.leads_to(current) => Err(
+            StorageError::Conflict(format!("payment {} is already {status}", payment.id)),
+        ),
+        _ => Err(StorageError::Conflict(format!(
+            "payment {} is {status}, so it can't be {}",
+            payment.id,
+            payment_status_name(goal)
+        ))),
+    }
+}
+
+/// Checks nothing is left owing on `bill` and no payment is still going
+/// through or waiting to be confirmed, so the party can leave
+pub fn check_settled(bill: &Bill, payments: &[Payment]) -> StorageResult<()> {
+    if let Some(payment) = payments.iter().find(|payment| {
+        matches!(
+            payment.status,
+            PaymentStatus::CaptureUnconfirmed
+                | PaymentStatus::VoidUnconfirmed
+                | PaymentStatus::RefundUnconfirmed
+        )
+    }) {
+        let retry = match payment.status {
+            PaymentStatus::CaptureUnconfirmed => "capture",
+            PaymentStatus::VoidUnconfirmed => "void",
+            _ => "refund",
+        };
+        return Err(StorageError::Conflict(format!(
+            "payment {} is {}, {retry} it again to find out",
+            payment.id,
+            payment_status_name(payment.status)
+        )));
+    }
+    if let Some(payment) = payments.iter().find(|payment| {
+        matches!(
+            payment.status,
+            PaymentStatus::Pending
+                | PaymentStatus::Authorized
+                | PaymentStatus::Capturing
+                | PaymentStatus::Voiding
+                | PaymentStatus::Refunding
+        )
+    }) {
+        return Err(StorageError::Conflict(format!(
+            "payment {} is still {}, capture or void it first",
+            payment.id,
+            payment_status_name(payment.status)
+        )));
+    }
+
+    let paid = payments
+        .iter()
+        .filter(|payment| payment.status == PaymentStatus::Captured)
+        .map(|payment| payment.amount)
+        .sum::<i64>();
+    match bill.total - paid {
+        balance if balance > 0 => Err(StorageError::Conflict(format!(
+            "the table still owes {}",
+            format_money(balance, &bill.currency)
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Checks a snapshot is self-consistent before it replaces the current data,
+/// reporting every problem found rather than stopping at the first one.
+pub fn validate_import(data: &DataExport) -> StorageResult<()> {
+    let mut problems = Vec::new();
+    let mut item_ids = HashSet::new();
+    let mut names = HashSet::new();
+
+    for item in data.menu.items.iter().chain(&data.retired_items) {
+        problems.extend(menu_item_problems(&NewMenuItem {
+            name: item.name.clone(),
+            prep_min_m: item.prep_min_m,
+            prep_max_m: item.prep_max_m,
+            station: item.station,
+            modifiers: item.modifiers.clone(),
+            price: item.price,
+        }));
+        if !item_ids.insert(item.id) {
+            problems.push(format!("menu item {} is listed more than once", item.id));
+        }
+        if !names.insert(&item.name) {
+            problems.push(format!("'{}' is listed more than once", item.name));
+        }
+    }
+
+    let mut table_ids = HashSet::new();
+    let mut labels = HashSet::new();
+
+    for table in &data.table_registry {
+        problems.extend(table_problems(&NewTable {
+            label: table.label.clone(),
+            section: table.section.clone(),
+            seats: table.seats,
+        }));
+        if !table_ids.insert(table.id) {
+            problems.push(format!("table {} is listed more than once", table.id));
+        }
+        if !labels.insert(&table.label) {
+            problems.push(format!("table '{}' is listed more than once", table.label));
+        }
+    }
+
+    let mut parties = HashMap::new();
+    let mut open_tables = HashSet::new();
+
+    for party in &data.parties {
+        let party_id = party.id;
+        if parties.insert(party_id, party.table_id).is_some() {
+            problems.push(format!("party {party_id} is listed more than once"));
+        }
+        if party.guests < 1 {
+            problems.push(format!("party {party_id} has no guests"));
+        }
+        if party.closed_at.is_none() && !open_tables.insert(party.table_id) {
+            problems.push(format!(
+                "table {} has more than one party seated",
+                party.table_id
+            ));
+        }
+        for timestamp in std::iter::once(&party.seated_at).chain(&party.closed_at) {
+            if chrono::DateTime::parse_from_rfc3339(timestamp).is_err() {
+                problems.push(format!(
+                    "party {party_id} has an invalid timestamp '{timestamp}'"
+                ));
+            }
+        }
+        problems.extend(check_split_problems(party_id, &party.check_split));
+    }
+
+    let mut order_ids = HashSet::new();
+
+    for table in &data.tables {
+        for order in &table.orders {
+            let order_id = order.order_id;
+            if !order_ids.insert(order_id) {
+                problems.push(format!("order {order_id} is listed more than once"));
+            }
+            if let Some(party_id) = order.party_id {
+                match parties.get(&party_id) {
+                    None => problems.push(format!(
+                        "order {order_id} refers to unknown party {party_id}"
+                    )),
+                    Some(&party_table) if party_table != table.table_id => problems.push(format!(
+                        "order {order_id} is for a different table than party {party_id}"
+                    )),
+                    Some(_) => {}
+                }
+            }
+            if !item_ids.contains(&order.item_id) {
+                problems.push(format!(
+                    "order {order_id} refers to unknown menu item {}",
+                    order.item_id
+                ));
+            }
+            let parse = |timestamp: &String| {
+                chrono::DateTime::parse_from_rfc3339(timestamp)
+                    .map_err(|_| format!("order {order_id} has an invalid timestamp '{timestamp}'"))
+            };
+            let ordered_at = parse(&order.ordered_at);
+            let removed_at = order
+                .removal
+                .as_ref()
+                .map(|removal| parse(&removal.removed_at));
+            match (ordered_at, removed_at) {
+                (Ok(ordered_at), Some(Ok(removed_at))) if removed_at < ordered_at => {
+                    problems.push(format!("order {order_id} was removed before it was placed"))
+                }
+                (ordered_at, removed_at) => problems.extend(
+                    ordered_at
+                        .err()
+                        .into_iter()
+                        .chain(removed_at.and_then(Result::err)),
+                ),
+            }
+            problems.extend(order.finished_at.as_ref().and_then(|at| parse(at).err()));
+            if timing::parse_ready_at(&order.ready_at).is_none() {
+                problems.push(format!(
+                    "order {order_id} has an invalid ready_at '{}'",
+                    order.ready_at
+                ));
+            }
+            if order.prep_secs.is_some_and(|prep_secs| prep_secs < 0) {
+                problems.push(format!("order {order_id} has a negative prep time"));
+            }
+            if order.quantity < 1 {
+                problems.push(format!("order {order_id} has a quantity below 1"));
+            }
+            if order.unit_price < 0 {
+                problems.push(format!("order {order_id} has a negative unit price"));
+            }
+            // Tables can lose seats after an order, so only the lower bound holds
+            if order.seat.is_some_and(|seat| seat < 1) {
+                problems.push(format!("order {order_id} is for a seat below 1"));
+            }
+            problems.extend(
+                order
+                    .status_history
+                    .iter()
+                    .filter_map(|change| parse(&change.at).err()),
+            );
+            if !valid_status_history(order) {
+                problems.push(format!("order {order_id} has an invalid status history"));
+            }
+        }
+    }
+
+    let mut adjustment_ids = HashSet::new();
+    for adjustment in &data.adjustments {
+        let id = adjustment.id;
+        if !adjustment_ids.insert(id) {
+            problems.push(format!("adjustment {id} is listed more than once"));
+        }
+        match parties.get(&adjustment.party_id) {
+            None => problems.push(format!(
+                "adjustment {id} refers to unknown party {}",
+                adjustment.party_id
+            )),
+            Some(&party_table) if party_table != adjustment.table_id => problems.push(format!(
+                "adjustment {id} is for a different table than party {}",
+                adjustment.party_id
+            )),
+            Some(_) => {}
+        }
+        if let Some(order_id) = adjustment.order_id {
+            if !order_ids.contains(&order_id) {
+                problems.push(format!(
+                    "adjustment {id} refers to unknown order {order_id}"
+                ));
+            }
+        }
+        let request = NewAdjustment {
+            kind: adjustment.kind,
+            order_id: adjustment.order_id,
+            discount: adjustment.discount,
+            reason: adjustment.reason.clone(),
+            requested_by: adjustment.requested_by.clone(),
+            approved_by: adjustment.approved_by.clone(),
+            pin: None,
+        };
+        if let Err(StorageError::BadRequest(problem) | StorageError::Invalid(problem)) =
+            validate_adjustment(request)
+        {
+            problems.push(format!("adjustment {id} is invalid: {problem}"));
+        }
+        if chrono::DateTime::parse_from_rfc3339(&adjustment.created_at).is_err() {
+            problems.push(format!(
+                "adjustment {id} has an invalid timestamp '{}'",
+                adjustment.created_at
+            ));
+        }
+    }
+
+    let mut payment_ids = HashSet::new();
+    for payment in &data.payments {
+        let id = payment.id;
+        if !payment_ids.insert(id) {
+            problems.push(format!("payment {id} is listed more than once"));
+        }
+        match parties.get(&payment.party_id) {
+            None => problems.push(format!(
+                "payment {id} refers to unknown party {}",
+                payment.party_id
+            )),
+            Some(&party_table) if party_table != payment.table_id => problems.push(format!(
+                "payment {id} is for a different table than party {}",
+                payment.party_id
+            )),
+            Some(_) => {}
+        }
+        let request = NewPayment {
+            tender: payment.tender,
+            amount: payment.amount,
+            tip: payment.tip,
+        };
+        if let Err(StorageError::Invalid(problem)) = validate_payment(&request) {
+            problems.push(format!("payment {id} is invalid: {problem}"));
+        }
+        for at in [&payment.created_at, &payment.updated_at] {
+            if chrono::DateTime::parse_from_rfc3339(at).is_err() {
+                problems.push(format!("payment {id} has an invalid timestamp '{at}'"));
+            }
+        }
+    }
+
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(StorageError::Invalid(problems.join("; "))),
+    }
+}
+
+/// The table registry an import leaves behind. Exports from before tables
+/// were registered have none, so every table with orders or parties is
+/// registered under a default label instead.
+pub fn import_table_registry(data: &DataExport) -> Vec<Table> {
+    let mut registry = data.table_registry.clone();
+
+    let used_tables = data
+        .tables
+        .iter()
+        .map(|history| history.table_id)
+        .chain(data.parties.iter().map(|party| party.table_id));
+    for table_id in used_tables {
+        if !registry.iter().any(|table| table.id == table_id) {
+            registry.push(Table {
+                id: table_id,
+                label: format!("Table {table_id}"),
+                section: DEFAULT_TABLE_SECTION.to_string(),
+                seats: DEFAULT_TABLE_SEATS,
+                active: true,
+            });
+        }
+    }
+
+    registry
+}
+
+#[cfg(test)]
+mod tests {
+    use shared::Tender;
+
+    use super::*;
+
+    /// Item 1 is a burger with a cheese modifier, at a table of four
+    fn menu() -> HashMap<i64, MenuItemRow> {
+        let burger = MenuItemRow {
+            prep_min_m: 2.0,
+            prep_max_m: 4.0,
+            station: Station::Grill,
+            modifiers: vec![Modifier {
+                name: "Extra Cheese".to_string(),
+                price_delta: 50,
+                prep_delta_m: 0.0,
+            }],
+            price: 500,
+        };
+        HashMap::from([(1, burger)])
+    }
+
+    fn order_line(item_id: i64) -> OrderLine {
+        OrderLine {
+            item_id,
+            quantity: 1,
+            modifiers: Vec::new(),
+            notes: None,
+            seat: None,
+        }
+    }
+
+    /// Why each rejected line of `lines` was turned away
+    fn rejections(lines: &[OrderLine]) -> Vec<(usize, RejectionReason, Option<String>)> {
+        validate_order(lines, &menu(), 4)
+            .unwrap_err()
+            .rejected_items
+            .into_iter()
+            .map(|item| (item.line, item.reason, item.modifier))
+            .collect()
+    }
+
+    #[test]
+    fn valid_orders_are_placed_with_their_modifiers_priced_in() {
+        let line = OrderLine {
+            quantity: 2,
+            modifiers: vec![" extra cheese ".to_string()],
+            notes: Some("  no pickles ".to_string()),
+            seat: Some(4),
+            ..order_line(1)
+        };
+
+        let placed = validate_order(&[line], &menu(), 4).unwrap();
+        assert_eq!(placed.len(), 1);
+        assert_eq!(placed[0].unit_price, 550);
+        assert_eq!(placed[0].modifiers[0].name, "Extra Cheese");
+        assert_eq!(placed[0].notes.as_deref(), Some("no pickles"));
+    }
+
+    #[test]
+    fn empty_orders_are_rejected() {
+        let rejection = validate_order(&[], &menu(), 4).unwrap_err();
+        assert_eq!(rejection.message, "order contains no items");
+        assert!(rejection.rejected_items.is_empty());
+    }
+
+    #[test]
+    fn unknown_items_are_rejected() {
+        let lines = [order_line(1), order_line(7)];
+        assert_eq!(
+            rejections(&lines),
+            vec![(1, RejectionReason::UnknownItem, None)]
+        );
+    }
+
+    #[test]
+    fn quantities_outside_the_limits_are_rejected() {
+        for quantity in [0, -1, MAX_LINE_QUANTITY + 1] {
+            let line = OrderLine {
+                quantity,
+                ..order_line(1)
+            };
+            assert_eq!(
+                rejections(&[line]),
+                vec![(0, RejectionReason::InvalidQuantity, None)]
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_and_repeated_modifiers_are_rejected() {
+        let line = OrderLine {
+            modifiers: vec![
+                "Extra Cheese".to_string(),
+                "EXTRA CHEESE".to_string(),
+                "Gravy".to_string(),
+            ],
+            ..order_line(1)
+        };
+        assert_eq!(
+            rejections(&[line]),
+            vec![
+                (
+                    0,
+                    RejectionReason::DuplicateModifier,
+                    Some("Extra Cheese".to_string())
+                ),
+                (
+                    0,
+                    RejectionReason::UnknownModifier,
+                    Some("Gravy".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn long_notes_and_missing_seats_are_rejected() {
+        let line = OrderLine {
+            notes: Some("a".repeat(MAX_NOTES_CHARS + 1)),
+            seat: Some(5),
+            ..order_line(1)
+        };
+        assert_eq!(
+            rejections(&[line]),
+            vec![
+                (0, RejectionReason::NotesTooLong, None),
+                (0, RejectionReason::InvalidSeat, None),
+            ]
+        );
+        let line = OrderLine {
+            seat: Some(0),
+            ..order_line(1)
+        };
+        assert_eq!(
+            rejections(&[line]),
+            vec![(0, RejectionReason::InvalidSeat, None)]
+        );
+    }
+
+    #[test]
+    fn one_bad_line_rejects_the_whole_order() {
+        let bad = OrderLine {
+            quantity: 0,
+            ..order_line(1)
+        };
+        let rejection =
+            validate_order(&[order_line(1), bad, order_line(9)], &menu(), 4).unwrap_err();
+        assert_eq!(rejection.message, "2 item(s) could not be ordered");
+        assert_eq!(rejection.rejected_items.len(), 2);
+    }
+
+    #[test]
+    fn orders_only_move_along_the_kitchen_flow() {
+        use OrderStatus::*;
+
+        let allowed = [
+            (Placed, InProgress),
+            (Placed, Cancelled),
+            (InProgress, Ready),
+            (InProgress, Cancelled),
+            (Ready, Served),
+            (Ready, Cancelled),
+        ];
+
+        for current in OrderStatus::ALL {
+            for next in OrderStatus::ALL {
+                let expected = allowed.contains(&(current, next));
+                assert_eq!(current.can_become(next), expected, "{current} -> {next}");
+                match check_transition(current, next) {
+                    Ok(()) => assert!(expected, "{current} -> {next} was allowed"),
+                    Err(StorageError::Conflict(message)) => {
+                        assert!(!expected, "{current} -> {next} was refused");
+                        assert_eq!(
+                            message,
+                            format!("order is {current} and can't become {next}")
+                        );
+                    }
+                    Err(e) => panic!("{current} -> {next} failed with {e:?}"),
+                }
+            }
+        }
+    }
+
+    fn adjustment(kind: AdjustmentKind) -> NewAdjustment {
+        NewAdjustment {
+            kind,
+            order_id: Some(1),
+            discount: None,
+            reason: " sent back ".to_string(),
+            requested_by: " amy ".to_string(),
+            approved_by: " Sam ".to_string(),
+            pin: Some("4321".to_string()),
+        }
+    }
+
+    fn discount(discount: Discount) -> NewAdjustment {
+        NewAdjustment {
+            discount: Some(discount),
+            ..adjustment(AdjustmentKind::Discount)
+        }
+    }
+
+    fn manager(active: bool, pin_hash: String) -> ManagerRow {
+        ManagerRow {
+            manager: Manager {
+                id: 1,
+                name: "Sam".to_string(),
+                active,
+            },
+            pin_hash,
+        }
+    }
+
+    #[test]
+    fn pins_only_match_their_own_hash() {
+        let pin_hash = hash_pin("4321");
+
+        assert!(pin_hash.starts_with("pbkdf2-sha256$600000$"));
+        assert!(pin_matches("4321", &pin_hash));
+        assert!(!pin_matches("1234", &pin_hash));
+        assert_ne!(pin_hash, hash_pin("4321"), "each hash has its own salt");
+        assert!(!pin_hash_outdated(&pin_hash));
+        assert!(!pin_matches("4321", "pbkdf2-sha256$600000$zz$zz"));
+        assert!(!pin_matches("4321", ""));
+    }
+
+    #[test]
+    fn pins_hashed_before_pbkdf2_still_match_but_are_outdated() {
+        // SHA-256 of "a1b2c3d4" followed by "4321"
+        let salt = "a1b2c3d4";
+        let digest = Sha256::new()
+            .chain_update(salt)
+            .chain_update("4321")
+            .finalize();
+        let legacy = format!("{salt}${}", to_hex(&digest));
+
+        assert!(pin_matches("4321", &legacy));
+        assert!(!pin_matches("1234", &legacy));
+        assert!(pin_hash_outdated(&legacy));
+        assert!(pin_hash_outdated("pbkdf2-sha256$1000$00$00"));
+    }
+
+    #[test]
+    fn adjustments_are_trimmed_and_need_every_name() {
+        let request = validate_adjustment(adjustment(AdjustmentKind::Comp)).unwrap();
+        assert_eq!(
+            (
+                request.reason.as_str(),
+                request.requested_by.as_str(),
+                request.approved_by.as_str()
+            ),
+            ("sent back", "amy", "Sam")
+        );
+
+        let unnamed = NewAdjustment {
+            approved_by: "  ".to_string(),
+            ..adjustment(AdjustmentKind::Comp)
+        };
+        assert!(matches!(
+            validate_adjustment(unnamed),
+            Err(StorageError::BadRequest(message)) if message == "approved_by must not be empty"
+        ));
+    }
+
+    #[test]
+    fn adjustments_are_rejected_when_malformed() {
+        let no_amount = adjustment(AdjustmentKind::Discount);
+        let comp_with_amount = NewAdjustment {
+            discount: Some(Discount::Fixed { amount: 100 }),
+            ..adjustment(AdjustmentKind::Comp)
+        };
+        let void_without_order = NewAdjustment {
+            order_id: None,
+            ..adjustment(AdjustmentKind::Void)
+        };
+        for request in [no_amount, comp_with_amount, void_without_order] {
+            assert!(matches!(
+                validate_adjustment(request),
+                Err(StorageError::BadRequest(_))
+            ));
+        }
+
+        let out_of_range = [
+            discount(Discount::Percent { bps: 0 }),
+            discount(Discount::Percent { bps: 10_001 }),
+            discount(Discount::Fixed { amount: 0 }),
+            discount(Discount::Fixed {
+                amount: MAX_PRICE + 1,
+            }),
+        ];
+        for request in out_of_range {
+            assert!(matches!(
+                validate_adjustment(request),
+                Err(StorageError::Invalid(_))
+            ));
+        }
+
+        let own_approval = NewAdjustment {
+            approved_by: "amy".to_string(),
+            ..adjustment(AdjustmentKind::Void)
+        };
+        assert!(matches!(
+            validate_adjustment(own_approval),
+            Err(StorageError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn discounts_may_be_approved_by_oneself_without_a_pin() {
+        let request = NewAdjustment {
+            order_id: None,
+            approved_by: "amy".to_string(),
+            pin: None,
+            ..discount(Discount::Percent { bps: 10_000 })
+        };
+
+        assert!(validate_adjustment(request).is_ok());
+    }
+
+    #[test]
+    fn approvals_need_an_active_manager_and_their_pin() {
+        let sam = manager(true, hash_pin("4321"));
+        let retired = manager(false, sam.pin_hash.clone());
+        let comp = adjustment(AdjustmentKind::Comp);
+        let wrong_pin = NewAdjustment {
+            pin: Some("1234".to_string()),
+            ..adjustment(AdjustmentKind::Void)
+        };
+        let no_pin = NewAdjustment {
+            pin: None,
+            ..adjustment(AdjustmentKind::Void)
+        };
+        let discount = NewAdjustment {
+            pin: None,
+            ..discount(Discount::Fixed { amount: 100 })
+        };
+
+        assert!(check_approval(&comp, Some(&sam)).is_ok());
+        assert!(check_approval(&discount, Some(&sam)).is_ok());
+        for (request, manager) in [
+            (&comp, None),
+            (&comp, Some(&retired)),
+            (&wrong_pin, Some(&sam)),
+            (&no_pin, Some(&sam)),
+        ] {
+            assert!(matches!(
+                check_approval(request, manager),
+                Err(StorageError::Forbidden(_))
+            ));
+        }
+        for manager in [None, Some(&retired)] {
+            assert!(matches!(
+                check_approval(&discount, manager),
+                Err(StorageError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn only_ready_or_served_orders_can_be_voided() {
+        let void = adjustment(AdjustmentKind::Void);
+        let comp = adjustment(AdjustmentKind::Comp);
+
+        for status in OrderStatus::ALL {
+            let voidable = matches!(status, OrderStatus::Ready | OrderStatus::Served);
+            assert_eq!(check_voidable(&void, status).is_ok(), voidable, "{status}");
+            assert!(check_voidable(&comp, status).is_ok(), "{status}");
+        }
+    }
+
+    fn payment(id: i64, status: PaymentStatus, amount: i64) -> Payment {
+        Payment {
+            id,
+            party_id: 1,
+            table_id: 1,
+            tender: Tender::Card,
+            amount,
+            tip: 100,
+            status,
+            reference: Some("ref_1".to_string()),
+            failure: None,
+            created_at: "then".to_string(),
+            updated_at: "then".to_string(),
+        }
+    }
+
+    fn bill(total: i64) -> Bill {
+        Bill {
+            table_id: 1,
+            currency: "USD".to_string(),
+            lines: Vec::new(),
+            subtotal: total,
+            check_discount: 0,
+            tax_rate_bps: 0,
+            tax: 0,
+            total,
+            adjustments: Vec::new(),
+        }
+    }
+
+    /// The status a payment in `from` is left in by `change`, or why it
+    /// can't be made
+    fn apply(from: PaymentStatus, change: PaymentChange) -> StorageResult<PaymentStatus> {
+        apply_payment_change(payment(1, from, 1000), change, "now".to_string())
+            .map(|payment| payment.status)
+    }
+
+    #[test]
+    fn payments_move_through_their_claims() {
+        use PaymentStatus::*;
+
+        let steps = [
+            (
+                Pending,
+                PaymentChange::Authorized {
+                    reference: "ref_2".to_string(),
+                },
+                Authorized,
+            ),
+            (
+                Pending,
+                PaymentChange::Declined {
+                    reason: "no".to_string(),
+                },
+                Declined,
+            ),
+            (Pending, PaymentChange::TimedOut, TimedOut),
+            (Authorized, PaymentChange::Capturing { tip: 50 }, Capturing),
+            (Capturing, PaymentChange::Captured { tip: 50 }, Captured),
+            (Authorized, PaymentChange::Voiding, Voiding),
+            (Voiding, PaymentChange::Voided, Voided),
+            (Captured, PaymentChange::Refunding, Refunding),
+            (Refunding, PaymentChange::Refunded, Refunded),
+            (Capturing, PaymentChange::Abandoned, Authorized),
+            (Voiding, PaymentChange::Abandoned, Authorized),
+            (Refunding, PaymentChange::Abandoned, Captured),
+            (Capturing, PaymentChange::Unconfirmed, CaptureUnconfirmed),
+            (Voiding, PaymentChange::Unconfirmed, VoidUnconfirmed),
+            (Refunding, PaymentChange::Unconfirmed, RefundUnconfirmed),
+            (
+                CaptureUnconfirmed,
+                PaymentChange::Capturing { tip: 100 },
+                Capturing,
+            ),
+            (VoidUnconfirmed, PaymentChange::Voiding, Voiding),
+            (RefundUnconfirmed, PaymentChange::Refunding, Refunding),
+        ];
+        for (from, change, to) in steps {
+            assert_eq!(
+                apply(from, change.clone()).unwrap(),
+                to,
+                "{from:?} {change:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn payments_keep_what_the_processor_said() {
+        let authorized = PaymentChange::Authorized {
+            reference: "ref_2".to_string(),
+        };
+        let authorized = apply_payment_change(
+            payment(1, PaymentStatus::Pending, 1000),
+            authorized,
+            "now".to_string(),
+        )
+        .unwrap();
+        assert_eq!(authorized.reference.as_deref(), Some("ref_2"));
+        assert_eq!(authorized.updated_at, "now");
+
+        let timed_out = apply_payment_change(
+            payment(1, PaymentStatus::Capturing, 1000),
+            PaymentChange::Unconfirmed,
+            "now".to_string(),
+        )
+        .unwrap();
+        assert!(timed_out.failure.is_some());
+
+        let captured = apply_payment_change(
+            timed_out,
+            PaymentChange::Capturing { tip: 80 },
+            "later".to_string(),
+        )
+        .and_then(|payment| {
+            apply_payment_change(
+                payment,
+                PaymentChange::Captured { tip: 80 },
+                "later".to_string(),
+            )
+        })
+        .unwrap();
+        assert_eq!((captured.tip, captured.failure), (80, None));
+    }
+
+    #[test]
+    fn payments_refuse_changes_from_the_wrong_status() {
+        use PaymentStatus::*;
+
+        let refused = [
+            (Pending, PaymentChange::Voiding),
+            (Authorized, PaymentChange::Refunding),
+            (Captured, PaymentChange::Voiding),
+            (Capturing, PaymentChange::Voiding),
+            (Capturing, PaymentChange::Capturing { tip: 100 }),
+            (Voided, PaymentChange::Capturing { tip: 100 }),
+            (CaptureUnconfirmed, PaymentChange::Voiding),
+            (VoidUnconfirmed, PaymentChange::Capturing { tip: 100 }),
+            (
+                Declined,
+                PaymentChange::Authorized {
+                    reference: "ref_2".to_string(),
+                },
+            ),
+        ];
+        for (from, change) in refused {
+            assert!(
+                matches!(apply(from, change.clone()), Err(StorageError::Conflict(_))),
+                "{from:?} {change:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn captures_cant_take_more_than_was_authorized() {
+        use PaymentStatus::*;
+
+        assert_eq!(
+            apply(Authorized, PaymentChange::Capturing { tip: 100 }).unwrap(),
+            Capturing
+        );
+        assert_eq!(
+            apply(Authorized, PaymentChange::Capturing { tip: 0 }).unwrap(),
+            Capturing
+        );
+        for change in [
+            PaymentChange::Capturing { tip: 101 },
+            PaymentChange::Capturing { tip: -1 },
+        ] {
+            assert!(matches!(
+                apply(Authorized, change),
+                Err(StorageError::Invalid(_))
+            ));
+        }
+        assert!(matches!(
+            apply(Capturing, PaymentChange::Captured { tip: 101 }),
+            Err(StorageError::Invalid(message))
+                if message == "payment 1 was authorized for 1100, so it can't be captured for 1101"
+        ));
+    }
+
+    #[test]
+    fn payments_cant_be_for_more_than_is_owing() {
+        let card = |amount| NewPayment {
+            tender: Tender::Card,
+            amount,
+            tip: 500,
+        };
+        let payments = [
+            payment(1, PaymentStatus::Captured, 300),
+            payment(2, PaymentStatus::Authorized, 200),
+            payment(3, PaymentStatus::CaptureUnconfirmed, 100),
+            payment(4, PaymentStatus::Declined, 1000),
+            payment(5, PaymentStatus::Voided, 1000),
+        ];
+
+        assert!(check_payable(&card(400), &bill(1000), &payments).is_ok());
+        assert!(matches!(
+            check_payable(&card(401), &bill(1000), &payments),
+            Err(StorageError::Invalid(message)) if message == "only 4.00 USD is left to pay"
+        ));
+        assert!(matches!(
+            check_payable(&card(1), &bill(500), &payments),
+            Err(StorageError::Invalid(message)) if message == "only 0.00 USD is left to pay"
+        ));
+    }
+
+    #[test]
+    fn parties_settle_once_every_payment_is_captured_or_done_with() {
+        use PaymentStatus::*;
+
+        let settled = [
+            payment(1, Captured, 600),
+            payment(2, Captured, 400),
+            payment(3, Voided, 1000),
+            payment(4, Declined, 1000),
+            payment(5, Refunded, 1000),
+            payment(6, TimedOut, 1000),
+        ];
+        assert!(check_settled(&bill(1000), &settled).is_ok());
+
+        assert!(matches!(
+            check_settled(&bill(1001), &settled),
+            Err(StorageError::Conflict(message)) if message == "the table still owes 0.01 USD"
+        ));
+        for status in [
+            Pending,
+            Authorized,
+            Capturing,
+            Voiding,
+            Refunding,
+            CaptureUnconfirmed,
+            VoidUnconfirmed,
+            RefundUnconfirmed,
+        ] {
+            let payments = [settled.as_slice(), &[payment(7, status, 0)]].concat();
+            assert!(
+                matches!(
+                    check_settled(&bill(1000), &payments),
+                    Err(StorageError::Conflict(message)) if message.starts_with("payment 7 is")
+                ),
+                "{status:?}"
+            );
+        }
+    }
+}
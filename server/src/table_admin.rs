@@ -0,0 +1,103 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use shared::{NewTable, Table, TablePatch};
+
+use crate::storage::Storage;
+use crate::{ServiceResponse, ServiceState};
+
+/// Routes letting managers configure the restaurant's tables while the service runs
+pub fn routes<S: Storage>() -> Router<ServiceState<S>> {
+    Router::new()
+        .route("/tables", get(get_tables::<S>).post(create_table::<S>))
+        .route(
+            "/tables/:table_id/config",
+            get(get_table_config::<S>)
+                .patch(patch_table::<S>)
+                .put(put_table::<S>)
+                .delete(delete_table::<S>),
+        )
+}
+
+// Client: list every table, so staff pick from the registry instead of typing a number
+async fn get_tables<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path(restaurant_id): Path<i64>,
+) -> ServiceResponse<Json<Vec<Table>>> {
+    println!("get_tables {restaurant_id}");
+    let tables = state.storage.get_tables(restaurant_id).await?;
+
+    Ok((StatusCode::OK, Json(tables)))
+}
+
+// Manager: register a new table
+async fn create_table<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path(restaurant_id): Path<i64>,
+    Json(payload): Json<NewTable>,
+) -> ServiceResponse<Json<Table>> {
+    println!("create_table {restaurant_id}, {}", payload.label);
+    let table = state.storage.create_table(restaurant_id, payload).await?;
+
+    Ok((StatusCode::CREATED, Json(table)))
+}
+
+// Manager: query a table's label, section, seats and whether it is active
+async fn get_table_config<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+) -> ServiceResponse<Json<Table>> {
+    println!("get_table_config {restaurant_id}, {table_id}");
+    let table = state.storage.get_table(restaurant_id, table_id).await?;
+
+    Ok((StatusCode::OK, Json(table)))
+}
+
+// Manager: replace a table's label, section and seats
+async fn put_table<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+    Json(payload): Json<NewTable>,
+) -> ServiceResponse<Json<Table>> {
+    println!("put_table {restaurant_id}, {table_id}");
+    let patch = TablePatch {
+        label: Some(payload.label),
+        section: Some(payload.section),
+        seats: Some(payload.seats),
+        active: None,
+    };
+    let table = state
+        .storage
+        .update_table(restaurant_id, table_id, patch)
+        .await?;
+
+    Ok((StatusCode::OK, Json(table)))
+}
+
+// Manager: change some fields of a table, including deactivating or reactivating it
+async fn patch_table<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+    Json(payload): Json<TablePatch>,
+) -> ServiceResponse<Json<Table>> {
+    println!("patch_table {restaurant_id}, {table_id}");
+    let table = state
+        .storage
+        .update_table(restaurant_id, table_id, payload)
+        .await?;
+
+    Ok((StatusCode::OK, Json(table)))
+}
+
+// Manager: remove a table from the registry. Tables which ever had orders are refused
+// with a 409 and should be deactivated instead.
+async fn delete_table<S: Storage>(
+    State(state): State<ServiceState<S>>,
+    Path((restaurant_id, table_id)): Path<(i64, i64)>,
+) -> ServiceResponse<()> {
+    println!("delete_table {restaurant_id}, {table_id}");
+    state.storage.delete_table(restaurant_id, table_id).await?;
+
+    Ok((StatusCode::NO_CONTENT, ()))
+}
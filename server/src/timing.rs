@@ -0,0 +1,72 @@
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+use chrono_tz::Tz;
+use shared::{OrderStatus, Restaurant, TableOrder};
+
+/// How orders stored before `ready_at` became a real timestamp had it written, in UTC
+const LEGACY_READY_AT: &str = "%d/%m/%Y %H:%M";
+
+/// How `ready_at` is shown to staff, in the restaurant's time zone
+const DISPLAY_FORMAT: &str = "%d/%m/%Y %H:%M";
+
+/// Writes a `ready_at` instant. Whole seconds in UTC keep every stored value
+/// the same length, so they sort and compare correctly as text.
+pub fn format_ready_at(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Reads a `ready_at` timestamp, including ones in the legacy format
+pub fn parse_ready_at(ready_at: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(ready_at)
+        .map(|at| at.with_timezone(&Utc))
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(ready_at, LEGACY_READY_AT).map(|at| at.and_utc())
+        })
+        .ok()
+}
+
+/// Rewrites a legacy `ready_at` as RFC 3339, leaving anything else as it is
+pub fn normalize_ready_at(ready_at: &str) -> String {
+    match DateTime::parse_from_rfc3339(ready_at) {
+        Ok(_) => ready_at.to_string(),
+        Err(_) => parse_ready_at(ready_at).map_or_else(|| ready_at.to_string(), format_ready_at),
+    }
+}
+
+/// Parses an IANA time zone name, e.g. "Europe/London"
+pub fn parse_time_zone(name: &str) -> Result<Tz, String> {
+    name.parse()
+        .map_err(|_| format!("'{name}' is not a known time zone"))
+}
+
+/// The restaurant's time zone, or UTC if it isn't one we know
+pub fn restaurant_time_zone(restaurant: &Restaurant) -> Tz {
+    parse_time_zone(&restaurant.time_zone).unwrap_or(Tz::UTC)
+}
+
+/// Fills in the parts of each order which depend on the current time and
+/// the restaurant's time zone
+pub fn annotate<'a>(orders: impl IntoIterator<Item = &'a mut TableOrder>, restaurant: &Restaurant) {
+    let time_zone = restaurant_time_zone(restaurant);
+    let now = Utc::now();
+
+    orders
+        .into_iter()
+        .for_each(|order| annotate_order(order, time_zone, now));
+}
+
+fn annotate_order(order: &mut TableOrder, time_zone: Tz, now: DateTime<Utc>) {
+    let Some(ready_at) = parse_ready_at(&order.ready_at) else {
+        return;
+    };
+    let cooking = matches!(order.status, OrderStatus::Placed | OrderStatus::InProgress);
+
+    order.ready_at_local = ready_at
+        .with_timezone(&time_zone)
+        .format(DISPLAY_FORMAT)
+        .to_string();
+    order.remaining_secs = match cooking {
+        true => (ready_at - now).num_seconds().max(0),
+        false => 0,
+    };
+    order.overdue = cooking && now > ready_at;
+}
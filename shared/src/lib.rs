@@ -1,34 +1,1065 @@
+use std::fmt;
+use std::str::FromStr;
+
 use serde::{Deserialize, Serialize};
 
 pub const SERVICE_URL: &str = "127.0.0.1:3030";
 
-#[derive(Debug, Serialize, Deserialize)]
+/// One site hosted by the server. Menus, tables and orders all belong to a
+/// restaurant, under `/restaurants/:restaurant_id/...`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Restaurant {
+    pub id: i64,
+    pub name: String,
+    /// IANA time zone the restaurant shows times in, e.g. "Asia/Tokyo"
+    pub time_zone: String,
+    /// How many orders the kitchen can cook at once
+    pub kitchen_slots: i64,
+    /// ISO 4217 code of the currency menu prices are in, e.g. "JPY"
+    pub currency: String,
+    /// Tax added to bills, in basis points of the subtotal, e.g. 1000 for 10%
+    pub tax_rate_bps: i64,
+}
+
+/// Body of `POST /restaurants`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewRestaurant {
+    pub name: String,
+    /// Defaults to UTC when missing
+    #[serde(default)]
+    pub time_zone: Option<String>,
+    /// Defaults to 4 when missing
+    #[serde(default)]
+    pub kitchen_slots: Option<i64>,
+    /// Defaults to USD when missing. Can't be changed later, as every price
+    /// is stored in its minor units.
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Defaults to no tax when missing
+    #[serde(default)]
+    pub tax_rate_bps: Option<i64>,
+}
+
+/// Body of `PATCH /restaurants/:restaurant_id`. Missing fields are left unchanged.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RestaurantPatch {
+    pub name: Option<String>,
+    pub time_zone: Option<String>,
+    pub kitchen_slots: Option<i64>,
+    pub tax_rate_bps: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Menu {
+    /// Bumped on every menu change, so clients can tell when to refresh
+    pub version: i64,
     pub items: Vec<MenuItem>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MenuItem {
     pub id: i64,
     pub name: String,
     pub prep_min_m: f32,
     pub prep_max_m: f32,
+    /// Missing from exports made before items had a station
+    #[serde(default)]
+    pub station: Station,
+    /// Options guests can pick when ordering the item
+    #[serde(default)]
+    pub modifiers: Vec<Modifier>,
+    /// In minor units of the restaurant's currency (e.g. cents). Missing from
+    /// exports made before items had a price, which were free.
+    #[serde(default)]
+    pub price: i64,
+}
+
+/// Body of `POST /menu/items` and `PUT /menu/items/:item_id`, and one entry
+/// of a menu catalog file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewMenuItem {
+    pub name: String,
+    pub prep_min_m: f32,
+    pub prep_max_m: f32,
+    /// Defaults to the grill when missing
+    #[serde(default)]
+    pub station: Station,
+    /// Defaults to none when missing
+    #[serde(default)]
+    pub modifiers: Vec<Modifier>,
+    /// In minor units of the restaurant's currency. Defaults to free when
+    /// missing.
+    #[serde(default)]
+    pub price: i64,
+}
+
+/// Body of `PATCH /menu/items/:item_id`. Missing fields are left unchanged.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MenuItemPatch {
+    pub name: Option<String>,
+    pub prep_min_m: Option<f32>,
+    pub prep_max_m: Option<f32>,
+    pub station: Option<Station>,
+    /// Replaces the item's whole modifier set
+    pub modifiers: Option<Vec<Modifier>>,
+    pub price: Option<i64>,
+}
+
+/// An option offered for a menu item, e.g. "No pickles" or "Extra sauce".
+/// Orders keep a copy of the modifiers chosen, so later menu edits don't
+/// change what was ordered.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Modifier {
+    /// Unique within the item, ignoring case. Orders pick modifiers by name.
+    pub name: String,
+    /// Added to the item's price, in minor units of the restaurant's currency
+    /// (e.g. cents). Negative for a discount.
+    #[serde(default)]
+    pub price_delta: i64,
+    /// Minutes added to the item's prep time. Negative to take some off.
+    #[serde(default)]
+    pub prep_delta_m: f32,
+}
+
+/// The part of the kitchen a menu item is made at. Each order is routed to
+/// its item's station when it is placed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Station {
+    #[default]
+    Grill,
+    Fryer,
+    Drinks,
+    Dessert,
+}
+
+impl Station {
+    pub const ALL: [Station; 4] = [
+        Station::Grill,
+        Station::Fryer,
+        Station::Drinks,
+        Station::Dessert,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Station::Grill => "grill",
+            Station::Fryer => "fryer",
+            Station::Drinks => "drinks",
+            Station::Dessert => "dessert",
+        }
+    }
+}
+
+impl fmt::Display for Station {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Station {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Station::ALL
+            .into_iter()
+            .find(|station| station.as_str() == s)
+            .ok_or_else(|| format!("unknown station '{s}'"))
+    }
+}
+
+/// An order waiting at a kitchen station, as listed by
+/// `GET /stations/:station_id/queue`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StationLine {
+    pub table_id: i64,
+    pub table_label: String,
+    pub item_name: String,
+    #[serde(flatten)]
+    pub order: TableOrder,
+}
+
+/// Body of `POST /stations/:station_id/queue/:order_id/claim`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaimLineRequest {
+    /// Name of the staff member who is making the order
+    pub claimed_by: String,
+}
+
+/// A table in a restaurant's registry. Orders can only be placed for
+/// registered tables which are active.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Table {
+    pub id: i64,
+    /// Name shown to staff, e.g. "Booth A" or "Patio 2"
+    pub label: String,
+    /// Area of the restaurant the table is in, e.g. "Patio" or "Bar"
+    pub section: String,
+    pub seats: i64,
+    /// Inactive tables are kept so old orders can refer to them
+    pub active: bool,
+}
+
+/// Body of `POST /tables` and `PUT /tables/:table_id/config`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewTable {
+    pub label: String,
+    pub section: String,
+    pub seats: i64,
+}
+
+/// Body of `PATCH /tables/:table_id/config`. Missing fields are left unchanged.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TablePatch {
+    pub label: Option<String>,
+    pub section: Option<String>,
+    pub seats: Option<i64>,
+    pub active: Option<bool>,
+}
+
+/// A group of guests seated at a table. Orders for the table belong to its
+/// open party, and closing the party finishes them and frees the table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Party {
+    pub id: i64,
+    pub table_id: i64,
+    pub guests: i64,
+    pub seated_at: String,
+    /// Set once the party has left
+    pub closed_at: Option<String>,
+    /// How the party's bill is divided. Exports from before bills could be
+    /// split have none, which is a single check.
+    #[serde(default)]
+    pub check_split: CheckSplit,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Body of `POST /tables/:table_id/party`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeatPartyRequest {
+    pub guests: i64,
+}
+
+/// A party along with every order it placed, still live, finished or archived
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartyDetails {
+    pub party: Party,
+    pub orders: Vec<OrderHistoryEntry>,
+}
+
+/// Body of `POST /tables/:table_id`. Each entry of `items` orders one of
+/// that item with nothing changed, and is placed ahead of `lines`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct OrderItemsRequest {
+    #[serde(default)]
     pub items: Vec<i64>,
+    #[serde(default)]
+    pub lines: Vec<OrderLine>,
+}
+
+impl OrderItemsRequest {
+    /// Every line of the order, `items` first, in the order they are numbered
+    /// by `RejectedItem::line`
+    pub fn into_lines(self) -> Vec<OrderLine> {
+        self.items
+            .into_iter()
+            .map(OrderLine::plain)
+            .chain(self.lines)
+            .collect()
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// One line of an order: a number of the same item, made the same way
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderLine {
+    pub item_id: i64,
+    /// Defaults to 1 when missing
+    #[serde(default = "default_quantity")]
+    pub quantity: i64,
+    /// Names of modifiers from the item's modifier set
+    #[serde(default)]
+    pub modifiers: Vec<String>,
+    /// Special instructions for the kitchen, e.g. "allergic to sesame"
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// Which of the table's seats the line is for, numbered from 1
+    #[serde(default)]
+    pub seat: Option<i64>,
+}
+
+impl OrderLine {
+    /// A single item with no modifiers or notes
+    pub fn plain(item_id: i64) -> Self {
+        Self {
+            item_id,
+            quantity: default_quantity(),
+            modifiers: Vec::new(),
+            notes: None,
+            seat: None,
+        }
+    }
+}
+
+/// Quantity of order lines which don't give one, including every order
+/// placed before lines had a quantity
+pub fn default_quantity() -> i64 {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TableResponse {
     pub table_id: i64,
     pub ordered_items: Vec<TableOrder>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TableOrder {
     pub order_id: i64,
     pub item_id: i64,
+    pub quantity: i64,
+    /// The modifiers chosen, as they were on the menu when the order was placed
+    pub modifiers: Vec<Modifier>,
+    pub notes: Option<String>,
+    /// Price of one of the item with its modifiers, in minor units, frozen
+    /// when the order was placed
+    pub unit_price: i64,
+    /// The seat the order is for, if the server said
+    pub seat: Option<i64>,
+    /// When the kitchen expects the order to be ready, as an RFC 3339 UTC timestamp
+    pub ready_at: String,
+    /// `ready_at` in the restaurant's time zone, formatted for display
+    pub ready_at_local: String,
+    /// Seconds left until `ready_at`. Zero once it has passed, or once the
+    /// order is ready, served or cancelled.
+    pub remaining_secs: i64,
+    /// Whether `ready_at` has passed while the order is still being made
+    pub overdue: bool,
+    pub status: OrderStatus,
+    /// Every status the order has been in, oldest first, starting with
+    /// `placed` when it was ordered
+    pub status_history: Vec<StatusChange>,
+    pub station: Station,
+    /// Who took the order on at its station, if anyone has
+    pub claimed_by: Option<String>,
+}
+
+/// Where an order is in the kitchen. Orders move forward through
+/// placed → in_progress → ready → served, and can be cancelled until served.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus {
+    #[default]
+    Placed,
+    InProgress,
+    Ready,
+    Served,
+    Cancelled,
+}
+
+impl OrderStatus {
+    pub const ALL: [OrderStatus; 5] = [
+        OrderStatus::Placed,
+        OrderStatus::InProgress,
+        OrderStatus::Ready,
+        OrderStatus::Served,
+        OrderStatus::Cancelled,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OrderStatus::Placed => "placed",
+            OrderStatus::InProgress => "in_progress",
+            OrderStatus::Ready => "ready",
+            OrderStatus::Served => "served",
+            OrderStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// Whether an order in this status may move to `next`
+    pub fn can_become(self, next: OrderStatus) -> bool {
+        matches!(
+            (self, next),
+            (OrderStatus::Placed, OrderStatus::InProgress)
+                | (OrderStatus::InProgress, OrderStatus::Ready)
+                | (OrderStatus::Ready, OrderStatus::Served)
+                | (
+                    OrderStatus::Placed | OrderStatus::InProgress | OrderStatus::Ready,
+                    OrderStatus::Cancelled
+                )
+        )
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OrderStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OrderStatus::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| format!("unknown order status '{s}'"))
+    }
+}
+
+/// When an order entered a status
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub status: OrderStatus,
+    pub at: String,
+}
+
+/// Body of `PATCH /tables/:table_id/:order_id`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateOrderStatusRequest {
+    pub status: OrderStatus,
+}
+
+/// Query parameters of `GET /tables/:table_id`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TableItemsQuery {
+    /// Comma separated statuses, e.g. `placed,in_progress`. Orders in any
+    /// status are listed when missing.
+    pub status: Option<String>,
+}
+
+/// A change pushed to everyone following `GET /events`, sent as a
+/// server-sent event named after its `type`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveEvent {
+    /// An order was placed for a table
+    OrderCreated { table_id: i64, order: TableOrder },
+    /// An order no longer shows for its table: it was removed, its party was
+    /// checked out, or it was archived. `station` is missing once the order
+    /// has left the live orders altogether.
+    OrderRemoved {
+        table_id: i64,
+        order_id: i64,
+        station: Option<Station>,
+    },
+    /// The kitchen moved an order on to a new status
+    OrderStatusChanged { table_id: i64, order: TableOrder },
+    /// A removed order was restored, or the kitchen quoted it a new ready time
+    OrderUpdated { table_id: i64, order: TableOrder },
+    /// The menu was edited, and is now at `version`
+    MenuChanged { version: i64 },
+    /// The follower fell behind and `missed` events were dropped, so
+    /// anything it built from the stream should be fetched again
+    Lagged { missed: u64 },
+}
+
+impl LiveEvent {
+    /// The event's `type`, which is also the server-sent event's name
+    pub fn name(&self) -> &'static str {
+        match self {
+            LiveEvent::OrderCreated { .. } => "order_created",
+            LiveEvent::OrderRemoved { .. } => "order_removed",
+            LiveEvent::OrderStatusChanged { .. } => "order_status_changed",
+            LiveEvent::OrderUpdated { .. } => "order_updated",
+            LiveEvent::MenuChanged { .. } => "menu_changed",
+            LiveEvent::Lagged { .. } => "lagged",
+        }
+    }
+
+    /// The table the event is about, if it is about an order
+    pub fn table_id(&self) -> Option<i64> {
+        match self {
+            LiveEvent::OrderCreated { table_id, .. }
+            | LiveEvent::OrderRemoved { table_id, .. }
+            | LiveEvent::OrderStatusChanged { table_id, .. }
+            | LiveEvent::OrderUpdated { table_id, .. } => Some(*table_id),
+            LiveEvent::MenuChanged { .. } | LiveEvent::Lagged { .. } => None,
+        }
+    }
+
+    /// The station the event's order is made at, when known
+    pub fn station(&self) -> Option<Station> {
+        match self {
+            LiveEvent::OrderCreated { order, .. }
+            | LiveEvent::OrderStatusChanged { order, .. }
+            | LiveEvent::OrderUpdated { order, .. } => Some(order.station),
+            LiveEvent::OrderRemoved { station, .. } => *station,
+            LiveEvent::MenuChanged { .. } | LiveEvent::Lagged { .. } => None,
+        }
+    }
+}
+
+/// Query parameters of `GET /events`. Missing fields match every event, and
+/// events which aren't about a table or station are always sent.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LiveFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub table_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub station: Option<Station>,
+}
+
+impl LiveFilter {
+    pub fn matches(&self, event: &LiveEvent) -> bool {
+        let table_matches = match (self.table_id, event.table_id()) {
+            (Some(wanted), Some(table_id)) => wanted == table_id,
+            _ => true,
+        };
+        let station_matches = match (self.station, event.station()) {
+            (Some(wanted), Some(station)) => wanted == station,
+            _ => true,
+        };
+        table_matches && station_matches
+    }
+}
+
+/// What a table owes for its live orders, as returned by
+/// `GET /tables/:table_id/bill`. Every amount is in minor units of `currency`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bill {
+    pub table_id: i64,
+    pub currency: String,
+    /// Cancelled orders are left off
+    pub lines: Vec<BillLine>,
+    pub subtotal: i64,
+    /// Taken off by check-level discounts, after the lines' own adjustments
+    pub check_discount: i64,
+    /// In basis points of the subtotal
+    pub tax_rate_bps: i64,
+    /// Worked out on the subtotal less `check_discount`, rounded to the
+    /// nearest minor unit, halves up
+    pub tax: i64,
+    pub total: i64,
+    /// Every discount, comp and void behind the amounts, oldest first
+    pub adjustments: Vec<Adjustment>,
+}
+
+/// One order on a bill
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BillLine {
+    pub order_id: i64,
+    pub item_id: i64,
+    pub item_name: String,
+    pub quantity: i64,
+    pub modifiers: Vec<Modifier>,
+    /// The item's price plus its modifiers', as they were when it was ordered
+    pub unit_price: i64,
+    pub line_total: i64,
+    pub seat: Option<i64>,
+    /// The discount, comp or void made to the line, if any
+    pub adjustment: Option<AdjustmentKind>,
+    /// Taken off `line_total` by the adjustment. Comps and voids take it all.
+    pub discount: i64,
+    /// What is left to pay for the line
+    pub net_total: i64,
+}
+
+/// A manager who can approve discounts, comps and voids. Their PIN is never
+/// sent back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manager {
+    pub id: i64,
+    pub name: String,
+    /// Managers who have left can no longer approve anything
+    pub active: bool,
+}
+
+/// Body of `POST /managers`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewManager {
+    pub name: String,
+    /// 4 to 12 digits
+    pub pin: String,
+}
+
+/// What an adjustment does to a party's bill
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdjustmentKind {
+    /// Takes part of an order's price, or of the whole bill, off
+    Discount,
+    /// Gives an order away, so it is on the bill at no charge. Any order on
+    /// the bill can be comped, whether or not it has been made.
+    Comp,
+    /// Takes an order which has been made, i.e. is ready or served, off what
+    /// is owed, e.g. when it was sent back. Orders not made yet are removed
+    /// from the table instead.
+    Void,
+}
+
+/// How much a discount takes off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Discount {
+    /// A share of the amount, in basis points, rounded to the nearest minor
+    /// unit with halves up
+    Percent { bps: i64 },
+    /// A fixed amount in minor units, never more than the amount itself
+    Fixed { amount: i64 },
+}
+
+/// Body of `POST /tables/:table_id/adjustments`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewAdjustment {
+    pub kind: AdjustmentKind,
+    /// The order adjusted. Discounts without one are taken off the whole
+    /// bill, while comps and voids always need one.
+    #[serde(default)]
+    pub order_id: Option<i64>,
+    /// How much a discount takes off. Only discounts have one.
+    #[serde(default)]
+    pub discount: Option<Discount>,
+    pub reason: String,
+    /// The server who asked for the adjustment
+    pub requested_by: String,
+    /// Name of the manager approving it
+    pub approved_by: String,
+    /// The approving manager's PIN, which comps and voids need
+    #[serde(default)]
+    pub pin: Option<String>,
+}
+
+/// A discount, comp or void made to a party's bill
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Adjustment {
+    pub id: i64,
+    pub party_id: i64,
+    pub table_id: i64,
+    /// Missing for check-level discounts
+    pub order_id: Option<i64>,
+    pub kind: AdjustmentKind,
+    pub discount: Option<Discount>,
+    pub reason: String,
+    pub requested_by: String,
+    pub approved_by: String,
+    pub created_at: String,
+}
+
+/// How a party's bill is divided into checks which are paid separately.
+/// Checks are numbered from 1.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CheckSplit {
+    /// Everything on one check
+    #[default]
+    Single,
+    /// The whole bill shared as evenly as the currency allows
+    Even { checks: i64 },
+    /// Each order on one check. Orders which were never moved, including
+    /// ones placed after the bill was split, are on check 1.
+    ByOrder {
+        checks: i64,
+        /// Sorted by order id
+        assignments: Vec<CheckAssignment>,
+    },
+}
+
+/// An order moved onto a check of a bill split by order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckAssignment {
+    pub order_id: i64,
+    pub check: i64,
+}
+
+/// Body of `POST /tables/:table_id/checks/even`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SplitEvenlyRequest {
+    pub checks: i64,
+}
+
+/// Body of `POST /tables/:table_id/checks/move`. Check `checks + 1` can be
+/// named to open a new check.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoveToCheckRequest {
+    pub order_ids: Vec<i64>,
+    pub check: i64,
+}
+
+/// A table's bill divided into checks, as returned by the
+/// `/tables/:table_id/checks` endpoints. The checks' amounts always add up
+/// to the bill's exactly.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableChecks {
+    pub table_id: i64,
+    pub currency: String,
+    pub split: CheckSplit,
+    pub checks: Vec<Check>,
+    pub subtotal: i64,
+    pub check_discount: i64,
+    pub tax: i64,
+    pub total: i64,
+}
+
+/// One check of a split bill
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Check {
+    pub number: i64,
+    /// The orders on the check. Even splits share every order, so list none.
+    pub lines: Vec<BillLine>,
+    pub subtotal: i64,
+    /// The check's share of the bill's check-level discounts
+    pub check_discount: i64,
+    pub tax: i64,
+    pub total: i64,
+}
+
+/// How a payment is made
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tender {
+    /// Taken at the table, so it is captured as soon as it is recorded
+    Cash,
+    /// Authorized and captured through the payment processor
+    Card,
+}
+
+/// Where a payment has got to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentStatus {
+    /// Sent to the processor, which hasn't answered yet
+    Pending,
+    /// The processor has held the amount, which is owed until captured
+    Authorized,
+    /// Paid, counting towards the bill
+    Captured,
+    /// Authorized and then released without being captured
+    Voided,
+    /// Captured and then given back
+    Refunded,
+    /// Turned down by the processor
+    Declined,
+    /// The processor didn't answer in time
+    TimedOut,
+    /// The processor is being asked to capture the authorized amount
+    Capturing,
+    /// The processor is being asked to release the hold
+    Voiding,
+    /// The processor is being asked to give a captured payment back
+    Refunding,
+    /// The processor didn't answer a capture in time, so whether the payment
+    /// was taken has to be found out by capturing it again
+    CaptureUnconfirmed,
+    /// The processor didn't answer a void in time, so whether the hold was
+    /// let go has to be found out by voiding it again
+    VoidUnconfirmed,
+    /// The processor didn't answer a refund in time, so whether the payment
+    /// was given back has to be found out by refunding it again
+    RefundUnconfirmed,
+}
+
+/// Body of `POST /tables/:table_id/payments`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewPayment {
+    pub tender: Tender,
+    /// Paid towards the bill, in minor units
+    pub amount: i64,
+    /// Paid on top of the bill, in minor units
+    #[serde(default)]
+    pub tip: i64,
+}
+
+/// Body of `POST /payments/:payment_id/capture`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CapturePaymentRequest {
+    /// Replaces the tip given when the payment was authorized, as tips are
+    /// often only settled once the card slip is signed. It can't be more
+    /// than the tip authorized.
+    #[serde(default)]
+    pub tip: Option<i64>,
+}
+
+/// One payment towards a party's bill
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Payment {
+    pub id: i64,
+    pub party_id: i64,
+    pub table_id: i64,
+    pub tender: Tender,
+    pub amount: i64,
+    pub tip: i64,
+    pub status: PaymentStatus,
+    /// The processor's reference for card payments it has authorized
+    pub reference: Option<String>,
+    /// Why the processor declined or failed the payment
+    pub failure: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// The payments made towards a table's bill, and what is left to pay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TablePayments {
+    pub table_id: i64,
+    pub currency: String,
+    /// What the bill comes to
+    pub total: i64,
+    /// Captured towards the bill, tips aside
+    pub paid: i64,
+    /// Tips on captured payments
+    pub tips: i64,
+    /// What is still owed, or below zero once more has been paid than is
+    /// owed. The party can only be closed once it reaches zero.
+    pub balance: i64,
+    /// Every payment tried for the party, oldest first
+    pub payments: Vec<Payment>,
+}
+
+/// How many digits of a currency's amounts are minor units, e.g. 2 for the
+/// cents of USD and 0 for JPY, which has none
+pub fn minor_unit_digits(currency: &str) -> u32 {
+    match currency {
+        "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF" | "UGX"
+        | "VND" | "VUV" | "XAF" | "XOF" | "XPF" => 0,
+        "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+        _ => 2,
+    }
+}
+
+/// Formats an amount in minor units for display, e.g. "12.50 USD"
+pub fn format_money(amount: i64, currency: &str) -> String {
+    let digits = minor_unit_digits(currency);
+    let sign = if amount < 0 { "-" } else { "" };
+    let amount = amount.unsigned_abs();
+    match digits {
+        0 => format!("{sign}{amount} {currency}"),
+        _ => {
+            let scale = 10u64.pow(digits);
+            format!(
+                "{sign}{}.{:0width$} {currency}",
+                amount / scale,
+                amount % scale,
+                width = digits as usize
+            )
+        }
+    }
+}
+
+/// Query parameters of `DELETE /tables/:table_id/:order_id`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoveOrderRequest {
+    pub reason: RemovalReason,
+    /// Name of the staff member removing the order
+    pub removed_by: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemovalReason {
+    CustomerChangedMind,
+    KitchenError,
+    Comp,
+}
+
+/// Every order ever placed for a table, including removed ones
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableHistory {
+    pub table_id: i64,
+    pub orders: Vec<OrderHistoryEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderHistoryEntry {
+    pub order_id: i64,
+    pub item_id: i64,
+    pub ordered_at: String,
+    pub ready_at: String,
+    /// Set while the order is removed, cleared again if it is restored
+    pub removal: Option<Removal>,
+    /// The party the order was placed for. Orders from before parties have none.
+    #[serde(default)]
+    pub party_id: Option<i64>,
+    /// When the order's party was closed, finishing it
+    #[serde(default)]
+    pub finished_at: Option<String>,
+    #[serde(default)]
+    pub status: OrderStatus,
+    /// Missing from exports made before orders had a status
+    #[serde(default)]
+    pub status_history: Vec<StatusChange>,
+    /// How long the kitchen was told the order takes to cook. Missing from
+    /// exports made before the kitchen was scheduled.
+    #[serde(default)]
+    pub prep_secs: Option<i64>,
+    #[serde(default)]
+    pub station: Station,
+    #[serde(default)]
+    pub claimed_by: Option<String>,
+    /// Missing from exports made before orders had lines
+    #[serde(default = "default_quantity")]
+    pub quantity: i64,
+    #[serde(default)]
+    pub modifiers: Vec<Modifier>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// Missing from exports made before items had a price
+    #[serde(default)]
+    pub unit_price: i64,
+    #[serde(default)]
+    pub seat: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Removal {
+    pub removed_at: String,
+    pub removed_by: String,
+    pub reason: RemovalReason,
+}
+
+/// Returned with a 422 when an order can't be placed. Nothing from the
+/// request is stored unless every line is accepted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderRejection {
+    pub message: String,
+    pub rejected_items: Vec<RejectedItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejectedItem {
+    /// Position of the line in `OrderItemsRequest::into_lines`
+    pub line: usize,
+    pub item_id: i64,
+    pub reason: RejectionReason,
+    /// The modifier the reason is about, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modifier: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectionReason {
+    /// No menu item exists with this id, or it has been retired
+    UnknownItem,
+    /// The quantity is below 1 or above the most a line can hold
+    InvalidQuantity,
+    /// The item has no modifier by this name
+    UnknownModifier,
+    /// The same modifier was picked more than once
+    DuplicateModifier,
+    /// The notes are longer than the kitchen can be sent
+    NotesTooLong,
+    /// The seat is below 1 or beyond the table's seat count
+    InvalidSeat,
+}
+
+/// Result of replaying the order event log to rebuild the orders projection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RebuildReport {
+    pub events_replayed: usize,
+    /// Number of live orders according to the log
+    pub orders: usize,
+    /// Every way the stored orders differed from the log before rebuilding
+    pub mismatches: Vec<String>,
+    /// False for a dry run, which only checks the projection
+    pub rebuilt: bool,
+}
+
+/// Portable copy of the menu and every order, for moving data between
+/// servers and seeding test environments. Importing it replaces all data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataExport {
+    /// The menu as clients see it, along with its version
+    pub menu: Menu,
+    /// Menu items which have been retired, kept so old orders can refer to them
+    pub retired_items: Vec<MenuItem>,
+    /// Every order ever placed, including removed ones, grouped by table
+    pub tables: Vec<TableHistory>,
+    /// Every registered table, active or not. Missing from exports made
+    /// before tables were registered.
+    #[serde(default)]
+    pub table_registry: Vec<Table>,
+    /// Every party, open or closed
+    #[serde(default)]
+    pub parties: Vec<Party>,
+    /// Every discount, comp and void. Managers aren't exported, as their
+    /// PINs stay with the server they were set on.
+    #[serde(default)]
+    pub adjustments: Vec<Adjustment>,
+    /// Every payment tried, whatever became of it
+    #[serde(default)]
+    pub payments: Vec<Payment>,
+}
+
+/// Result of importing a `DataExport`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub menu_items: usize,
+    pub tables: usize,
+    pub parties: usize,
+    pub orders: usize,
+    pub adjustments: usize,
+    pub payments: usize,
+}
+
+/// Result of `POST /admin/backup`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupReport {
+    /// Where on the server the backup was written
+    pub path: String,
+    pub bytes: u64,
+}
+
+/// What an end-of-day close-out moved into the archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CloseOutSummary {
+    /// Business day the archived orders belong to, as `YYYY-MM-DD`
+    pub business_date: String,
+    pub closed_at: String,
+    /// Number of tables which had orders
+    pub tables: usize,
+    /// Number of orders archived, removed ones included
+    pub orders: usize,
+    pub removed_orders: usize,
+    /// How many of each item were served, leaving out removed and cancelled
+    /// orders. Lines count once for each of their quantity.
+    pub items_served: Vec<ItemCount>,
+    /// Parties still seated which hadn't settled up, so were left open with
+    /// their orders
+    #[serde(default)]
+    pub unsettled_parties: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemCount {
+    pub item_id: i64,
+    pub name: String,
+    pub count: usize,
+}
+
+/// An order moved out of the live tables by a close-out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedOrder {
+    pub business_date: String,
+    pub order_id: i64,
+    pub table_id: i64,
+    pub item_id: i64,
+    /// The item's name when it was archived, as the menu may change later
+    pub item_name: String,
+    pub ordered_at: String,
     pub ready_at: String,
+    pub removal: Option<Removal>,
+    #[serde(default)]
+    pub party_id: Option<i64>,
+    #[serde(default)]
+    pub finished_at: Option<String>,
+    #[serde(default)]
+    pub status: OrderStatus,
+    #[serde(default)]
+    pub status_history: Vec<StatusChange>,
+    #[serde(default = "default_quantity")]
+    pub quantity: i64,
+    #[serde(default)]
+    pub modifiers: Vec<Modifier>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub unit_price: i64,
+    #[serde(default)]
+    pub seat: Option<i64>,
+    #[serde(default)]
+    pub station: Station,
+    /// How long the kitchen was told the order takes to cook. Not known for
+    /// orders archived before it was kept.
+    #[serde(default)]
+    pub prep_secs: Option<i64>,
 }